@@ -42,7 +42,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let result = client.send_intent("make uppercase hello world", 0.85).await?;
     handle_result(&result);
 
-    // Example 4: Unclear intent (should trigger CLARIFY)
+    // Example 4: Unclear intent (CLARIFY, or REFUSE if nothing matches)
     client.reset();
     println!("\n Sending: 'do something' (vague intent)");
     let result = client.send_intent("do something", 0.50).await?;
@@ -78,18 +78,37 @@ fn handle_result(result: &NextAction) {
                 }
             }
         }
+        NextAction::Accepted { job, .. } => {
+            println!("   Running in the background as job {}", job.id);
+        }
+        NextAction::Scheduled { scheduled, .. } => {
+            println!("   Scheduled as {}, next run at {}", scheduled.id, scheduled.next_run);
+        }
         NextAction::Clarify { questions, response } => {
             println!(" Server needs clarification (confidence: {:.2}):", response.confidence);
             for q in questions {
                 println!("   - {}", q);
             }
         }
+        NextAction::Confirm { confirmation, response } => {
+            println!(" Server asks for confirmation (confidence: {:.2}):", response.confidence);
+            println!(
+                "   {} (cost: {:.2})",
+                confirmation.interpretation, confirmation.estimated_cost
+            );
+        }
         NextAction::Propose { alternatives, response } => {
             println!(" Server proposes alternatives (confidence: {:.2}):", response.confidence);
             for alt in alternatives {
                 println!("   - {} (conf: {:.2})", alt.interpretation, alt.confidence);
             }
         }
+        NextAction::ProposePlan { plan, response } => {
+            println!(" Server proposes a plan (confidence: {:.2}):", response.confidence);
+            for (i, step) in plan.steps.iter().enumerate() {
+                println!("   {}. {} ({})", i + 1, step.intent, step.capability_id);
+            }
+        }
         NextAction::Refused { reason, response } => {
             println!(" Request refused (confidence: {:.2}):", response.confidence);
             println!("   Reason: {}", reason);
@@ -1,13 +1,26 @@
 //! TCP/TLS connection for SINP client.
 
+use base64::Engine;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 use tokio_rustls::TlsConnector;
-use rustls::pki_types::ServerName;
 
-use sinp_core::{Request, Response, SinpError, SinpResult};
+use sinp_core::{
+    Cancel, CancelAck, Catalog, ClientFrame, DiscoveryRequest, Event, EventKind, Feature, Hello,
+    Job, JobQuery, Progress, Request, Response, ScheduleQuery, ScheduledExecution, ServerFrame,
+    Session, SinpError, SinpResult, Subscribe,
+};
 
 /// Client connection configuration.
 #[derive(Debug, Clone)]
@@ -20,6 +33,21 @@ pub struct ConnectionConfig {
     pub use_tls: bool,
     /// Max message size.
     pub max_message_size: usize,
+    /// PEM files with CA certificates to trust.
+    pub ca_files: Vec<PathBuf>,
+    /// Trust the operating system's root certificates.
+    pub use_system_roots: bool,
+    /// Accepted SHA-256 hashes (base64) of the server's SubjectPublicKeyInfo.
+    ///
+    /// If set, the server certificate must match one of them. Without any
+    /// trusted roots the pin alone authenticates the server.
+    pub pinned_spki_sha256: Vec<String>,
+    /// Client certificate chain (PEM) presented for mutual TLS.
+    pub client_cert_path: Option<PathBuf>,
+    /// Private key (PEM) for the client certificate.
+    pub client_key_path: Option<PathBuf>,
+    /// HELLO sent on connect; `None` speaks the legacy 0.1 framing.
+    pub hello: Option<Hello>,
 }
 
 impl Default for ConnectionConfig {
@@ -29,6 +57,12 @@ impl Default for ConnectionConfig {
             server_name: None,
             use_tls: false,
             max_message_size: 1024 * 1024,
+            ca_files: Vec::new(),
+            use_system_roots: false,
+            pinned_spki_sha256: Vec::new(),
+            client_cert_path: None,
+            client_key_path: None,
+            hello: Some(Hello::default().with_features([Feature::Streaming])),
         }
     }
 }
@@ -43,111 +77,754 @@ impl ConnectionConfig {
         }
     }
 
-    /// Create config for TLS connection.
+    /// Create config for TLS connection trusting the system roots.
     pub fn tls(addr: SocketAddr, server_name: impl Into<String>) -> Self {
         Self {
             server_addr: addr,
             server_name: Some(server_name.into()),
             use_tls: true,
+            use_system_roots: true,
             ..Default::default()
         }
     }
+
+    /// Trust the CA certificates in a PEM file.
+    pub fn with_ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_files.push(path.into());
+        self
+    }
+
+    /// Enable or disable the system root certificates.
+    pub fn with_system_roots(mut self, enabled: bool) -> Self {
+        self.use_system_roots = enabled;
+        self
+    }
+
+    /// Pin the server's SubjectPublicKeyInfo SHA-256 hash (base64).
+    pub fn with_pinned_spki(mut self, sha256_base64: impl Into<String>) -> Self {
+        self.pinned_spki_sha256.push(sha256_base64.into());
+        self
+    }
+
+    /// Present a client certificate for mutual TLS.
+    pub fn with_client_cert(
+        mut self,
+        cert_path: impl Into<PathBuf>,
+        key_path: impl Into<PathBuf>,
+    ) -> Self {
+        self.client_cert_path = Some(cert_path.into());
+        self.client_key_path = Some(key_path.into());
+        self
+    }
+
+    /// Set the HELLO announced on connect.
+    pub fn with_hello(mut self, hello: Hello) -> Self {
+        self.hello = Some(hello);
+        self
+    }
+
+    /// Skip the handshake and speak the legacy 0.1 framing, for servers that
+    /// predate HELLO.
+    pub fn with_legacy_framing(mut self) -> Self {
+        self.hello = None;
+        self
+    }
+}
+
+/// Compute the base64 SHA-256 hash of a certificate's SubjectPublicKeyInfo.
+///
+/// This is the value expected by [`ConnectionConfig::with_pinned_spki`].
+pub fn spki_sha256(cert: &CertificateDer<'_>) -> SinpResult<String> {
+    let hash = spki_hash(cert)
+        .map_err(|e| SinpError::CertificateVerification(format!("Invalid certificate: {:?}", e)))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(hash))
+}
+
+fn spki_hash(cert: &CertificateDer<'_>) -> Result<[u8; 32], webpki::Error> {
+    let cert = webpki::EndEntityCert::try_from(cert)?;
+    Ok(Sha256::digest(cert.subject_public_key_info().as_ref()).into())
+}
+
+/// Frame received for a request sent with
+/// [`Connection::send_request_streaming`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// Update while the capability runs.
+    Progress(Progress),
+    /// Final response; nothing follows it.
+    Response(Box<Response>),
 }
 
+/// Callback for events pushed by the server.
+type EventHandler = Box<dyn FnMut(&Event) + Send>;
+
+/// Registered callbacks with the kind of event each takes.
+type EventHandlers = Arc<Mutex<Vec<(EventKind, EventHandler)>>>;
+
 /// Connection to SINP server.
-pub enum Connection {
-    Tcp(TcpStream),
-    Tls(tokio_rustls::client::TlsStream<TcpStream>),
+///
+/// Frames are read by a background task, which passes pushed events to the
+/// registered callbacks as they arrive and everything else on to the
+/// connection's calls.
+pub struct Connection {
+    writer: Box<dyn AsyncWrite + Send + Sync + Unpin>,
+    frames: mpsc::Receiver<SinpResult<Vec<u8>>>,
+    reader: tokio::task::JoinHandle<()>,
+    handlers: EventHandlers,
+    session: Session,
 }
 
 impl Connection {
-    /// Connect to server.
+    /// Connect to server and perform the HELLO handshake.
     pub async fn connect(config: &ConnectionConfig) -> SinpResult<Self> {
         let stream = TcpStream::connect(&config.server_addr)
             .await
             .map_err(|e| SinpError::Transport(format!("Connection failed: {}", e)))?;
 
-        if config.use_tls {
-            let connector = Self::create_tls_connector()?;
+        let mut connection = if config.use_tls {
+            let connector = Self::create_tls_connector(config)?;
             let server_name_str = config
                 .server_name
                 .clone()
                 .unwrap_or_else(|| "localhost".to_string());
             let server_name: ServerName<'static> = server_name_str
+                .clone()
                 .try_into()
                 .map_err(|_| SinpError::Transport("Invalid server name".to_string()))?;
 
             let tls_stream = connector
                 .connect(server_name, stream)
                 .await
-                .map_err(|e| SinpError::Transport(format!("TLS handshake failed: {}", e)))?;
+                .map_err(|e| handshake_error(e, &server_name_str))?;
 
-            Ok(Self::Tls(tls_stream))
+            Self::start(tls_stream, config.max_message_size)
         } else {
-            Ok(Self::Tcp(stream))
+            Self::start(stream, config.max_message_size)
+        };
+        if let Some(ref hello) = config.hello {
+            connection.session = connection.handshake(hello).await?;
         }
+        Ok(connection)
     }
 
-    /// Create TLS connector with system roots.
-    fn create_tls_connector() -> SinpResult<TlsConnector> {
-        let root_store = rustls::RootCertStore::empty();
-        // In production, load system certs or custom CA
-        
-        let config = rustls::ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+    /// Start reading frames from `stream` in the background.
+    fn start<S>(stream: S, max_message_size: usize) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + Sync + Unpin + 'static,
+    {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let handlers = EventHandlers::default();
+        let (sender, frames) = mpsc::channel(1);
+        let reader = tokio::spawn({
+            let handlers = Arc::clone(&handlers);
+            async move {
+                loop {
+                    let frame = read_frame(&mut reader, max_message_size).await;
+                    if let Ok(ref frame) = frame {
+                        if let Ok(ServerFrame::Event(event)) = serde_json::from_slice(frame) {
+                            dispatch(&handlers, &event);
+                            continue;
+                        }
+                    }
+                    let failed = frame.is_err();
+                    if sender.send(frame).await.is_err() || failed {
+                        break;
+                    }
+                }
+            }
+        });
+        Self {
+            writer: Box::new(writer),
+            frames,
+            reader,
+            handlers,
+            session: Session::legacy(),
+        }
+    }
 
-        Ok(TlsConnector::from(Arc::new(config)))
+    /// Parameters agreed with the server.
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    /// Exchange HELLO frames and check the server's choice.
+    async fn handshake(&mut self, hello: &Hello) -> SinpResult<Session> {
+        self.write_frame(&ClientFrame::Hello(hello.clone())).await?;
+        // Servers that predate HELLO drop the connection on it
+        let frame = self.read_frame().await.map_err(|e| {
+            SinpError::Protocol(format!(
+                "No HELLO reply ({}); the server may only support legacy framing",
+                e
+            ))
+        })?;
+        match serde_json::from_slice(&frame) {
+            Ok(ServerFrame::HelloAck(session)) => {
+                if !hello.versions.contains(&session.version) {
+                    return Err(SinpError::Protocol(format!(
+                        "Server chose unsupported protocol version {}",
+                        session.version
+                    )));
+                }
+                if let Some(missing) = hello
+                    .required_features
+                    .iter()
+                    .find(|f| !session.supports(**f))
+                {
+                    return Err(SinpError::Protocol(format!(
+                        "Server did not agree to required feature {:?}",
+                        missing
+                    )));
+                }
+                tracing::debug!("Negotiated session: {:?}", session);
+                Ok(session)
+            }
+            Ok(ServerFrame::HelloReject(reject)) => Err(SinpError::Protocol(format!(
+                "Handshake rejected: {} (server versions: {})",
+                reject.reason,
+                reject.supported.versions.join(", ")
+            ))),
+            _ => Err(SinpError::Protocol("Unexpected reply to HELLO".to_string())),
+        }
+    }
+
+    /// Create TLS connector from the configured trust store.
+    fn create_tls_connector(config: &ConnectionConfig) -> SinpResult<TlsConnector> {
+        let root_store = Self::load_root_store(config)?;
+        let pins = config
+            .pinned_spki_sha256
+            .iter()
+            .map(|pin| decode_pin(pin))
+            .collect::<SinpResult<Vec<_>>>()?;
+
+        if root_store.is_empty() && pins.is_empty() {
+            return Err(SinpError::Transport(
+                "No trusted certificates: configure CA files, system roots or SPKI pins"
+                    .to_string(),
+            ));
+        }
+
+        let builder = rustls::ClientConfig::builder();
+        let builder = if pins.is_empty() {
+            builder.with_root_certificates(root_store)
+        } else {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let verifier = PinnedCertVerifier::new(root_store, pins, provider)?;
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(verifier))
+        };
+
+        let tls_config = match (&config.client_cert_path, &config.client_key_path) {
+            (Some(cert_path), Some(key_path)) => {
+                let (certs, key) = Self::load_client_cert(cert_path, key_path)?;
+                builder.with_client_auth_cert(certs, key).map_err(|e| {
+                    SinpError::Transport(format!("Invalid client certificate: {}", e))
+                })?
+            }
+            _ => builder.with_no_client_auth(),
+        };
+
+        Ok(TlsConnector::from(Arc::new(tls_config)))
+    }
+
+    /// Load the client certificate chain and private key.
+    fn load_client_cert(
+        cert_path: &std::path::Path,
+        key_path: &std::path::Path,
+    ) -> SinpResult<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        let cert_file = std::fs::File::open(cert_path)
+            .map_err(|e| SinpError::Transport(format!("Failed to open client cert: {}", e)))?;
+        let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(cert_file))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| SinpError::Transport(format!("Failed to read client cert: {}", e)))?;
+
+        let key_file = std::fs::File::open(key_path)
+            .map_err(|e| SinpError::Transport(format!("Failed to open client key: {}", e)))?;
+        let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(key_file))
+            .map_err(|e| SinpError::Transport(format!("Failed to read client key: {}", e)))?
+            .ok_or_else(|| SinpError::Transport("No client private key found".to_string()))?;
+
+        Ok((certs, key))
+    }
+
+    /// Build the root store from CA files and, optionally, the system roots.
+    fn load_root_store(config: &ConnectionConfig) -> SinpResult<RootCertStore> {
+        let mut root_store = RootCertStore::empty();
+
+        if config.use_system_roots {
+            let native = rustls_native_certs::load_native_certs();
+            for e in &native.errors {
+                tracing::warn!("Failed to load system root certificate: {}", e);
+            }
+            let (added, ignored) = root_store.add_parsable_certificates(native.certs);
+            tracing::debug!(
+                "Loaded {} system root certificates ({} ignored)",
+                added,
+                ignored
+            );
+        }
+
+        for path in &config.ca_files {
+            let file = std::fs::File::open(path).map_err(|e| {
+                SinpError::Transport(format!("Failed to open CA file {}: {}", path.display(), e))
+            })?;
+            let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| {
+                    SinpError::Transport(format!(
+                        "Failed to read CA file {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+            if certs.is_empty() {
+                return Err(SinpError::Transport(format!(
+                    "No certificates found in CA file {}",
+                    path.display()
+                )));
+            }
+            for cert in certs {
+                root_store.add(cert).map_err(|e| {
+                    SinpError::Transport(format!(
+                        "Invalid CA certificate in {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+            }
+        }
+
+        Ok(root_store)
     }
 
     /// Send a request and receive response.
     pub async fn send_request(&mut self, request: &Request) -> SinpResult<Response> {
-        match self {
-            Self::Tcp(stream) => Self::send_recv(stream, request).await,
-            Self::Tls(stream) => Self::send_recv(stream, request).await,
+        if self.session.is_legacy() {
+            self.write_frame(request).await?;
+            let frame = self.read_frame().await?;
+            return Ok(serde_json::from_slice(&frame)?);
+        }
+
+        self.ensure_tagged()?;
+        self.write_frame(&ClientFrame::Request(Box::new(request.clone())))
+            .await?;
+        loop {
+            match self.next_frame().await? {
+                ServerFrame::Progress(progress) => {
+                    tracing::debug!("Skipping progress {}", progress.sequence);
+                }
+                ServerFrame::Response(response) => return Ok(*response),
+                other => return Err(unexpected_frame("response", &other)),
+            }
         }
     }
 
-    /// Send request and receive response on stream.
-    async fn send_recv<S>(stream: &mut S, request: &Request) -> SinpResult<Response>
-    where
-        S: AsyncReadExt + AsyncWriteExt + Unpin,
+    /// Send a request and receive the progress reported while it runs,
+    /// followed by the response.
+    ///
+    /// The stream ends after the response. Progress is only sent if the
+    /// session agreed on [`Feature::Streaming`](sinp_core::Feature::Streaming).
+    /// Frames are read as the stream is polled, so a consumer that falls
+    /// behind holds the server's handler back rather than being flooded.
+    pub async fn send_request_streaming(
+        &mut self,
+        request: &Request,
+    ) -> SinpResult<impl futures_util::Stream<Item = SinpResult<StreamEvent>> + '_> {
+        self.ensure_tagged()?;
+        self.write_frame(&ClientFrame::Request(Box::new(request.clone())))
+            .await?;
+
+        Ok(futures_util::stream::unfold(
+            Some(self),
+            |connection| async move {
+                let connection = connection?;
+                match connection.next_frame().await {
+                    Ok(ServerFrame::Progress(progress)) => {
+                        Some((Ok(StreamEvent::Progress(progress)), Some(connection)))
+                    }
+                    Ok(ServerFrame::Response(response)) => {
+                        Some((Ok(StreamEvent::Response(response)), None))
+                    }
+                    Ok(other) => Some((Err(unexpected_frame("response", &other)), None)),
+                    Err(e) => Some((Err(e), None)),
+                }
+            },
+        ))
+    }
+
+    /// Send a request and receive its response, cancelling it if `cancel`
+    /// completes first.
+    ///
+    /// On cancellation a CANCEL for the request's conversation is sent and
+    /// the server's response, normally a `cancelled` refusal, is still
+    /// awaited. The acknowledgement is returned with it, or `None` if the
+    /// request completed before the server saw the CANCEL. Progress is
+    /// passed to `on_progress`.
+    pub async fn send_request_cancellable(
+        &mut self,
+        request: &Request,
+        on_progress: &mut dyn FnMut(&Progress),
+        cancel: impl Future<Output = ()>,
+    ) -> SinpResult<(Response, Option<CancelAck>)> {
+        self.ensure_tagged()?;
+        self.write_frame(&ClientFrame::Request(Box::new(request.clone())))
+            .await?;
+
+        let mut cancel = std::pin::pin!(cancel);
+        let mut sent: Option<Cancel> = None;
+        let response = loop {
+            tokio::select! {
+                frame = self.next_frame() => match frame? {
+                    ServerFrame::Progress(progress) => on_progress(&progress),
+                    ServerFrame::Response(response) => break *response,
+                    other => return Err(unexpected_frame("response", &other)),
+                },
+                () = &mut cancel, if sent.is_none() => {
+                    let mut message = Cancel::new(request.sender.clone(), request.conversation_id);
+                    message.credential = request.credential.clone();
+                    self.write_frame(&ClientFrame::Cancel(message.clone())).await?;
+                    sent = Some(message);
+                }
+            }
+        };
+        let Some(sent) = sent else {
+            return Ok((response, None));
+        };
+
+        // The server answers the CANCEL after the response
+        let frame = self.read_frame().await?;
+        match serde_json::from_slice(&frame)? {
+            ServerFrame::CancelAck(ack) if ack.in_response_to == sent.message_id => {
+                Ok((response, Some(ack)))
+            }
+            ServerFrame::Error(error) if error.in_response_to == sent.message_id => {
+                tracing::debug!("Cancel arrived too late: {}", error.reason);
+                Ok((response, None))
+            }
+            other => Err(unexpected_frame("cancel acknowledgement", &other)),
+        }
+    }
+
+    /// Cancel a conversation that is not waiting for a response.
+    pub async fn cancel(&mut self, cancel: &Cancel) -> SinpResult<CancelAck> {
+        match self.exchange(&ClientFrame::Cancel(cancel.clone())).await? {
+            ServerFrame::CancelAck(ack) => Ok(ack),
+            other => Err(unexpected_frame("cancel acknowledgement", &other)),
+        }
+    }
+
+    /// Request a page of the capability catalog.
+    pub async fn discover(&mut self, request: &DiscoveryRequest) -> SinpResult<Catalog> {
+        match self
+            .exchange(&ClientFrame::Discover(request.clone()))
+            .await?
+        {
+            ServerFrame::Catalog(catalog) => Ok(catalog),
+            other => Err(unexpected_frame("catalog", &other)),
+        }
+    }
+
+    /// Query background jobs; with `wait` set, the reply comes once the
+    /// job has completed.
+    pub async fn jobs(&mut self, query: &JobQuery) -> SinpResult<Vec<Job>> {
+        match self.exchange(&ClientFrame::Jobs(query.clone())).await? {
+            ServerFrame::Jobs(list) => Ok(list.jobs),
+            other => Err(unexpected_frame("jobs", &other)),
+        }
+    }
+
+    /// List scheduled executions, or cancel one if the query names it.
+    pub async fn schedules(
+        &mut self,
+        query: &ScheduleQuery,
+    ) -> SinpResult<Vec<ScheduledExecution>> {
+        match self
+            .exchange(&ClientFrame::Schedules(query.clone()))
+            .await?
+        {
+            ServerFrame::Schedules(list) => Ok(list.schedules),
+            other => Err(unexpected_frame("schedules", &other)),
+        }
+    }
+
+    /// Register a callback for pushed events of `kind`.
+    ///
+    /// Callbacks run on the connection's reader task, so one that blocks
+    /// holds back every frame behind it. Events are only pushed once
+    /// subscribed to with [`Self::subscribe`].
+    pub fn on_event(&mut self, kind: EventKind, handler: impl FnMut(&Event) + Send + 'static) {
+        self.handlers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((kind, Box::new(handler)));
+    }
+
+    /// Replace the events the server pushes on this connection and return
+    /// those it agreed to.
+    pub async fn subscribe(&mut self, subscribe: &Subscribe) -> SinpResult<Vec<EventKind>> {
+        match self
+            .exchange(&ClientFrame::Subscribe(subscribe.clone()))
+            .await?
+        {
+            ServerFrame::Subscribed(subscribed) => Ok(subscribed.events),
+            other => Err(unexpected_frame("subscription", &other)),
+        }
+    }
+
+    /// Send a tagged frame and read the server's reply.
+    async fn exchange(&mut self, frame: &ClientFrame) -> SinpResult<ServerFrame> {
+        self.ensure_tagged()?;
+        self.write_frame(frame).await?;
+        self.next_frame().await
+    }
+
+    /// Fail unless the session uses tagged frames.
+    fn ensure_tagged(&self) -> SinpResult<()> {
+        if self.session.is_legacy() {
+            return Err(SinpError::Protocol(format!(
+                "Not available with protocol {}",
+                self.session.version
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read a tagged frame, turning error frames into errors.
+    async fn next_frame(&mut self) -> SinpResult<ServerFrame> {
+        let reply = self.read_frame().await?;
+        match serde_json::from_slice(&reply)? {
+            ServerFrame::Error(error) => Err(error.into_error()),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Write a length-prefixed JSON frame.
+    async fn write_frame<T: serde::Serialize>(&mut self, message: &T) -> SinpResult<()> {
+        let json = serde_json::to_vec(message)?;
+        write_frame(&mut self.writer, &json).await
+    }
+
+    /// Next frame from the reader task, other than pushed events.
+    ///
+    /// Cancel safe: a frame is never lost by dropping the future.
+    async fn read_frame(&mut self) -> SinpResult<Vec<u8>> {
+        self.frames.recv().await.unwrap_or_else(|| {
+            Err(SinpError::Transport(
+                "Read error: connection closed".to_string(),
+            ))
+        })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // The reader holds half of the stream open until it stops
+        self.reader.abort();
+    }
+}
+
+/// Pass an event to the callbacks registered for its kind.
+fn dispatch(handlers: &EventHandlers, event: &Event) {
+    let mut handlers = handlers.lock().unwrap_or_else(|e| e.into_inner());
+    let mut handled = false;
+    for (kind, handler) in handlers.iter_mut() {
+        if *kind == event.kind() {
+            handler(event);
+            handled = true;
+        }
+    }
+    if !handled {
+        tracing::debug!("No handler for {:?} event", event.kind());
+    }
+}
+
+async fn write_frame<S>(stream: &mut S, json: &[u8]) -> SinpResult<()>
+where
+    S: AsyncWriteExt + Unpin,
+{
+    let len = json.len() as u32;
+
+    // Send length prefix + message
+    stream
+        .write_all(&len.to_be_bytes())
+        .await
+        .map_err(|e| SinpError::Transport(format!("Write error: {}", e)))?;
+    stream
+        .write_all(json)
+        .await
+        .map_err(|e| SinpError::Transport(format!("Write error: {}", e)))?;
+    stream
+        .flush()
+        .await
+        .map_err(|e| SinpError::Transport(format!("Flush error: {}", e)))
+}
+
+/// Read a length-prefixed frame.
+async fn read_frame<S>(stream: &mut S, max_message_size: usize) -> SinpResult<Vec<u8>>
+where
+    S: AsyncReadExt + Unpin,
+{
+    // Read response length
+    let mut len_buf = [0u8; 4];
+    stream
+        .read_exact(&mut len_buf)
+        .await
+        .map_err(|e| SinpError::Transport(format!("Read error: {}", e)))?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > max_message_size {
+        return Err(SinpError::Validation(format!(
+            "Message too large: {} > {}",
+            len, max_message_size
+        )));
+    }
+
+    // Read response body
+    let mut msg_buf = vec![0u8; len];
+    stream
+        .read_exact(&mut msg_buf)
+        .await
+        .map_err(|e| SinpError::Transport(format!("Read error: {}", e)))?;
+    Ok(msg_buf)
+}
+
+fn unexpected_frame(expected: &str, frame: &ServerFrame) -> SinpError {
+    SinpError::Protocol(format!("Expected {}, got {:?}", expected, frame))
+}
+
+/// Decode a base64 SPKI pin.
+fn decode_pin(pin: &str) -> SinpResult<[u8; 32]> {
+    base64::engine::general_purpose::STANDARD
+        .decode(pin)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            SinpError::Transport(format!(
+                "Invalid SPKI pin (expected base64 SHA-256): {}",
+                pin
+            ))
+        })
+}
+
+/// Map a failed TLS handshake to a SINP error.
+fn handshake_error(error: std::io::Error, server_name: &str) -> SinpError {
+    let reason = match error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
     {
-        // Serialize request
-        let json = serde_json::to_vec(request)?;
-        let len = json.len() as u32;
+        Some(rustls::Error::InvalidCertificate(reason)) => reason,
+        _ => return SinpError::Transport(format!("TLS handshake failed: {}", error)),
+    };
 
-        // Send length prefix + message
-        stream
-            .write_all(&len.to_be_bytes())
-            .await
-            .map_err(|e| SinpError::Transport(format!("Write error: {}", e)))?;
-        stream
-            .write_all(&json)
-            .await
-            .map_err(|e| SinpError::Transport(format!("Write error: {}", e)))?;
-        stream
-            .flush()
-            .await
-            .map_err(|e| SinpError::Transport(format!("Flush error: {}", e)))?;
+    let message = match reason {
+        CertificateError::UnknownIssuer => {
+            "server certificate is not signed by a trusted CA".to_string()
+        }
+        CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. } => {
+            format!("server certificate is not valid for {}", server_name)
+        }
+        CertificateError::Expired | CertificateError::ExpiredContext { .. } => {
+            "server certificate has expired".to_string()
+        }
+        CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+            "server certificate is not valid yet".to_string()
+        }
+        CertificateError::ApplicationVerificationFailure => {
+            "server public key does not match any pinned SPKI hash".to_string()
+        }
+        other => format!("{:?}", other),
+    };
+    SinpError::CertificateVerification(message)
+}
 
-        // Read response length
-        let mut len_buf = [0u8; 4];
-        stream
-            .read_exact(&mut len_buf)
-            .await
-            .map_err(|e| SinpError::Transport(format!("Read error: {}", e)))?;
-        let len = u32::from_be_bytes(len_buf) as usize;
+/// Server certificate verifier enforcing SPKI pins.
+///
+/// Chain verification is delegated to webpki when roots are configured.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    chain: Option<Arc<WebPkiServerVerifier>>,
+    pins: Vec<[u8; 32]>,
+    provider: Arc<CryptoProvider>,
+}
 
-        // Read response body
-        let mut msg_buf = vec![0u8; len];
-        stream
-            .read_exact(&mut msg_buf)
-            .await
-            .map_err(|e| SinpError::Transport(format!("Read error: {}", e)))?;
+impl PinnedCertVerifier {
+    fn new(
+        roots: RootCertStore,
+        pins: Vec<[u8; 32]>,
+        provider: Arc<CryptoProvider>,
+    ) -> SinpResult<Self> {
+        let chain = if roots.is_empty() {
+            None
+        } else {
+            let verifier =
+                WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .map_err(|e| SinpError::Transport(format!("TLS verifier error: {}", e)))?;
+            Some(verifier)
+        };
+
+        Ok(Self {
+            chain,
+            pins,
+            provider,
+        })
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(ref chain) = self.chain {
+            chain.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+
+        let hash = spki_hash(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        if !self.pins.contains(&hash) {
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
 
-        // Parse response
-        let response: Response = serde_json::from_slice(&msg_buf)?;
-        Ok(response)
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
     }
 }
@@ -19,15 +19,18 @@
 mod connection;
 mod state_machine;
 
-pub use connection::{Connection, ConnectionConfig};
+pub use connection::{spki_sha256, Connection, ConnectionConfig, StreamEvent};
 pub use state_machine::{ClientStateMachine, NextAction};
 
+use std::future::Future;
 use std::net::SocketAddr;
 
 use sinp_core::{
     message::{AuthMethod, Context, ContextType, Sender},
     security::semantic_hash,
-    Action, Alternative, Request, SinpResult,
+    Action, Alternative, Cancel, Capability, Catalog, DiscoveryRequest, Event, EventKind, Job,
+    JobQuery, Preview, Progress, Request, Schedule, ScheduleQuery, ScheduledExecution, SinpResult,
+    Subscribe,
 };
 
 /// High-level SINP client.
@@ -35,7 +38,11 @@ pub struct SinpClient {
     connection: Connection,
     state_machine: ClientStateMachine,
     sender: Sender,
+    credential: Option<String>,
+    catalog: Option<Catalog>,
     context_history: Vec<String>,
+    /// Kinds of events subscribed to on the connection.
+    events: Vec<EventKind>,
 }
 
 impl SinpClient {
@@ -56,7 +63,10 @@ impl SinpClient {
                 id: format!("client_{}", uuid::Uuid::new_v4()),
                 auth_method: AuthMethod::None,
             },
+            credential: None,
+            catalog: None,
             context_history: Vec::new(),
+            events: Vec::new(),
         })
     }
 
@@ -70,17 +80,34 @@ impl SinpClient {
             .parse()
             .map_err(|e| sinp_core::SinpError::Transport(format!("Invalid address: {}", e)))?;
 
-        let config = ConnectionConfig::tls(addr, server_name);
+        Self::connect_with_config(ConnectionConfig::tls(addr, server_name)).await
+    }
+
+    /// Connect to a SINP server with an explicit connection configuration.
+    ///
+    /// Use this to configure the TLS trust store (CA files, system roots,
+    /// SPKI pins) and client certificate. With a client certificate the
+    /// sender claims certificate authentication; its ID must then be set
+    /// (see [`SinpClient::with_sender`]) to the certificate's common name.
+    pub async fn connect_with_config(config: ConnectionConfig) -> SinpResult<Self> {
         let connection = Connection::connect(&config).await?;
+        let auth_method = if config.use_tls && config.client_cert_path.is_some() {
+            AuthMethod::Certificate
+        } else {
+            AuthMethod::None
+        };
 
         Ok(Self {
             connection,
             state_machine: ClientStateMachine::new(),
             sender: Sender {
                 id: format!("client_{}", uuid::Uuid::new_v4()),
-                auth_method: AuthMethod::Certificate,
+                auth_method,
             },
+            credential: None,
+            catalog: None,
             context_history: Vec::new(),
+            events: Vec::new(),
         })
     }
 
@@ -90,6 +117,25 @@ impl SinpClient {
         self
     }
 
+    /// Authenticate with an API key.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.sender.auth_method = AuthMethod::ApiKey;
+        self.credential = Some(key.into());
+        self
+    }
+
+    /// Authenticate with a signed token issued to the sender ID.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.sender.auth_method = AuthMethod::Token;
+        self.credential = Some(token.into());
+        self
+    }
+
+    /// Parameters agreed with the server in the HELLO handshake.
+    pub fn session(&self) -> &sinp_core::Session {
+        self.connection.session()
+    }
+
     /// Get current state.
     pub fn state(&self) -> sinp_core::ClientState {
         self.state_machine.state()
@@ -101,19 +147,319 @@ impl SinpClient {
         intent: impl Into<String>,
         confidence: f64,
     ) -> SinpResult<NextAction> {
-        let intent = intent.into();
+        self.send(
+            intent.into(),
+            confidence,
+            |_| {},
+            &mut |_| {},
+            std::future::pending(),
+        )
+        .await
+    }
+
+    /// Send an intent, cancelling it on the server if `cancel` completes
+    /// before the response arrives.
+    ///
+    /// A running capability is asked to stop and the conversation is
+    /// abandoned; the server's refusal is returned as
+    /// [`NextAction::Refused`]. Not available with the legacy 0.1 framing.
+    pub async fn send_intent_until(
+        &mut self,
+        intent: impl Into<String>,
+        confidence: f64,
+        cancel: impl Future<Output = ()>,
+    ) -> SinpResult<NextAction> {
+        if self.connection.session().is_legacy() {
+            return Err(sinp_core::SinpError::Protocol(
+                "Cancellation needs a HELLO handshake".to_string(),
+            ));
+        }
+        self.send(intent.into(), confidence, |_| {}, &mut |_| {}, cancel)
+            .await
+    }
+
+    /// Send an intent, passing the progress the capability reports to
+    /// `on_progress` as it arrives.
+    ///
+    /// Progress is only reported if the session agreed on streaming.
+    pub async fn send_intent_with_progress(
+        &mut self,
+        intent: impl Into<String>,
+        confidence: f64,
+        mut on_progress: impl FnMut(&Progress),
+    ) -> SinpResult<NextAction> {
+        self.send(
+            intent.into(),
+            confidence,
+            |_| {},
+            &mut on_progress,
+            std::future::pending(),
+        )
+        .await
+    }
+
+    /// Send an intent with an idempotency key.
+    ///
+    /// Sending the same intent with the same key again, e.g. from a new
+    /// connection after a timeout, returns the original response instead of
+    /// executing twice.
+    pub async fn send_intent_idempotent(
+        &mut self,
+        intent: impl Into<String>,
+        confidence: f64,
+        key: impl Into<String>,
+    ) -> SinpResult<NextAction> {
+        let key = key.into();
+        self.send(
+            intent.into(),
+            confidence,
+            |request| request.idempotency_key = Some(key),
+            &mut |_| {},
+            std::future::pending(),
+        )
+        .await
+    }
+
+    /// Send an intent to run as a background job.
+    ///
+    /// Once negotiated to EXECUTE the server answers right away with
+    /// [`NextAction::Accepted`]; fetch the outcome with
+    /// [`SinpClient::job`] or [`SinpClient::wait_for_job`], also from a
+    /// later connection with the same sender.
+    pub async fn send_intent_in_background(
+        &mut self,
+        intent: impl Into<String>,
+        confidence: f64,
+    ) -> SinpResult<NextAction> {
+        self.send(
+            intent.into(),
+            confidence,
+            |request| request.background = true,
+            &mut |_| {},
+            std::future::pending(),
+        )
+        .await
+    }
+
+    /// Send an intent to run later, once or repeatedly.
+    ///
+    /// Once negotiated to EXECUTE the server stores it and answers with
+    /// [`NextAction::Scheduled`]; each run starts a background job. List
+    /// and cancel scheduled executions with [`SinpClient::scheduled`] and
+    /// [`SinpClient::cancel_scheduled`].
+    pub async fn send_intent_scheduled(
+        &mut self,
+        intent: impl Into<String>,
+        confidence: f64,
+        schedule: Schedule,
+    ) -> SinpResult<NextAction> {
+        self.send(
+            intent.into(),
+            confidence,
+            |request| request.schedule = Some(schedule),
+            &mut |_| {},
+            std::future::pending(),
+        )
+        .await
+    }
+
+    async fn send(
+        &mut self,
+        intent: String,
+        confidence: f64,
+        options: impl FnOnce(&mut Request),
+        on_progress: &mut dyn FnMut(&Progress),
+        cancel: impl Future<Output = ()>,
+    ) -> SinpResult<NextAction> {
         self.context_history.push(format!("User: {}", intent));
 
         let context = self.build_context();
-        let request = Request::new(self.sender.clone(), &intent, confidence, context);
+        let mut request = Request::new(self.sender.clone(), &intent, confidence, context);
+        options(&mut request);
+        self.stamp(&mut request);
 
         self.state_machine.on_request_sent(&request)?;
-        let response = self.connection.send_request(&request).await?;
+        let (response, cancelled) = if self.connection.session().is_legacy() {
+            (self.connection.send_request(&request).await?, None)
+        } else {
+            self.connection
+                .send_request_cancellable(&request, on_progress, cancel)
+                .await?
+        };
 
         self.context_history
             .push(format!("Server: {}", response.interpretation.text));
 
-        self.state_machine.on_response_received(response)
+        let next = self.state_machine.on_response_received(response)?;
+        // Cancelled after the server replied but before the conversation ended
+        if cancelled.is_some() && !self.state_machine.state().is_terminal() {
+            self.state_machine.abandon()?;
+        }
+        Ok(next)
+    }
+
+    /// Cancel the current conversation on the server and abandon it.
+    ///
+    /// Use this between responses, e.g. instead of answering a
+    /// clarification; to stop a request that is still running, send it with
+    /// [`SinpClient::send_intent_until`].
+    pub async fn cancel(&mut self, reason: impl Into<String>) -> SinpResult<()> {
+        let conversation_id = self
+            .state_machine
+            .conversation_id()
+            .filter(|_| !self.state_machine.state().is_terminal())
+            .ok_or_else(|| {
+                sinp_core::SinpError::Protocol("No conversation in progress".to_string())
+            })?;
+
+        let mut cancel = Cancel::new(self.sender.clone(), conversation_id);
+        cancel.credential = self.credential.clone();
+        cancel.reason = Some(reason.into());
+        self.connection.cancel(&cancel).await?;
+        self.state_machine.abandon()
+    }
+
+    /// Preview what the server would do for an intent, without side effects.
+    ///
+    /// The dry run is independent of the current conversation and does not
+    /// change client state.
+    pub async fn preview(
+        &mut self,
+        intent: impl Into<String>,
+        confidence: f64,
+    ) -> SinpResult<Preview> {
+        let mut request = Request::new(self.sender.clone(), intent, confidence, self.build_context());
+        request.dry_run = true;
+        self.stamp(&mut request);
+
+        let response = self.connection.send_request(&request).await?;
+        response
+            .action_metadata
+            .and_then(|m| m.preview)
+            .ok_or_else(|| sinp_core::SinpError::Protocol("Response carries no preview".to_string()))
+    }
+
+    /// Fetch the capabilities available to this sender.
+    ///
+    /// The catalog is cached; it is only transferred again if its version
+    /// changed since the last call.
+    pub async fn discover(&mut self) -> SinpResult<&[Capability]> {
+        let mut request = DiscoveryRequest::new(self.sender.clone());
+        request.credential = self.credential.clone();
+        request.if_none_match = self.catalog.as_ref().map(|c| c.version.clone());
+
+        let mut catalog = self.connection.discover(&request).await?;
+        if !catalog.not_modified {
+            while let Some(cursor) = catalog.next_cursor.take() {
+                let mut next = DiscoveryRequest::new(self.sender.clone());
+                next.credential = self.credential.clone();
+                next.cursor = Some(cursor);
+                let page = self.connection.discover(&next).await?;
+                catalog.capabilities.extend(page.capabilities);
+                catalog.next_cursor = page.next_cursor;
+            }
+            self.catalog = Some(catalog);
+        }
+
+        Ok(self
+            .catalog
+            .as_ref()
+            .map(|c| c.capabilities.as_slice())
+            .unwrap_or_default())
+    }
+
+    /// Whether the last response reported a catalog version other than the
+    /// cached one (or nothing is cached yet).
+    pub fn catalog_changed(&self) -> bool {
+        let reported = self
+            .state_machine
+            .last_response()
+            .and_then(|r| r.responder.catalog_version.as_deref());
+        match (reported, &self.catalog) {
+            (Some(reported), Some(catalog)) => reported != catalog.version,
+            (_, None) => true,
+            (None, Some(_)) => false,
+        }
+    }
+
+    /// Call `handler` for every event of `kind` the server pushes about this
+    /// sender, e.g. a background job completing.
+    ///
+    /// The connection is subscribed to the kinds of all handlers registered
+    /// so far. Handlers run on the connection's reader task, apart from the
+    /// responses to requests, and should not block.
+    pub async fn subscribe(
+        &mut self,
+        kind: EventKind,
+        handler: impl FnMut(&Event) + Send + 'static,
+    ) -> SinpResult<()> {
+        self.connection.on_event(kind, handler);
+        if !self.events.contains(&kind) {
+            self.events.push(kind);
+        }
+        let mut subscribe = Subscribe::new(self.sender.clone(), self.events.clone());
+        subscribe.credential = self.credential.clone();
+        self.connection.subscribe(&subscribe).await?;
+        Ok(())
+    }
+
+    /// List this sender's background jobs, oldest first.
+    pub async fn jobs(&mut self) -> SinpResult<Vec<Job>> {
+        let query = self.job_query(None);
+        self.connection.jobs(&query).await
+    }
+
+    /// Current status of a background job.
+    pub async fn job(&mut self, id: uuid::Uuid) -> SinpResult<Job> {
+        let query = self.job_query(Some(id));
+        self.fetch_job(query).await
+    }
+
+    /// Wait until a background job completes.
+    pub async fn wait_for_job(&mut self, id: uuid::Uuid) -> SinpResult<Job> {
+        let mut query = self.job_query(Some(id));
+        query.wait = true;
+        self.fetch_job(query).await
+    }
+
+    fn job_query(&self, job_id: Option<uuid::Uuid>) -> JobQuery {
+        let mut query = JobQuery::new(self.sender.clone());
+        query.credential = self.credential.clone();
+        query.job_id = job_id;
+        query
+    }
+
+    async fn fetch_job(&mut self, query: JobQuery) -> SinpResult<Job> {
+        self.connection
+            .jobs(&query)
+            .await?
+            .pop()
+            .ok_or_else(|| sinp_core::SinpError::Protocol("Server returned no job".to_string()))
+    }
+
+    /// List this sender's scheduled executions, soonest first.
+    pub async fn scheduled(&mut self) -> SinpResult<Vec<ScheduledExecution>> {
+        let query = self.schedule_query(None);
+        self.connection.schedules(&query).await
+    }
+
+    /// Cancel one of this sender's scheduled executions.
+    pub async fn cancel_scheduled(&mut self, id: uuid::Uuid) -> SinpResult<ScheduledExecution> {
+        let query = self.schedule_query(Some(id));
+        self.connection
+            .schedules(&query)
+            .await?
+            .pop()
+            .ok_or_else(|| {
+                sinp_core::SinpError::Protocol("Server returned no scheduled execution".to_string())
+            })
+    }
+
+    fn schedule_query(&self, cancel: Option<uuid::Uuid>) -> ScheduleQuery {
+        let mut query = ScheduleQuery::new(self.sender.clone());
+        query.credential = self.credential.clone();
+        query.cancel = cancel;
+        query
     }
 
     /// Respond to a CLARIFY action with answers.
@@ -132,7 +478,8 @@ impl SinpClient {
             .ok_or_else(|| sinp_core::SinpError::Protocol("No previous response".to_string()))?
             .clone();
 
-        let request = Request::reply(&last_response, self.sender.clone(), &answers, confidence, context);
+        let mut request = Request::reply(&last_response, self.sender.clone(), &answers, confidence, context);
+        self.stamp(&mut request);
 
         self.state_machine.on_clarification_provided()?;
         self.state_machine.on_request_sent(&request)?;
@@ -144,6 +491,15 @@ impl SinpClient {
         self.state_machine.on_response_received(response)
     }
 
+    /// Answer a confirmation request.
+    ///
+    /// The server executes the confirmed capability without re-interpreting
+    /// the original intent.
+    pub async fn confirm(&mut self, accept: bool) -> SinpResult<NextAction> {
+        let answer = if accept { "yes" } else { "no" };
+        self.respond_to_clarify(answer, 1.0).await
+    }
+
     /// Accept a proposal.
     pub async fn accept_proposal(
         &mut self,
@@ -160,7 +516,8 @@ impl SinpClient {
             .ok_or_else(|| sinp_core::SinpError::Protocol("No previous response".to_string()))?
             .clone();
 
-        let request = Request::reply(&last_response, self.sender.clone(), &intent, confidence, context);
+        let mut request = Request::reply(&last_response, self.sender.clone(), &intent, confidence, context);
+        self.stamp(&mut request);
 
         self.state_machine.on_proposal_accepted()?;
         self.state_machine.on_request_sent(&request)?;
@@ -189,7 +546,8 @@ impl SinpClient {
             .ok_or_else(|| sinp_core::SinpError::Protocol("No previous response".to_string()))?
             .clone();
 
-        let request = Request::reply(&last_response, self.sender.clone(), &new_intent, confidence, context);
+        let mut request = Request::reply(&last_response, self.sender.clone(), &new_intent, confidence, context);
+        self.stamp(&mut request);
 
         self.state_machine.on_proposal_rejected()?;
         self.state_machine.on_request_sent(&request)?;
@@ -216,6 +574,12 @@ impl SinpClient {
         self.context_history.clear();
     }
 
+    /// Fill in the negotiated protocol version and the credential.
+    fn stamp(&self, request: &mut Request) {
+        request.protocol_version = self.connection.session().version.clone();
+        request.credential = self.credential.clone();
+    }
+
     /// Build context from history.
     fn build_context(&self) -> Context {
         let content = self.context_history.join("\n");
@@ -1,7 +1,7 @@
 //! Client state machine for SINP protocol.
 
 use sinp_core::{
-    Action, ClientEvent, ClientState, Request, Response, SinpError, SinpResult,
+    Action, ClientEvent, ClientState, RefusalCode, Request, Response, SinpError, SinpResult,
 };
 
 /// Client state machine managing conversation flow.
@@ -51,27 +51,66 @@ impl ClientStateMachine {
         let next = match response.action {
             Action::Execute => {
                 self.transition(ClientEvent::ResponseExecute)?;
-                NextAction::Done(response)
+                let metadata = response.action_metadata.as_ref();
+                let job = metadata.and_then(|m| m.job.clone());
+                let scheduled = metadata.and_then(|m| m.scheduled.clone());
+                match (job, scheduled) {
+                    (Some(job), _) => NextAction::Accepted { job, response },
+                    (None, Some(scheduled)) => NextAction::Scheduled {
+                        scheduled,
+                        response,
+                    },
+                    (None, None) => NextAction::Done(response),
+                }
             }
             Action::Clarify => {
                 self.transition(ClientEvent::ResponseClarify)?;
-                let questions = response
+                let confirmation = response
                     .action_metadata
                     .as_ref()
-                    .and_then(|m| m.questions.clone())
-                    .unwrap_or_default();
-                NextAction::Clarify { questions, response }
+                    .and_then(|m| m.confirmation.clone());
+                if let Some(confirmation) = confirmation {
+                    NextAction::Confirm {
+                        confirmation,
+                        response,
+                    }
+                } else {
+                    let questions = response
+                        .action_metadata
+                        .as_ref()
+                        .and_then(|m| m.questions.clone())
+                        .unwrap_or_default();
+                    NextAction::Clarify { questions, response }
+                }
             }
             Action::Propose => {
                 self.transition(ClientEvent::ResponsePropose)?;
-                let alternatives = response.alternatives.clone().unwrap_or_default();
-                NextAction::Propose {
-                    alternatives,
-                    response,
+                let plan = response
+                    .action_metadata
+                    .as_ref()
+                    .and_then(|m| m.plan.clone());
+                if let Some(plan) = plan {
+                    NextAction::ProposePlan { plan, response }
+                } else {
+                    let alternatives = response.alternatives.clone().unwrap_or_default();
+                    NextAction::Propose {
+                        alternatives,
+                        response,
+                    }
                 }
             }
             Action::Refuse => {
-                self.transition(ClientEvent::ResponseRefuse)?;
+                // A request cancelled by the client ends the conversation
+                let cancelled = response
+                    .action_metadata
+                    .as_ref()
+                    .and_then(|m| m.reason_code)
+                    == Some(RefusalCode::Cancelled);
+                self.transition(if cancelled {
+                    ClientEvent::Abandoned
+                } else {
+                    ClientEvent::ResponseRefuse
+                })?;
                 let reason = response
                     .action_metadata
                     .as_ref()
@@ -119,6 +158,7 @@ impl ClientStateMachine {
             (ClientState::Pending, ClientEvent::ResponseClarify) => ClientState::Refining,
             (ClientState::Pending, ClientEvent::ResponsePropose) => ClientState::Refining,
             (ClientState::Pending, ClientEvent::ResponseRefuse) => ClientState::Failed,
+            (ClientState::Pending, ClientEvent::Abandoned) => ClientState::Abandoned,
             (ClientState::Refining, ClientEvent::ClarificationProvided) => ClientState::Pending,
             (ClientState::Refining, ClientEvent::ProposalAccepted) => ClientState::Pending,
             (ClientState::Refining, ClientEvent::ProposalRejected) => ClientState::Pending,
@@ -157,16 +197,36 @@ impl Default for ClientStateMachine {
 pub enum NextAction {
     /// Intent satisfied, contains result.
     Done(Response),
+    /// Execution accepted as a background job; fetch its result later.
+    Accepted {
+        job: sinp_core::Job,
+        response: Response,
+    },
+    /// Execution stored by the server to run on the request's schedule.
+    Scheduled {
+        scheduled: sinp_core::ScheduledExecution,
+        response: Response,
+    },
     /// Server needs clarification.
     Clarify {
         questions: Vec<String>,
         response: Response,
     },
+    /// Server asks to confirm its interpretation before executing.
+    Confirm {
+        confirmation: sinp_core::Confirmation,
+        response: Response,
+    },
     /// Server proposes alternatives.
     Propose {
         alternatives: Vec<sinp_core::Alternative>,
         response: Response,
     },
+    /// Server proposes a plan for a compound intent; answer yes/no.
+    ProposePlan {
+        plan: sinp_core::Plan,
+        response: Response,
+    },
     /// Request was refused.
     Refused { reason: String, response: Response },
 }
@@ -204,6 +264,7 @@ mod tests {
             responder: Responder {
                 id: "srv".to_string(),
                 capabilities: vec![],
+                catalog_version: None,
             },
             interpretation: Interpretation {
                 text: "test".to_string(),
@@ -213,6 +274,7 @@ mod tests {
             action_metadata: None,
             alternatives: None,
             confidence: 0.9,
+            explanation: None,
         }
     }
 
@@ -231,6 +293,63 @@ mod tests {
         assert_eq!(sm.state(), ClientState::Satisfied);
     }
 
+    #[test]
+    fn accepted_flow() {
+        let mut sm = ClientStateMachine::new();
+        let req = sample_request();
+        sm.on_request_sent(&req).unwrap();
+
+        let mut resp = sample_response(Action::Execute);
+        resp.action_metadata = Some(sinp_core::ActionMetadata {
+            job: Some(sinp_core::Job {
+                id: uuid::Uuid::new_v4(),
+                conversation_id: resp.conversation_id,
+                sender_id: "test".to_string(),
+                capability_id: "echo:v1".to_string(),
+                intent: "echo".to_string(),
+                status: sinp_core::JobStatus::Running,
+                submitted_at: chrono::Utc::now(),
+                completed_at: None,
+                expires_at: None,
+                result: None,
+                error: None,
+            }),
+            ..Default::default()
+        });
+        let next = sm.on_response_received(resp).unwrap();
+        assert!(matches!(next, NextAction::Accepted { .. }), "{:?}", next);
+        assert_eq!(sm.state(), ClientState::Satisfied);
+    }
+
+    #[test]
+    fn scheduled_flow() {
+        let mut sm = ClientStateMachine::new();
+        let req = sample_request();
+        sm.on_request_sent(&req).unwrap();
+
+        let mut resp = sample_response(Action::Execute);
+        let at = chrono::Utc::now() + chrono::Duration::hours(1);
+        resp.action_metadata = Some(sinp_core::ActionMetadata {
+            scheduled: Some(sinp_core::ScheduledExecution {
+                id: uuid::Uuid::new_v4(),
+                conversation_id: resp.conversation_id,
+                sender_id: "test".to_string(),
+                capability_id: "echo:v1".to_string(),
+                intent: "echo".to_string(),
+                schedule: sinp_core::Schedule::At { at },
+                created_at: chrono::Utc::now(),
+                next_run: at,
+                last_run: None,
+                last_job: None,
+                runs: 0,
+            }),
+            ..Default::default()
+        });
+        let next = sm.on_response_received(resp).unwrap();
+        assert!(matches!(next, NextAction::Scheduled { .. }), "{:?}", next);
+        assert_eq!(sm.state(), ClientState::Satisfied);
+    }
+
     #[test]
     fn clarify_flow() {
         let mut sm = ClientStateMachine::new();
@@ -245,4 +364,67 @@ mod tests {
         sm.on_clarification_provided().unwrap();
         assert_eq!(sm.state(), ClientState::Pending);
     }
+
+    #[test]
+    fn confirm_flow() {
+        let mut sm = ClientStateMachine::new();
+        let req = sample_request();
+        sm.on_request_sent(&req).unwrap();
+
+        let mut resp = sample_response(Action::Clarify);
+        resp.action_metadata = Some(sinp_core::ActionMetadata {
+            confirmation: Some(sinp_core::Confirmation {
+                capability_id: "echo:v1".to_string(),
+                interpretation: "Execute echo:v1".to_string(),
+                estimated_cost: 0.1,
+            }),
+            ..Default::default()
+        });
+        let next = sm.on_response_received(resp).unwrap();
+        match next {
+            NextAction::Confirm { confirmation, .. } => {
+                assert_eq!(confirmation.capability_id, "echo:v1")
+            }
+            other => panic!("expected Confirm, got {:?}", other),
+        }
+        assert_eq!(sm.state(), ClientState::Refining);
+    }
+
+    #[test]
+    fn propose_plan_flow() {
+        let mut sm = ClientStateMachine::new();
+        let req = sample_request();
+        sm.on_request_sent(&req).unwrap();
+
+        let mut resp = sample_response(Action::Propose);
+        resp.action_metadata = Some(sinp_core::ActionMetadata {
+            plan: Some(sinp_core::Plan {
+                steps: vec![],
+                confidence: 0.8,
+                estimated_cost: 0.3,
+            }),
+            ..Default::default()
+        });
+        let next = sm.on_response_received(resp).unwrap();
+        match next {
+            NextAction::ProposePlan { plan, .. } => assert_eq!(plan.estimated_cost, 0.3),
+            other => panic!("expected ProposePlan, got {:?}", other),
+        }
+        assert_eq!(sm.state(), ClientState::Refining);
+    }
+
+    #[test]
+    fn cancelled_flow() {
+        let mut sm = ClientStateMachine::new();
+        sm.on_request_sent(&sample_request()).unwrap();
+
+        let mut resp = sample_response(Action::Refuse);
+        resp.action_metadata = Some(sinp_core::ActionMetadata {
+            reason_code: Some(RefusalCode::Cancelled),
+            ..Default::default()
+        });
+        let next = sm.on_response_received(resp).unwrap();
+        assert!(matches!(next, NextAction::Refused { .. }));
+        assert_eq!(sm.state(), ClientState::Abandoned);
+    }
 }
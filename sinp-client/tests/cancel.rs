@@ -0,0 +1,102 @@
+//! Cancellation against a scripted server.
+
+mod common;
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use sinp_client::{NextAction, SinpClient};
+use sinp_core::{
+    Action, ActionMetadata, CancelAck, ClientFrame, ClientState, Feature, Hello, Progress,
+    RefusalCode, Request, ServerFrame,
+};
+
+/// Serve connections. Requests are either answered with a clarification
+/// or, while "running", wait for the client's CANCEL.
+async fn serve() -> SocketAddr {
+    let server = Hello::default().with_features([Feature::Streaming]);
+    let mut running: Option<Request> = None;
+    common::serve(server, move |frame| match frame {
+        ClientFrame::Request(request) if request.intent.starts_with("run") => {
+            let progress = Progress {
+                in_response_to: request.message_id,
+                sequence: 0,
+                fraction: Some(0.0),
+                message: Some("started".to_string()),
+                partial: None,
+            };
+            running = Some(*request);
+            vec![ServerFrame::Progress(progress)]
+        }
+        ClientFrame::Request(request) => {
+            let response = common::respond(&request, Action::Clarify);
+            vec![ServerFrame::Response(Box::new(response))]
+        }
+        ClientFrame::Cancel(cancel) => match running.take() {
+            Some(request) => {
+                assert_eq!(cancel.conversation_id, request.conversation_id);
+                let mut response = common::respond(&request, Action::Refuse);
+                response.action_metadata = Some(ActionMetadata {
+                    reason: Some("Request cancelled by client".to_string()),
+                    reason_code: Some(RefusalCode::Cancelled),
+                    ..Default::default()
+                });
+                let ack = CancelAck {
+                    in_response_to: cancel.message_id,
+                    conversation_id: cancel.conversation_id,
+                    interrupted: true,
+                };
+                vec![
+                    ServerFrame::Response(Box::new(response)),
+                    ServerFrame::CancelAck(ack),
+                ]
+            }
+            None => {
+                assert_eq!(cancel.reason.as_deref(), Some("changed my mind"));
+                let ack = CancelAck {
+                    in_response_to: cancel.message_id,
+                    conversation_id: cancel.conversation_id,
+                    interrupted: false,
+                };
+                vec![ServerFrame::CancelAck(ack)]
+            }
+        },
+        other => unreachable!("unexpected frame {:?}", other),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn cancel_running_request() {
+    let addr = serve().await;
+    let mut client = SinpClient::connect(addr.to_string()).await.unwrap();
+
+    let next = client
+        .send_intent_until(
+            "run export",
+            0.9,
+            tokio::time::sleep(Duration::from_millis(20)),
+        )
+        .await
+        .unwrap();
+    let NextAction::Refused { response, .. } = next else {
+        panic!("expected a refusal, got {:?}", next);
+    };
+    assert_eq!(
+        response.action_metadata.unwrap().reason_code,
+        Some(RefusalCode::Cancelled)
+    );
+    assert_eq!(client.state(), ClientState::Abandoned);
+}
+
+#[tokio::test]
+async fn cancel_between_responses() {
+    let addr = serve().await;
+    let mut client = SinpClient::connect(addr.to_string()).await.unwrap();
+    assert!(client.cancel("changed my mind").await.is_err());
+
+    let next = client.send_intent("book a table", 0.9).await.unwrap();
+    assert!(matches!(next, NextAction::Clarify { .. }), "{:?}", next);
+    client.cancel("changed my mind").await.unwrap();
+    assert_eq!(client.state(), ClientState::Abandoned);
+}
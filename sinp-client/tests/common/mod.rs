@@ -0,0 +1,81 @@
+//! Scripted server shared by the integration tests.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use sinp_core::{
+    Action, ClientFrame, Hello, Interpretation, Request, Responder, Response, ServerFrame,
+};
+
+/// Read a client frame, or `None` once the client disconnects.
+pub async fn read(stream: &mut TcpStream) -> Option<ClientFrame> {
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf).await.ok()?;
+    let mut buf = vec![0u8; u32::from_be_bytes(len_buf) as usize];
+    stream.read_exact(&mut buf).await.unwrap();
+    Some(serde_json::from_slice(&buf).unwrap())
+}
+
+/// Write a server frame.
+pub async fn write(stream: &mut TcpStream, frame: &ServerFrame) {
+    let json = serde_json::to_vec(frame).unwrap();
+    stream
+        .write_all(&(json.len() as u32).to_be_bytes())
+        .await
+        .unwrap();
+    stream.write_all(&json).await.unwrap();
+}
+
+/// Answer `request` with `action` at full confidence.
+pub fn respond(request: &Request, action: Action) -> Response {
+    Response::to_request(
+        request,
+        Responder {
+            id: "scripted-server".to_string(),
+            capabilities: vec![],
+            catalog_version: None,
+        },
+        Interpretation {
+            text: request.intent.clone(),
+            confidence: 1.0,
+        },
+        action,
+        1.0,
+    )
+}
+
+/// Serve connections one after another.
+///
+/// HELLO is negotiated against `server`; every other frame is answered with
+/// the frames `script` returns for it. Script state carries over between
+/// connections.
+pub async fn serve<F>(server: Hello, mut script: F) -> SocketAddr
+where
+    F: FnMut(ClientFrame) -> Vec<ServerFrame> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            while let Some(frame) = read(&mut stream).await {
+                let replies = match frame {
+                    ClientFrame::Hello(hello) => {
+                        vec![ServerFrame::HelloAck(server.negotiate(&hello).unwrap())]
+                    }
+                    frame => script(frame),
+                };
+                for reply in &replies {
+                    write(&mut stream, reply).await;
+                }
+            }
+        }
+    });
+
+    addr
+}
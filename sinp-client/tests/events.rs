@@ -0,0 +1,89 @@
+//! Event subscriptions against a scripted server.
+
+mod common;
+
+use std::net::SocketAddr;
+
+use tokio::sync::mpsc;
+
+use sinp_client::{NextAction, SinpClient};
+use sinp_core::{
+    Action, ClientFrame, Event, EventKind, Hello, Job, JobStatus, ServerFrame, ServerState,
+    Subscribed,
+};
+
+/// Serve connections, pushing a job completion and a conversation update
+/// ahead of each response.
+async fn serve() -> SocketAddr {
+    common::serve(Hello::default(), |frame| match frame {
+        ClientFrame::Subscribe(subscribe) => {
+            let subscribed = Subscribed {
+                in_response_to: subscribe.message_id,
+                events: subscribe.events,
+            };
+            vec![ServerFrame::Subscribed(subscribed)]
+        }
+        ClientFrame::Request(request) => {
+            let job = Job {
+                id: uuid::Uuid::new_v4(),
+                conversation_id: uuid::Uuid::new_v4(),
+                sender_id: request.sender.id.clone(),
+                capability_id: "report:v1".to_string(),
+                intent: "export report".to_string(),
+                status: JobStatus::Succeeded,
+                submitted_at: chrono::Utc::now(),
+                completed_at: Some(chrono::Utc::now()),
+                expires_at: None,
+                result: Some(serde_json::json!({ "rows": 42 })),
+                error: None,
+            };
+            let completed = Event::JobCompleted { job: Box::new(job) };
+            let updated = Event::ConversationUpdated {
+                conversation_id: request.conversation_id,
+                state: ServerState::Done,
+            };
+            let response = common::respond(&request, Action::Execute);
+            vec![
+                ServerFrame::Event(completed),
+                ServerFrame::Event(updated),
+                ServerFrame::Response(Box::new(response)),
+            ]
+        }
+        other => unreachable!("unexpected frame {:?}", other),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn events_reach_handlers_apart_from_responses() {
+    let addr = serve().await;
+    let mut client = SinpClient::connect(addr.to_string()).await.unwrap();
+
+    let (completed, mut completions) = mpsc::unbounded_channel();
+    client
+        .subscribe(EventKind::JobCompleted, move |event| {
+            completed.send(event.clone()).unwrap();
+        })
+        .await
+        .unwrap();
+    let (updated, mut updates) = mpsc::unbounded_channel();
+    client
+        .subscribe(EventKind::ConversationUpdated, move |event| {
+            updated.send(event.clone()).unwrap();
+        })
+        .await
+        .unwrap();
+
+    let next = client.send_intent("echo hello", 0.9).await.unwrap();
+    assert!(matches!(next, NextAction::Done(_)), "{:?}", next);
+
+    let Event::JobCompleted { job } = completions.recv().await.unwrap() else {
+        panic!("expected a job completion");
+    };
+    assert_eq!(job.result, Some(serde_json::json!({ "rows": 42 })));
+    let Event::ConversationUpdated { state, .. } = updates.recv().await.unwrap() else {
+        panic!("expected a conversation update");
+    };
+    assert_eq!(state, ServerState::Done);
+    assert!(completions.try_recv().is_err());
+}
@@ -0,0 +1,84 @@
+//! Background jobs against a scripted server.
+
+mod common;
+
+use std::net::SocketAddr;
+
+use sinp_client::{NextAction, SinpClient};
+use sinp_core::{Action, ActionMetadata, ClientFrame, Hello, Job, JobList, JobStatus, ServerFrame};
+
+/// Serve connections that accept background requests as a job, which has
+/// completed by the time it is queried.
+async fn serve() -> SocketAddr {
+    let mut job: Option<Job> = None;
+    common::serve(Hello::default(), move |frame| match frame {
+        ClientFrame::Request(request) => {
+            assert!(request.background);
+            let accepted = Job {
+                id: uuid::Uuid::new_v4(),
+                conversation_id: request.conversation_id,
+                sender_id: request.sender.id.clone(),
+                capability_id: "report:v1".to_string(),
+                intent: request.intent.clone(),
+                status: JobStatus::Running,
+                submitted_at: chrono::Utc::now(),
+                completed_at: None,
+                expires_at: None,
+                result: None,
+                error: None,
+            };
+            let mut response = common::respond(&request, Action::Execute);
+            response.action_metadata = Some(ActionMetadata {
+                job: Some(accepted.clone()),
+                ..Default::default()
+            });
+            job = Some(accepted);
+            vec![ServerFrame::Response(Box::new(response))]
+        }
+        ClientFrame::Jobs(query) => {
+            let mut done = job.clone().unwrap();
+            done.status = JobStatus::Succeeded;
+            done.result = Some(serde_json::json!({ "rows": 42 }));
+            if let Some(id) = query.job_id {
+                assert_eq!(id, done.id);
+            }
+            let list = JobList {
+                in_response_to: query.message_id,
+                jobs: vec![done],
+            };
+            vec![ServerFrame::Jobs(list)]
+        }
+        other => unreachable!("unexpected frame {:?}", other),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn result_fetched_on_a_later_connection() {
+    let addr = serve().await;
+    let mut client = SinpClient::connect(addr.to_string()).await.unwrap();
+    let sender = sinp_core::Sender {
+        id: "client_1".to_string(),
+        auth_method: sinp_core::message::AuthMethod::None,
+    };
+    client = client.with_sender(sender.clone());
+
+    let next = client
+        .send_intent_in_background("export report", 0.9)
+        .await
+        .unwrap();
+    let NextAction::Accepted { job, .. } = next else {
+        panic!("expected an accepted job, got {:?}", next);
+    };
+    assert_eq!(job.status, JobStatus::Running);
+    drop(client);
+
+    let mut client = SinpClient::connect(addr.to_string())
+        .await
+        .unwrap()
+        .with_sender(sender);
+    let done = client.wait_for_job(job.id).await.unwrap();
+    assert_eq!(done.status, JobStatus::Succeeded);
+    assert_eq!(done.result, Some(serde_json::json!({ "rows": 42 })));
+    assert_eq!(client.jobs().await.unwrap(), vec![done]);
+}
@@ -0,0 +1,82 @@
+//! Scheduled executions against a scripted server.
+
+mod common;
+
+use std::net::SocketAddr;
+
+use sinp_client::{NextAction, SinpClient};
+use sinp_core::{
+    Action, ActionMetadata, ClientFrame, Hello, Schedule, ScheduleList, ScheduledExecution,
+    ServerFrame,
+};
+
+/// Serve connections that schedule requests and list or cancel them.
+async fn serve() -> SocketAddr {
+    let mut scheduled: Vec<ScheduledExecution> = Vec::new();
+    common::serve(Hello::default(), move |frame| match frame {
+        ClientFrame::Request(request) => {
+            let Some(Schedule::At { at }) = request.schedule.clone() else {
+                panic!("expected a one-off schedule");
+            };
+            let stored = ScheduledExecution {
+                id: uuid::Uuid::new_v4(),
+                conversation_id: request.conversation_id,
+                sender_id: request.sender.id.clone(),
+                capability_id: "report:v1".to_string(),
+                intent: request.intent.clone(),
+                schedule: Schedule::At { at },
+                created_at: chrono::Utc::now(),
+                next_run: at,
+                last_run: None,
+                last_job: None,
+                runs: 0,
+            };
+            let mut response = common::respond(&request, Action::Execute);
+            response.action_metadata = Some(ActionMetadata {
+                scheduled: Some(stored.clone()),
+                ..Default::default()
+            });
+            scheduled.push(stored);
+            vec![ServerFrame::Response(Box::new(response))]
+        }
+        ClientFrame::Schedules(query) => {
+            let schedules = match query.cancel {
+                Some(id) => {
+                    let position = scheduled.iter().position(|s| s.id == id).unwrap();
+                    vec![scheduled.remove(position)]
+                }
+                None => scheduled.clone(),
+            };
+            let list = ScheduleList {
+                in_response_to: query.message_id,
+                schedules,
+            };
+            vec![ServerFrame::Schedules(list)]
+        }
+        other => unreachable!("unexpected frame {:?}", other),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn schedule_list_and_cancel() {
+    let addr = serve().await;
+    let mut client = SinpClient::connect(addr.to_string()).await.unwrap();
+
+    let at = chrono::Utc::now() + chrono::Duration::hours(12);
+    let next = client
+        .send_intent_scheduled("export report", 0.9, Schedule::At { at })
+        .await
+        .unwrap();
+    let NextAction::Scheduled { scheduled, .. } = next else {
+        panic!("expected a scheduled execution, got {:?}", next);
+    };
+    assert_eq!(scheduled.next_run, at);
+
+    assert_eq!(client.scheduled().await.unwrap(), vec![scheduled.clone()]);
+    assert_eq!(
+        client.cancel_scheduled(scheduled.id).await.unwrap(),
+        scheduled
+    );
+    assert!(client.scheduled().await.unwrap().is_empty());
+}
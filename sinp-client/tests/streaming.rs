@@ -0,0 +1,106 @@
+//! Progress streaming against a scripted server.
+
+mod common;
+
+use std::net::SocketAddr;
+
+use futures_util::StreamExt;
+
+use sinp_client::{Connection, ConnectionConfig, NextAction, SinpClient, StreamEvent};
+use sinp_core::message::AuthMethod;
+use sinp_core::{
+    Action, ClientFrame, Context, ContextType, Feature, Hello, Progress, Request, Sender,
+    ServerFrame,
+};
+
+/// Serve connections, reporting `updates` progress frames before each
+/// response.
+async fn serve(updates: u64) -> SocketAddr {
+    let server = Hello::default().with_features([Feature::Streaming]);
+    common::serve(server, move |frame| match frame {
+        ClientFrame::Request(request) => {
+            let mut frames: Vec<_> = (0..updates)
+                .map(|sequence| {
+                    ServerFrame::Progress(Progress {
+                        in_response_to: request.message_id,
+                        sequence,
+                        fraction: Some((sequence + 1) as f64 / updates as f64),
+                        message: None,
+                        partial: None,
+                    })
+                })
+                .collect();
+            let response = common::respond(&request, Action::Execute);
+            frames.push(ServerFrame::Response(Box::new(response)));
+            frames
+        }
+        other => unreachable!("unexpected frame {:?}", other),
+    })
+    .await
+}
+
+fn request() -> Request {
+    Request::new(
+        Sender {
+            id: "client_1".to_string(),
+            auth_method: AuthMethod::None,
+        },
+        "export report",
+        0.9,
+        Context {
+            context_type: ContextType::Transcript,
+            content: String::new(),
+            semantic_hash: String::new(),
+        },
+    )
+}
+
+#[tokio::test]
+async fn progress_then_response() {
+    let addr = serve(3).await;
+    let mut connection = Connection::connect(&ConnectionConfig::plaintext(addr))
+        .await
+        .unwrap();
+    assert!(connection.session().supports(Feature::Streaming));
+
+    let request = request();
+    let events: Vec<StreamEvent> = connection
+        .send_request_streaming(&request)
+        .await
+        .unwrap()
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(events.len(), 4);
+    for (sequence, event) in events[..3].iter().enumerate() {
+        let StreamEvent::Progress(progress) = event else {
+            panic!("expected progress, got {:?}", event);
+        };
+        assert_eq!(progress.in_response_to, request.message_id);
+        assert_eq!(progress.sequence, sequence as u64);
+    }
+    let StreamEvent::Response(ref response) = events[3] else {
+        panic!("expected response, got {:?}", events[3]);
+    };
+    assert_eq!(response.in_response_to, request.message_id);
+
+    // Callers that only want the response never see progress
+    let response = connection.send_request(&request).await.unwrap();
+    assert_eq!(response.action, Action::Execute);
+}
+
+#[tokio::test]
+async fn client_reports_progress() {
+    let addr = serve(2).await;
+    let mut client = SinpClient::connect(addr.to_string()).await.unwrap();
+
+    let mut fractions = Vec::new();
+    let next = client
+        .send_intent_with_progress("export report", 0.9, |progress| {
+            fractions.push(progress.fraction.unwrap())
+        })
+        .await
+        .unwrap();
+    assert!(matches!(next, NextAction::Done(_)), "{:?}", next);
+    assert_eq!(fractions, vec![0.5, 1.0]);
+}
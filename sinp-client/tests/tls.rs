@@ -0,0 +1,310 @@
+//! TLS trust store integration tests against a locally generated CA.
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::WebPkiClientVerifier;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+use sinp_client::{spki_sha256, ConnectionConfig, NextAction, SinpClient};
+use sinp_core::message::AuthMethod;
+use sinp_core::{Action, Interpretation, Request, Responder, Response, Sender, SinpError};
+
+struct TestPki {
+    ca_file: PathBuf,
+    ca_cert: CertificateDer<'static>,
+    server_cert: CertificateDer<'static>,
+    server_key: PrivateKeyDer<'static>,
+    client_cert_file: PathBuf,
+    client_key_file: PathBuf,
+}
+
+impl Drop for TestPki {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.ca_file);
+        let _ = std::fs::remove_file(&self.client_cert_file);
+        let _ = std::fs::remove_file(&self.client_key_file);
+    }
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sinp-test-{}-{}.pem", name, uuid::Uuid::new_v4()))
+}
+
+/// Generate a CA, a `localhost` server certificate and a `client_1` client
+/// certificate signed by it.
+fn generate_pki() -> TestPki {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(
+        DnType::CommonName,
+        format!("SINP Test CA {}", uuid::Uuid::new_v4()),
+    );
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let server_params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    let server_cert = server_params
+        .signed_by(&server_key, &ca_cert, &ca_key)
+        .unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "client_1");
+    let client_cert = client_params
+        .signed_by(&client_key, &ca_cert, &ca_key)
+        .unwrap();
+
+    let ca_file = temp_file("ca");
+    std::fs::write(&ca_file, ca_cert.pem()).unwrap();
+    let client_cert_file = temp_file("client-cert");
+    std::fs::write(&client_cert_file, client_cert.pem()).unwrap();
+    let client_key_file = temp_file("client-key");
+    std::fs::write(&client_key_file, client_key.serialize_pem()).unwrap();
+
+    TestPki {
+        ca_file,
+        ca_cert: ca_cert.der().clone(),
+        server_cert: server_cert.der().clone(),
+        server_key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(server_key.serialize_der())),
+        client_cert_file,
+        client_key_file,
+    }
+}
+
+/// Start a TLS server answering every request with EXECUTE.
+async fn start_server(pki: &TestPki) -> SocketAddr {
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![pki.server_cert.clone()], pki.server_key.clone_key())
+        .unwrap();
+    serve(TlsAcceptor::from(Arc::new(config))).await
+}
+
+/// Start a TLS server that requires a client certificate from the test CA.
+async fn start_mtls_server(pki: &TestPki) -> SocketAddr {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(pki.ca_cert.clone()).unwrap();
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .build()
+        .unwrap();
+    let config = rustls::ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(vec![pki.server_cert.clone()], pki.server_key.clone_key())
+        .unwrap();
+    serve(TlsAcceptor::from(Arc::new(config))).await
+}
+
+async fn serve(acceptor: TlsAcceptor) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let mut len_buf = [0u8; 4];
+                while stream.read_exact(&mut len_buf).await.is_ok() {
+                    let mut buf = vec![0u8; u32::from_be_bytes(len_buf) as usize];
+                    stream.read_exact(&mut buf).await.unwrap();
+                    let request: Request = serde_json::from_slice(&buf).unwrap();
+
+                    let response = Response::to_request(
+                        &request,
+                        Responder {
+                            id: "tls-test".to_string(),
+                            capabilities: vec![],
+                            catalog_version: None,
+                        },
+                        Interpretation {
+                            text: request.intent.clone(),
+                            confidence: 1.0,
+                        },
+                        Action::Execute,
+                        1.0,
+                    );
+                    let json = serde_json::to_vec(&response).unwrap();
+                    stream
+                        .write_all(&(json.len() as u32).to_be_bytes())
+                        .await
+                        .unwrap();
+                    stream.write_all(&json).await.unwrap();
+                    stream.flush().await.unwrap();
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+/// The test servers speak the legacy framing.
+fn config(addr: SocketAddr) -> ConnectionConfig {
+    ConnectionConfig::tls(addr, "localhost")
+        .with_system_roots(false)
+        .with_legacy_framing()
+}
+
+async fn connect_error(config: ConnectionConfig) -> SinpError {
+    match SinpClient::connect_with_config(config).await {
+        Ok(_) => panic!("connection should have failed"),
+        Err(e) => e,
+    }
+}
+
+#[tokio::test]
+async fn trusted_ca_file() {
+    let pki = generate_pki();
+    let addr = start_server(&pki).await;
+
+    let mut client = SinpClient::connect_with_config(config(addr).with_ca_file(&pki.ca_file))
+        .await
+        .unwrap();
+    let next = client.send_intent("hello over tls", 0.9).await.unwrap();
+    assert!(matches!(next, NextAction::Done(_)));
+}
+
+#[tokio::test]
+async fn untrusted_ca_is_rejected() {
+    let pki = generate_pki();
+    let other = generate_pki();
+    let addr = start_server(&pki).await;
+
+    let err = connect_error(config(addr).with_ca_file(&other.ca_file)).await;
+    match err {
+        SinpError::CertificateVerification(msg) => assert!(msg.contains("trusted CA"), "{}", msg),
+        other => panic!("unexpected error: {}", other),
+    }
+
+    // The system roots do not contain the test CA either (if there are any)
+    let err = connect_error(ConnectionConfig::tls(addr, "localhost")).await;
+    assert!(
+        matches!(
+            err,
+            SinpError::CertificateVerification(_) | SinpError::Transport(_)
+        ),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn wrong_server_name_is_rejected() {
+    let pki = generate_pki();
+    let addr = start_server(&pki).await;
+
+    let config = ConnectionConfig::tls(addr, "sinp.example.com")
+        .with_system_roots(false)
+        .with_ca_file(&pki.ca_file);
+    match connect_error(config).await {
+        SinpError::CertificateVerification(msg) => {
+            assert!(msg.contains("sinp.example.com"), "{}", msg)
+        }
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn spki_pinning() {
+    let pki = generate_pki();
+    let other = generate_pki();
+    let addr = start_server(&pki).await;
+    let pin = spki_sha256(&pki.server_cert).unwrap();
+    let wrong_pin = spki_sha256(&other.server_cert).unwrap();
+
+    // Pin alone authenticates the server
+    let mut client = SinpClient::connect_with_config(config(addr).with_pinned_spki(&pin))
+        .await
+        .unwrap();
+    let next = client.send_intent("pinned", 0.9).await.unwrap();
+    assert!(matches!(next, NextAction::Done(_)));
+
+    // Pin and CA must both match
+    SinpClient::connect_with_config(
+        config(addr)
+            .with_ca_file(&pki.ca_file)
+            .with_pinned_spki(&pin),
+    )
+    .await
+    .unwrap();
+
+    let err = connect_error(
+        config(addr)
+            .with_ca_file(&pki.ca_file)
+            .with_pinned_spki(&wrong_pin),
+    )
+    .await;
+    match err {
+        SinpError::CertificateVerification(msg) => assert!(msg.contains("pinned"), "{}", msg),
+        other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn invalid_trust_configuration() {
+    let pki = generate_pki();
+    let addr = start_server(&pki).await;
+
+    let err = connect_error(config(addr)).await;
+    assert!(
+        matches!(err, SinpError::Transport(ref msg) if msg.contains("No trusted")),
+        "{}",
+        err
+    );
+
+    let err = connect_error(config(addr).with_pinned_spki("not-a-pin")).await;
+    assert!(
+        matches!(err, SinpError::Transport(ref msg) if msg.contains("Invalid SPKI pin")),
+        "{}",
+        err
+    );
+
+    let err = connect_error(config(addr).with_ca_file("/nonexistent/ca.pem")).await;
+    assert!(
+        matches!(err, SinpError::Transport(ref msg) if msg.contains("CA file")),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn client_certificate() {
+    let pki = generate_pki();
+    let addr = start_mtls_server(&pki).await;
+
+    let mut client = SinpClient::connect_with_config(
+        config(addr)
+            .with_ca_file(&pki.ca_file)
+            .with_client_cert(&pki.client_cert_file, &pki.client_key_file),
+    )
+    .await
+    .unwrap()
+    .with_sender(Sender {
+        id: "client_1".to_string(),
+        auth_method: AuthMethod::Certificate,
+    });
+    let next = client.send_intent("mutual tls", 0.9).await.unwrap();
+    assert!(matches!(next, NextAction::Done(_)));
+
+    // Without a client certificate the server aborts the handshake; TLS 1.3
+    // clients only notice on the first exchange
+    let result = async {
+        let mut client =
+            SinpClient::connect_with_config(config(addr).with_ca_file(&pki.ca_file)).await?;
+        client.send_intent("no cert", 0.9).await
+    }
+    .await;
+    assert!(result.is_err());
+}
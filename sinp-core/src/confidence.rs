@@ -4,10 +4,12 @@
 //! - Confidence derivation: Φ_s = min(1, ρ · R(c) · A(res)) · P(pol)
 //! - Decision boundary: δ(Φ_s, Φ_c) → Action
 
-use crate::message::Action;
+use serde::{Deserialize, Serialize};
+
+use crate::message::{Action, Capability};
 
 /// Decision thresholds as defined in RFC.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Thresholds {
     /// Threshold for EXECUTE action (default 0.85).
     pub tau_exec: f64,
@@ -36,6 +38,35 @@ impl Thresholds {
             tau_accept,
         }
     }
+
+    /// Derive thresholds for a specific capability.
+    ///
+    /// Sensitive or expensive capabilities get a stricter τ_exec (and τ_accept
+    /// for sensitive ones) than the base thresholds:
+    /// - `private`: +0.05, `pii_sensitive`: +0.10
+    /// - `cost_units` ≥ 1.0: +0.02, ≥ 5.0: +0.05
+    ///
+    /// Thresholds are capped at 0.99 so execution remains reachable.
+    pub fn for_capability(&self, capability: &Capability) -> Self {
+        let privacy_margin = match capability.privacy_level.as_str() {
+            "pii_sensitive" => 0.10,
+            "private" => 0.05,
+            _ => 0.0,
+        };
+        let cost_margin = if capability.cost_units >= 5.0 {
+            0.05
+        } else if capability.cost_units >= 1.0 {
+            0.02
+        } else {
+            0.0
+        };
+
+        Self {
+            tau_exec: (self.tau_exec + privacy_margin + cost_margin).min(0.99),
+            tau_clarify: self.tau_clarify,
+            tau_accept: (self.tau_accept + privacy_margin).min(0.99),
+        }
+    }
 }
 
 /// Compute server confidence score.
@@ -59,12 +90,100 @@ pub fn compute_server_confidence(
     if !policy_passed {
         return 0.0;
     }
-    (rho * reliability * availability).min(1.0).max(0.0)
+    (rho * reliability * availability).clamp(0.0, 1.0)
+}
+
+/// The rule of the decision boundary that produced an action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecisionRule {
+    /// Request was malformed (REFUSE).
+    Malformed,
+    /// Request violates policy (REFUSE).
+    PolicyViolation,
+    /// Φ_s ≥ τ_exec and Φ_c ≥ τ_accept (EXECUTE).
+    Execute,
+    /// Φ_s ≥ τ_clarify and a better alternative exists (PROPOSE).
+    BetterAlternative,
+    /// Φ_s ≥ τ_exec but Φ_c < τ_accept (CLARIFY asking the client to confirm).
+    Confirm,
+    /// Φ_s ≥ τ_clarify without meeting the execution rule (CLARIFY).
+    Clarify,
+    /// Φ_s < τ_clarify (REFUSE).
+    BelowClarify,
+    /// The client answered yes to a confirmation or a proposed plan
+    /// (EXECUTE).
+    Confirmed,
+    /// A compound intent matched a plan whose every step has Φ_s ≥ τ_clarify
+    /// (PROPOSE).
+    Plan,
+}
+
+impl DecisionRule {
+    /// Action produced by this rule.
+    pub fn action(&self) -> Action {
+        match self {
+            Self::Malformed | Self::PolicyViolation | Self::BelowClarify => Action::Refuse,
+            Self::Execute | Self::Confirmed => Action::Execute,
+            Self::BetterAlternative | Self::Plan => Action::Propose,
+            Self::Confirm | Self::Clarify => Action::Clarify,
+        }
+    }
+}
+
+/// Decide which rule of the decision boundary applies.
+///
+/// Same as [`decide_action`], but reports the rule that fired.
+pub fn decision_rule(
+    phi_s: f64,
+    phi_c: f64,
+    thresholds: &Thresholds,
+    has_better_alternative: bool,
+    policy_violated: bool,
+    malformed: bool,
+) -> DecisionRule {
+    // REFUSE takes precedence
+    if malformed {
+        return DecisionRule::Malformed;
+    }
+    if policy_violated {
+        return DecisionRule::PolicyViolation;
+    }
+
+    // EXECUTE if both confidences meet thresholds
+    if phi_s >= thresholds.tau_exec && phi_c >= thresholds.tau_accept {
+        return DecisionRule::Execute;
+    }
+
+    // PROPOSE if a better alternative exists, unless nothing matches well
+    // enough to propose
+    if has_better_alternative && phi_s >= thresholds.tau_clarify {
+        return DecisionRule::BetterAlternative;
+    }
+
+    // CLARIFY by confirmation if only the client is unsure
+    if phi_s >= thresholds.tau_exec {
+        return DecisionRule::Confirm;
+    }
+
+    // CLARIFY while Φ_s is at least τ_clarify
+    if phi_s >= thresholds.tau_clarify {
+        return DecisionRule::Clarify;
+    }
+
+    // REFUSE below τ_clarify: no capability matches well enough
+    DecisionRule::BelowClarify
 }
 
 /// Decide action based on confidence scores.
 ///
-/// Implements the decision boundary δ(Φ_s, Φ_c) from RFC Section 4.3.
+/// Implements the decision boundary δ(Φ_s, Φ_c) from RFC Section 4.3:
+/// - REFUSE if the request is malformed or violates policy
+/// - EXECUTE if Φ_s ≥ τ_exec and Φ_c ≥ τ_accept
+/// - PROPOSE if Φ_s ≥ τ_clarify and a better alternative exists
+/// - CLARIFY (confirmation) if Φ_s ≥ τ_exec but Φ_c < τ_accept
+/// - CLARIFY if Φ_s ≥ τ_clarify
+/// - REFUSE otherwise (capability missing)
 ///
 /// # Arguments
 /// * `phi_s` - Server confidence
@@ -84,28 +203,15 @@ pub fn decide_action(
     policy_violated: bool,
     malformed: bool,
 ) -> Action {
-    // REFUSE takes precedence
-    if policy_violated || malformed {
-        return Action::Refuse;
-    }
-
-    // EXECUTE if both confidences meet thresholds
-    if phi_s >= thresholds.tau_exec && phi_c >= thresholds.tau_accept {
-        return Action::Execute;
-    }
-
-    // PROPOSE if a better alternative exists
-    if has_better_alternative {
-        return Action::Propose;
-    }
-
-    // CLARIFY if confidence is below execution threshold
-    if phi_s < thresholds.tau_exec {
-        return Action::Clarify;
-    }
-
-    // Default to CLARIFY for edge cases
-    Action::Clarify
+    decision_rule(
+        phi_s,
+        phi_c,
+        thresholds,
+        has_better_alternative,
+        policy_violated,
+        malformed,
+    )
+    .action()
 }
 
 /// Simplified decision function for common cases.
@@ -150,6 +256,94 @@ mod tests {
         assert_eq!(action, Action::Clarify);
     }
 
+    #[test]
+    fn decision_refuse_below_clarify() {
+        let thresholds = Thresholds::default();
+        let action = decide_action(0.10, 0.95, &thresholds, false, false, false);
+        assert_eq!(action, Action::Refuse);
+    }
+
+    #[test]
+    fn decision_boundary_table() {
+        let thresholds = Thresholds::default();
+        // (Φ_s, Φ_c, expected)
+        let cases = [
+            // Φ_s ≥ τ_exec
+            (1.00, 1.00, Action::Execute),
+            (0.90, 0.85, Action::Execute),
+            (0.85, 0.50, Action::Execute),
+            (0.85, 0.49, Action::Clarify),
+            (0.95, 0.10, Action::Clarify),
+            (0.95, 0.00, Action::Clarify),
+            // τ_clarify ≤ Φ_s < τ_exec
+            (0.84, 1.00, Action::Clarify),
+            (0.70, 0.85, Action::Clarify),
+            (0.50, 0.50, Action::Clarify),
+            (0.50, 0.00, Action::Clarify),
+            // Φ_s < τ_clarify
+            (0.49, 1.00, Action::Refuse),
+            (0.20, 0.85, Action::Refuse),
+            (0.00, 0.50, Action::Refuse),
+            (0.00, 0.00, Action::Refuse),
+        ];
+
+        for (phi_s, phi_c, expected) in cases {
+            let action = decide_action(phi_s, phi_c, &thresholds, false, false, false);
+            assert_eq!(action, expected, "Φ_s = {}, Φ_c = {}", phi_s, phi_c);
+        }
+    }
+
+    #[test]
+    fn decision_boundary_table_with_alternative() {
+        let thresholds = Thresholds::default();
+        let cases = [
+            (0.90, 0.85, Action::Execute),
+            (0.90, 0.20, Action::Propose),
+            (0.70, 0.85, Action::Propose),
+            (0.50, 0.85, Action::Propose),
+            // Φ_s < τ_clarify
+            (0.49, 0.85, Action::Refuse),
+            (0.10, 0.85, Action::Refuse),
+        ];
+
+        for (phi_s, phi_c, expected) in cases {
+            let action = decide_action(phi_s, phi_c, &thresholds, true, false, false);
+            assert_eq!(action, expected, "Φ_s = {}, Φ_c = {}", phi_s, phi_c);
+        }
+
+        // Policy violations refuse anywhere on the plane
+        for (phi_s, phi_c) in [(1.0, 1.0), (0.7, 0.7), (0.0, 0.0)] {
+            let action = decide_action(phi_s, phi_c, &thresholds, true, true, false);
+            assert_eq!(action, Action::Refuse);
+        }
+    }
+
+    #[test]
+    fn decision_rules() {
+        let thresholds = Thresholds::default();
+        let rule = |phi_s, phi_c, alt, policy, malformed| {
+            decision_rule(phi_s, phi_c, &thresholds, alt, policy, malformed)
+        };
+
+        assert_eq!(rule(0.9, 0.9, false, false, false), DecisionRule::Execute);
+        assert_eq!(rule(0.7, 0.9, true, false, false), DecisionRule::BetterAlternative);
+        assert_eq!(rule(0.3, 0.9, true, false, false), DecisionRule::BelowClarify);
+        assert_eq!(rule(0.9, 0.3, false, false, false), DecisionRule::Confirm);
+        assert_eq!(rule(0.7, 0.9, false, false, false), DecisionRule::Clarify);
+        assert_eq!(rule(0.3, 0.9, false, false, false), DecisionRule::BelowClarify);
+        assert_eq!(rule(0.9, 0.9, false, true, false), DecisionRule::PolicyViolation);
+        assert_eq!(rule(0.9, 0.9, false, true, true), DecisionRule::Malformed);
+
+        assert_eq!(DecisionRule::BelowClarify.action(), Action::Refuse);
+        assert_eq!(DecisionRule::Confirm.action(), Action::Clarify);
+        assert_eq!(DecisionRule::Confirmed.action(), Action::Execute);
+        assert_eq!(DecisionRule::Plan.action(), Action::Propose);
+        assert_eq!(
+            serde_json::to_string(&DecisionRule::BelowClarify).unwrap(),
+            "\"below_clarify\""
+        );
+    }
+
     #[test]
     fn decision_refuse_policy() {
         let thresholds = Thresholds::default();
@@ -171,6 +365,30 @@ mod tests {
         assert_eq!(action, Action::Propose);
     }
 
+    #[test]
+    fn capability_derived_thresholds() {
+        let base = Thresholds::default();
+        let mut cap = Capability {
+            id: "echo:v1".to_string(),
+            description: "Echo".to_string(),
+            inputs: vec![],
+            privacy_level: "public".to_string(),
+            cost_units: 0.1,
+        };
+        assert_eq!(base.for_capability(&cap), base);
+
+        cap.privacy_level = "pii_sensitive".to_string();
+        cap.cost_units = 5.0;
+        let derived = base.for_capability(&cap);
+        assert!((derived.tau_exec - 0.99).abs() < 1e-9);
+        assert!((derived.tau_accept - 0.60).abs() < 1e-9);
+        assert_eq!(derived.tau_clarify, base.tau_clarify);
+
+        // A Φ_s that executes `echo` only clarifies a sensitive capability
+        let action = decide_action(0.90, 0.85, &derived, false, false, false);
+        assert_eq!(action, Action::Clarify);
+    }
+
     #[test]
     fn custom_thresholds() {
         let thresholds = Thresholds::new(0.70, 0.40, 0.40);
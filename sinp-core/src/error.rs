@@ -10,10 +10,21 @@ pub enum RefusalCode {
     MalformedContext,
     /// Request requires PII but privacy constraints forbid.
     PrivacyViolation,
-    /// No capability matches intent with Φ > 0.2.
+    /// No capability matches intent with Φ_s ≥ τ_clarify.
     CapabilityMissing,
     /// Intent understood but forbidden by server rules.
     PolicyViolation,
+    /// Execution required operator approval, which was denied or timed out.
+    ApprovalDenied,
+    /// Sender identity could not be authenticated.
+    AuthenticationFailed,
+    /// Capability handler failed while executing.
+    ExecutionFailed,
+    /// Idempotency key reused for a different request, or while the
+    /// original is still being processed.
+    IdempotencyConflict,
+    /// Cancelled by the client before it completed.
+    Cancelled,
 }
 
 impl std::fmt::Display for RefusalCode {
@@ -23,6 +34,11 @@ impl std::fmt::Display for RefusalCode {
             Self::PrivacyViolation => write!(f, "privacy_violation"),
             Self::CapabilityMissing => write!(f, "capability_missing"),
             Self::PolicyViolation => write!(f, "policy_violation"),
+            Self::ApprovalDenied => write!(f, "approval_denied"),
+            Self::AuthenticationFailed => write!(f, "authentication_failed"),
+            Self::ExecutionFailed => write!(f, "execution_failed"),
+            Self::IdempotencyConflict => write!(f, "idempotency_conflict"),
+            Self::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -38,6 +54,10 @@ pub enum SinpError {
     #[error("validation error: {0}")]
     Validation(String),
 
+    /// Capability handler failed (backend error, timeout, bad output).
+    #[error("execution failed: {0}")]
+    Execution(String),
+
     /// Cryptographic error (signature, hash).
     #[error("crypto error: {0}")]
     Crypto(String),
@@ -46,6 +66,10 @@ pub enum SinpError {
     #[error("transport error: {0}")]
     Transport(String),
 
+    /// TLS peer certificate could not be verified.
+    #[error("certificate verification failed: {0}")]
+    CertificateVerification(String),
+
     /// Serialization/deserialization error.
     #[error("serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
@@ -79,6 +103,8 @@ mod tests {
         assert_eq!(RefusalCode::PrivacyViolation.to_string(), "privacy_violation");
         assert_eq!(RefusalCode::CapabilityMissing.to_string(), "capability_missing");
         assert_eq!(RefusalCode::PolicyViolation.to_string(), "policy_violation");
+        assert_eq!(RefusalCode::ApprovalDenied.to_string(), "approval_denied");
+        assert_eq!(RefusalCode::AuthenticationFailed.to_string(), "authentication_failed");
     }
 
     #[test]
@@ -0,0 +1,398 @@
+//! Connection handshake and framing for SINP.
+//!
+//! A 0.2 connection opens with a HELLO exchange in which client and server
+//! agree on protocol version, encoding, compression and optional features.
+//! Every frame after it is a tagged [`ClientFrame`] or [`ServerFrame`].
+//!
+//! A connection whose first frame is a bare [`Request`] speaks the legacy
+//! 0.1 framing: untagged requests and responses, no negotiation.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::RefusalCode;
+use crate::message::{
+    Cancel, CancelAck, Catalog, DiscoveryRequest, Event, JobList, JobQuery, Progress, Request,
+    Response, ScheduleList, ScheduleQuery, Subscribe, Subscribed,
+};
+
+/// Protocol versions this implementation speaks, preferred first.
+pub const SUPPORTED_VERSIONS: &[&str] = &[crate::PROTOCOL_VERSION, LEGACY_PROTOCOL_VERSION];
+
+/// Version spoken by clients that do not send HELLO.
+pub const LEGACY_PROTOCOL_VERSION: &str = "0.1";
+
+/// Message encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Json,
+    /// Encoding this implementation does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Frame compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    None,
+    /// Compression this implementation does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Optional protocol feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Ed25519 message signatures.
+    Signatures,
+    /// Progress frames before the final response.
+    Streaming,
+    /// Several conversations in flight on one connection.
+    Multiplexing,
+    /// Feature this implementation does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+/// HELLO announcing what one side of a connection supports.
+///
+/// Lists are in order of preference.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub versions: Vec<String>,
+    pub encodings: Vec<Encoding>,
+    pub compression: Vec<Compression>,
+    #[serde(default)]
+    pub features: Vec<Feature>,
+    /// Features the connection cannot do without.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_features: Vec<Feature>,
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self {
+            versions: SUPPORTED_VERSIONS.iter().map(|v| v.to_string()).collect(),
+            encodings: vec![Encoding::Json],
+            compression: vec![Compression::None],
+            features: Vec::new(),
+            required_features: Vec::new(),
+        }
+    }
+}
+
+impl Hello {
+    /// Announce optional features.
+    pub fn with_features(mut self, features: impl IntoIterator<Item = Feature>) -> Self {
+        self.features = features.into_iter().collect();
+        self
+    }
+
+    /// Require features; the handshake fails if the peer lacks any of them.
+    pub fn with_required_features(mut self, features: impl IntoIterator<Item = Feature>) -> Self {
+        self.required_features = features.into_iter().collect();
+        for feature in &self.required_features {
+            if !self.features.contains(feature) {
+                self.features.push(*feature);
+            }
+        }
+        self
+    }
+
+    /// Agree on a session with a client's HELLO (`self` is the server).
+    ///
+    /// The client's preference order wins. Returns the reason on
+    /// incompatibility.
+    pub fn negotiate(&self, client: &Hello) -> Result<Session, String> {
+        let version = client
+            .versions
+            .iter()
+            .find(|v| self.versions.contains(v))
+            .ok_or_else(|| {
+                format!(
+                    "no common protocol version (client: {}, server: {})",
+                    client.versions.join(", "),
+                    self.versions.join(", ")
+                )
+            })?;
+        let encoding = first_common(&client.encodings, &self.encodings, Encoding::Unknown)
+            .ok_or_else(|| "no common encoding".to_string())?;
+        let compression =
+            first_common(&client.compression, &self.compression, Compression::Unknown)
+                .ok_or_else(|| "no common compression".to_string())?;
+
+        for (required, by, other) in [
+            (&client.required_features, "client", &self.features),
+            (&self.required_features, "server", &client.features),
+        ] {
+            if let Some(missing) = required.iter().find(|f| !other.contains(f)) {
+                return Err(format!("{} requires unsupported feature {:?}", by, missing));
+            }
+        }
+
+        let features = client
+            .features
+            .iter()
+            .filter(|f| **f != Feature::Unknown && self.features.contains(f))
+            .copied()
+            .collect();
+
+        Ok(Session {
+            version: version.clone(),
+            encoding,
+            compression,
+            features,
+        })
+    }
+}
+
+fn first_common<T: Copy + PartialEq>(preferred: &[T], supported: &[T], unknown: T) -> Option<T> {
+    preferred
+        .iter()
+        .find(|x| **x != unknown && supported.contains(x))
+        .copied()
+}
+
+/// Parameters agreed for a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub version: String,
+    pub encoding: Encoding,
+    pub compression: Compression,
+    pub features: Vec<Feature>,
+}
+
+impl Session {
+    /// Session of a client that did not send HELLO.
+    pub fn legacy() -> Self {
+        Self {
+            version: LEGACY_PROTOCOL_VERSION.to_string(),
+            encoding: Encoding::Json,
+            compression: Compression::None,
+            features: Vec::new(),
+        }
+    }
+
+    /// Whether frames are untagged 0.1 requests and responses.
+    pub fn is_legacy(&self) -> bool {
+        self.version == LEGACY_PROTOCOL_VERSION
+    }
+
+    /// Whether a feature was agreed.
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+/// Server refusal of a HELLO.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HelloReject {
+    pub reason: String,
+    /// What the server does support.
+    pub supported: Hello,
+}
+
+/// Frame sent by the client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    Hello(Hello),
+    Request(Box<Request>),
+    Discover(DiscoveryRequest),
+    Cancel(Cancel),
+    Jobs(JobQuery),
+    Subscribe(Subscribe),
+    Schedules(ScheduleQuery),
+}
+
+/// Frame sent by the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    HelloAck(Session),
+    HelloReject(HelloReject),
+    Response(Box<Response>),
+    Catalog(Catalog),
+    Progress(Progress),
+    CancelAck(CancelAck),
+    Jobs(JobList),
+    Subscribed(Subscribed),
+    Schedules(ScheduleList),
+    /// Pushed to a subscribed client, unrelated to any request.
+    Event(Event),
+    Error(FrameError),
+}
+
+/// Failure of a frame that has no [`Response`] of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameError {
+    pub in_response_to: uuid::Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<RefusalCode>,
+    pub reason: String,
+}
+
+impl FrameError {
+    /// Report an error in reply to a frame.
+    pub fn new(in_response_to: uuid::Uuid, error: &crate::SinpError) -> Self {
+        match error {
+            crate::SinpError::Refused { code, reason } => Self {
+                in_response_to,
+                code: Some(*code),
+                reason: reason.clone(),
+            },
+            other => Self {
+                in_response_to,
+                code: None,
+                reason: other.to_string(),
+            },
+        }
+    }
+
+    /// Convert to the error it reports.
+    pub fn into_error(self) -> crate::SinpError {
+        match self.code {
+            Some(code) => crate::SinpError::Refused {
+                code,
+                reason: self.reason,
+            },
+            None => crate::SinpError::Protocol(self.reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{AuthMethod, Context, ContextType, Sender};
+
+    #[test]
+    fn negotiate_defaults() {
+        let session = Hello::default().negotiate(&Hello::default()).unwrap();
+        assert_eq!(session.version, crate::PROTOCOL_VERSION);
+        assert_eq!(session.encoding, Encoding::Json);
+        assert_eq!(session.compression, Compression::None);
+        assert!(session.features.is_empty());
+        assert!(!session.is_legacy());
+    }
+
+    #[test]
+    fn negotiate_features() {
+        let server = Hello::default().with_features([Feature::Signatures, Feature::Streaming]);
+        let client = Hello::default().with_features([Feature::Streaming, Feature::Multiplexing]);
+        let session = server.negotiate(&client).unwrap();
+        assert_eq!(session.features, vec![Feature::Streaming]);
+        assert!(session.supports(Feature::Streaming));
+        assert!(!session.supports(Feature::Signatures));
+
+        let client = Hello::default().with_required_features([Feature::Multiplexing]);
+        let reason = server.negotiate(&client).unwrap_err();
+        assert!(reason.contains("Multiplexing"), "{}", reason);
+    }
+
+    #[test]
+    fn incompatible_versions() {
+        let client = Hello {
+            versions: vec!["9.0".to_string()],
+            ..Default::default()
+        };
+        let reason = Hello::default().negotiate(&client).unwrap_err();
+        assert!(reason.contains("no common protocol version"), "{}", reason);
+
+        // Client preference wins
+        let client = Hello {
+            versions: vec!["0.1".to_string(), "0.2".to_string()],
+            ..Default::default()
+        };
+        let session = Hello::default().negotiate(&client).unwrap();
+        assert!(session.is_legacy());
+    }
+
+    #[test]
+    fn unknown_values_are_ignored() {
+        let json = r#"{"type":"hello","versions":["0.3","0.2"],"encodings":["cbor","json"],
+            "compression":["zstd","none"],"features":["telepathy","signatures"]}"#;
+        let ClientFrame::Hello(client) = serde_json::from_str(json).unwrap() else {
+            panic!("expected hello");
+        };
+        let server = Hello::default().with_features([Feature::Signatures]);
+        let session = server.negotiate(&client).unwrap();
+        assert_eq!(session.version, "0.2");
+        assert_eq!(session.features, vec![Feature::Signatures]);
+
+        let client = Hello {
+            encodings: vec![Encoding::Unknown],
+            ..Default::default()
+        };
+        assert!(server.negotiate(&client).is_err());
+    }
+
+    #[test]
+    fn frames_are_tagged() {
+        let request = Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            "hello",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        let json = serde_json::to_string(&ClientFrame::Request(Box::new(request.clone()))).unwrap();
+        assert!(json.contains("\"type\":\"request\""));
+        assert_eq!(
+            serde_json::from_str::<ClientFrame>(&json).unwrap(),
+            ClientFrame::Request(Box::new(request.clone()))
+        );
+
+        // A bare request is not a frame: that is how legacy clients are told apart
+        let bare = serde_json::to_string(&request).unwrap();
+        assert!(serde_json::from_str::<ClientFrame>(&bare).is_err());
+
+        let progress = ServerFrame::Progress(Progress {
+            in_response_to: request.message_id,
+            sequence: 0,
+            fraction: Some(0.5),
+            message: None,
+            partial: None,
+        });
+        let json = serde_json::to_string(&progress).unwrap();
+        assert!(json.contains("\"type\":\"progress\""));
+        assert!(!json.contains("partial"));
+        assert_eq!(
+            serde_json::from_str::<ServerFrame>(&json).unwrap(),
+            progress
+        );
+
+        let cancel =
+            ClientFrame::Cancel(Cancel::new(request.sender.clone(), request.conversation_id));
+        let json = serde_json::to_string(&cancel).unwrap();
+        assert!(json.contains("\"type\":\"cancel\""));
+        assert_eq!(serde_json::from_str::<ClientFrame>(&json).unwrap(), cancel);
+
+        let query = ClientFrame::Jobs(JobQuery::new(request.sender.clone()));
+        let json = serde_json::to_string(&query).unwrap();
+        assert!(json.contains("\"type\":\"jobs\""));
+        assert!(!json.contains("wait"));
+        assert_eq!(serde_json::from_str::<ClientFrame>(&json).unwrap(), query);
+
+        let cancel = ScheduleQuery::cancel(request.sender.clone(), uuid::Uuid::new_v4());
+        let query = ClientFrame::Schedules(cancel);
+        let json = serde_json::to_string(&query).unwrap();
+        assert!(json.contains("\"type\":\"schedules\""));
+        assert_eq!(serde_json::from_str::<ClientFrame>(&json).unwrap(), query);
+
+        let event = ServerFrame::Event(Event::CatalogChanged {
+            version: "v2".to_string(),
+        });
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"type\":\"event\",\"event\":\"catalog_changed\""));
+        assert_eq!(serde_json::from_str::<ServerFrame>(&json).unwrap(), event);
+    }
+}
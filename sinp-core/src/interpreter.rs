@@ -16,6 +16,8 @@ pub struct InterpretationResult {
     pub capability: Option<Capability>,
     /// Raw model probability (ρ).
     pub raw_confidence: f64,
+    /// Inputs of the matched capability extracted from the intent.
+    pub parameters: serde_json::Map<String, serde_json::Value>,
     /// Alternative interpretations.
     pub alternatives: Vec<AlternativeInterpretation>,
 }
@@ -158,18 +160,116 @@ impl Interpreter for KeywordInterpreter {
             })
             .collect();
 
+        let parameters = capability
+            .as_ref()
+            .map(|c| extract_parameters(intent, &c.inputs))
+            .unwrap_or_default();
+
         InterpretationResult {
             interpretation: capability
                 .as_ref()
                 .map(|c| format!("Execute {} for: {}", c.id, intent))
                 .unwrap_or_else(|| "No matching capability found".to_string()),
             capability,
+            parameters,
             raw_confidence: best_score,
             alternatives,
         }
     }
 }
 
+/// Extract capability inputs from an intent.
+///
+/// Recognizes `input=value` and `input: value`, where the value is either a
+/// double-quoted string or a single word. Input names match case-insensitively.
+pub fn extract_parameters(
+    intent: &str,
+    inputs: &[String],
+) -> serde_json::Map<String, serde_json::Value> {
+    // ASCII lowercasing keeps byte offsets valid for slicing `intent`
+    let lower = intent.to_ascii_lowercase();
+    let mut parameters = serde_json::Map::new();
+
+    for input in inputs {
+        let key = input.to_ascii_lowercase();
+        if key.is_empty() {
+            continue;
+        }
+
+        let mut search_from = 0;
+        while let Some(pos) = lower[search_from..].find(&key) {
+            let start = search_from + pos;
+            let end = start + key.len();
+            search_from = end;
+
+            // Must start at a word boundary
+            if lower[..start]
+                .chars()
+                .last()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            {
+                continue;
+            }
+
+            let rest = intent[end..].trim_start();
+            let Some(rest) = rest.strip_prefix('=').or_else(|| rest.strip_prefix(':')) else {
+                continue;
+            };
+            let rest = rest.trim_start();
+            let value = match rest.strip_prefix('"') {
+                Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+                None => rest
+                    .split(|c: char| c.is_whitespace() || c == ',')
+                    .next()
+                    .unwrap_or_default(),
+            };
+
+            if !value.is_empty() {
+                parameters.insert(input.clone(), serde_json::Value::String(value.to_string()));
+                break;
+            }
+        }
+    }
+
+    parameters
+}
+
+/// Split a compound intent into its clauses.
+///
+/// Clauses are separated by "and", "then" or a trailing `;`, outside double
+/// quotes: "check the weather in Paris and then email it to Bob" yields
+/// "check the weather in Paris" and "email it to Bob". A simple intent
+/// yields itself.
+pub fn split_compound(intent: &str) -> Vec<String> {
+    let mut clauses = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
+    let mut quoted = false;
+
+    let mut flush = |words: &mut Vec<&str>| {
+        let clause = words.join(" ");
+        let clause = clause.trim_end_matches([',', ';']).trim();
+        if !clause.is_empty() {
+            clauses.push(clause.to_string());
+        }
+        words.clear();
+    };
+
+    for word in intent.split_whitespace() {
+        if !quoted && matches!(word.to_lowercase().as_str(), "and" | "then") {
+            flush(&mut words);
+            continue;
+        }
+        quoted ^= word.matches('"').count() % 2 == 1;
+        words.push(word);
+        if !quoted && word.ends_with(';') {
+            flush(&mut words);
+        }
+    }
+    flush(&mut words);
+
+    clauses
+}
+
 /// Calibration function for LLM confidence scores.
 ///
 /// Uses Platt scaling: P(y=1|x) = 1 / (1 + exp(Ax + B))
@@ -273,6 +373,54 @@ mod tests {
         assert!(result.capability.is_none() || result.raw_confidence < 0.5);
     }
 
+    #[test]
+    fn keyword_interpreter_parameters() {
+        let interpreter = KeywordInterpreter::default();
+        let caps = sample_capabilities();
+        let ctx = sample_context();
+
+        let result = interpreter.interpret(
+            "Book a flight origin=LHR destination: \"New York\" date=2025-01-01",
+            &ctx,
+            &caps,
+        );
+
+        assert!(result.capability.as_ref().unwrap().id.contains("flight"));
+        assert_eq!(result.parameters["origin"], "LHR");
+        assert_eq!(result.parameters["destination"], "New York");
+        assert_eq!(result.parameters["date"], "2025-01-01");
+    }
+
+    #[test]
+    fn parameter_extraction() {
+        let inputs = vec!["location".to_string(), "date".to_string()];
+
+        let params = extract_parameters("Weather Location: London, tomorrow", &inputs);
+        assert_eq!(params["location"], "London");
+        assert!(!params.contains_key("date"));
+
+        // Input names must match whole words and be followed by a separator
+        let params = extract_parameters("relocation=Paris location Rome", &inputs);
+        assert!(params.is_empty());
+    }
+
+    #[test]
+    fn compound_intents() {
+        assert_eq!(
+            split_compound("check the weather in Paris and then email it to Bob"),
+            vec!["check the weather in Paris", "email it to Bob"]
+        );
+        assert_eq!(
+            split_compound("book a flight; reserve a hotel, then rent a car"),
+            vec!["book a flight", "reserve a hotel", "rent a car"]
+        );
+        assert_eq!(
+            split_compound(r#"send message="salt and pepper" then stop"#),
+            vec![r#"send message="salt and pepper""#, "stop"]
+        );
+        assert_eq!(split_compound("get the weather"), vec!["get the weather"]);
+    }
+
     #[test]
     fn platt_scaling() {
         // Test that platt scaling produces values in [0, 1]
@@ -8,19 +8,28 @@
 
 pub mod confidence;
 pub mod error;
+pub mod handshake;
 pub mod interpreter;
 pub mod message;
 pub mod security;
 pub mod state;
+pub mod version;
 
-pub use confidence::{compute_server_confidence, decide_action, Thresholds};
+pub use confidence::{
+    compute_server_confidence, decide_action, decision_rule, DecisionRule, Thresholds,
+};
 pub use error::{RefusalCode, SinpError, SinpResult};
+pub use handshake::{ClientFrame, Feature, Hello, ServerFrame, Session};
 pub use message::{
-    Action, ActionMetadata, Alternative, Capability, Constraints, Context, ContextType,
-    Interpretation, Message, Request, Responder, Response, Sender,
+    Action, ActionMetadata, Alternative, Cancel, CancelAck, Capability, Catalog, Confirmation,
+    Constraints, Context, ContextType, DiscoveryRequest, Event, EventKind, Explanation,
+    Interpretation, Job, JobList, JobQuery, JobStatus, Message, Plan, PlanStep, Preview, Progress,
+    Request, Responder, Response, Schedule, ScheduleList, ScheduleQuery, ScheduledExecution,
+    Sender, StepResult, StepStatus, Subscribe, Subscribed,
 };
 pub use security::{check_replay, semantic_hash, sign_message, verify_signature};
 pub use state::{ClientEvent, ClientState, ServerEvent, ServerState};
+pub use version::{parse_capability_id, Deprecation, VersionRange};
 
 /// Protocol version
-pub const PROTOCOL_VERSION: &str = "0.1";
+pub const PROTOCOL_VERSION: &str = "0.2";
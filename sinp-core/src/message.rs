@@ -3,11 +3,16 @@
 //! Defines the core message tuple M = (ID, CID, T, Sender, Ψ, Γ, Φ, Σ)
 //! as well as Request and Response schemas per RFC 0.1.
 
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::confidence::{DecisionRule, Thresholds};
 use crate::error::RefusalCode;
+use crate::state::ServerState;
+use crate::version::{Deprecation, VersionRange};
 
 /// Authentication method for sender identity.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -53,6 +58,9 @@ pub struct Constraints {
     pub privacy: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_ms: Option<u64>,
+    /// Acceptable versions per capability name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, VersionRange>,
 }
 
 /// Server capability definition.
@@ -100,6 +108,131 @@ pub struct ActionMetadata {
     /// Human-readable reason.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
+
+    /// Confirmation request if action is a confirm-style CLARIFY.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<Confirmation>,
+
+    /// Would-be outcome if the request was a dry run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<Preview>,
+
+    /// The matched capability version is deprecated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<Deprecation>,
+
+    /// Plan negotiated for a compound intent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Plan>,
+
+    /// Per-step results of an executed plan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<StepResult>>,
+
+    /// Job running the execution in the background, if the request asked
+    /// for one; its result is fetched later.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<Job>,
+
+    /// Scheduled execution the request was stored as, if it carried a
+    /// schedule; each run starts a background job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<ScheduledExecution>,
+}
+
+/// Outcome of a dry-run request: what the server would do, without doing it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preview {
+    /// Action the server would take.
+    pub action: Action,
+    /// Capability that would be executed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capability_id: Option<String>,
+    /// Inputs extracted from the intent.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+    /// Expected cost in capability cost units.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_cost: Option<f64>,
+    /// Whether execution would wait for operator approval.
+    #[serde(default)]
+    pub requires_approval: bool,
+    /// Description of the effect, if the capability provides a preview handler.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effect: Option<serde_json::Value>,
+}
+
+/// Interpretation the server asks the client to confirm before executing.
+///
+/// Sent when Φ_s ≥ τ_exec but Φ_c < τ_accept. A yes/no reply executes or
+/// drops the bound capability without re-interpreting the intent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Confirmation {
+    /// Capability that will be executed on confirmation.
+    pub capability_id: String,
+    /// Server's interpretation of the original intent.
+    pub interpretation: String,
+    /// Expected cost in capability cost units.
+    pub estimated_cost: f64,
+}
+
+/// Ordered capability invocations carrying out a compound intent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub steps: Vec<PlanStep>,
+    /// Lowest server confidence among the steps.
+    pub confidence: f64,
+    /// Expected cost of all steps in capability cost units.
+    pub estimated_cost: f64,
+}
+
+/// One capability invocation in a [`Plan`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub capability_id: String,
+    /// Clause of the intent this step carries out.
+    pub intent: String,
+    /// Server confidence Φ_s for the clause.
+    pub confidence: f64,
+    /// Inputs known before execution.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+    /// Inputs taken from the result of an earlier step, by step index.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub bindings: BTreeMap<String, usize>,
+    /// Required inputs the client has yet to provide.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+/// Outcome of one step of an executed [`Plan`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepResult {
+    /// Index of the step in the plan.
+    pub step: usize,
+    pub capability_id: String,
+    pub status: StepStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Result of the compensating handler, if the step was undone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compensation: Option<serde_json::Value>,
+}
+
+/// Status of a plan step after execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Completed,
+    Failed,
+    /// Not run because an earlier step failed.
+    Skipped,
+    /// Completed, then undone because a later step failed.
+    Compensated,
+    /// Completed, but undoing it failed; its side effects remain.
+    CompensationFailed,
 }
 
 /// Alternative action proposal.
@@ -112,11 +245,40 @@ pub struct Alternative {
     pub capability_id: String,
 }
 
+/// Breakdown of how the server arrived at its confidence and action.
+///
+/// Φ_s = min(1, ρ · R(c) · A(res)) · P(pol), decided against Φ_c with the
+/// thresholds of the matched capability.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// Matched capability, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capability_id: Option<String>,
+    /// Raw interpretation probability (ρ).
+    pub rho: f64,
+    /// Capability reliability R(c).
+    pub reliability: f64,
+    /// Resource availability A(res).
+    pub availability: f64,
+    /// Policy check P(pol).
+    pub policy_passed: bool,
+    /// Client confidence Φ_c.
+    pub client_confidence: f64,
+    /// Thresholds the decision was made against.
+    pub thresholds: Thresholds,
+    /// Decision rule that fired.
+    pub rule: DecisionRule,
+}
+
 /// Responder identity (server).
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Responder {
     pub id: String,
     pub capabilities: Vec<String>,
+    /// Version of the sender's capability catalog; a change means a cached
+    /// [`Catalog`] is stale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub catalog_version: Option<String>,
 }
 
 /// Base message fields common to requests and responses.
@@ -144,6 +306,28 @@ pub struct Request {
     pub constraints: Option<Constraints>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
+    /// Bearer token or API key, as indicated by `sender.auth_method`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    /// Decide without executing; the response carries a [`Preview`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// Inputs given explicitly; they take precedence over inputs extracted
+    /// from the intent.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+    /// Client-chosen key; a retry with the same key gets the original
+    /// response instead of executing again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Run the execution as a background job; the EXECUTE response carries
+    /// the [`Job`] instead of the result.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub background: bool,
+    /// Run the execution later instead of now; the EXECUTE response carries
+    /// the [`ScheduledExecution`] instead of the result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
 }
 
 impl Request {
@@ -166,6 +350,12 @@ impl Request {
             context,
             constraints: None,
             signature: None,
+            credential: None,
+            dry_run: false,
+            parameters: serde_json::Map::new(),
+            idempotency_key: None,
+            background: false,
+            schedule: None,
         }
     }
 
@@ -189,8 +379,343 @@ impl Request {
             context,
             constraints: None,
             signature: None,
+            credential: None,
+            dry_run: false,
+            parameters: serde_json::Map::new(),
+            idempotency_key: None,
+            background: false,
+            schedule: None,
+        }
+    }
+}
+
+/// Request for the capabilities available to the sender.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryRequest {
+    pub message_id: Uuid,
+    pub sender: Sender,
+    /// Bearer token or API key, as indicated by `sender.auth_method`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    /// Cursor from the previous page's `next_cursor`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// Maximum capabilities per page (capped by the server).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// Catalog version the client has cached.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub if_none_match: Option<String>,
+}
+
+impl DiscoveryRequest {
+    /// Request the first page of the catalog.
+    pub fn new(sender: Sender) -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            sender,
+            credential: None,
+            cursor: None,
+            limit: None,
+            if_none_match: None,
+        }
+    }
+}
+
+/// Client request to end a conversation, stopping any execution in
+/// progress.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cancel {
+    pub message_id: Uuid,
+    pub conversation_id: Uuid,
+    pub sender: Sender,
+    /// Bearer token or API key, as indicated by `sender.auth_method`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Cancel {
+    /// Cancel a conversation.
+    pub fn new(sender: Sender, conversation_id: Uuid) -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            conversation_id,
+            sender,
+            credential: None,
+            reason: None,
+        }
+    }
+}
+
+/// Server confirmation that a conversation was cancelled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancelAck {
+    pub in_response_to: Uuid,
+    pub conversation_id: Uuid,
+    /// Whether an execution was running and was asked to stop. Its
+    /// response precedes this acknowledgement.
+    #[serde(default)]
+    pub interrupted: bool,
+}
+
+/// Status of a background job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Execution running in the background, and its outcome once complete.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub sender_id: String,
+    pub capability_id: String,
+    pub intent: String,
+    pub status: JobStatus,
+    pub submitted_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// When the server discards the job; set once it completes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Job {
+    /// Whether the job has finished, successfully or not.
+    pub fn is_complete(&self) -> bool {
+        self.status != JobStatus::Running
+    }
+}
+
+/// Client query for its background jobs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobQuery {
+    pub message_id: Uuid,
+    pub sender: Sender,
+    /// Bearer token or API key, as indicated by `sender.auth_method`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    /// Job to report on; all of the sender's jobs if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<Uuid>,
+    /// Reply only once the job has completed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub wait: bool,
+}
+
+impl JobQuery {
+    /// List all of the sender's jobs.
+    pub fn new(sender: Sender) -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            sender,
+            credential: None,
+            job_id: None,
+            wait: false,
         }
     }
+
+    /// Report on a single job.
+    pub fn job(sender: Sender, job_id: Uuid) -> Self {
+        Self {
+            job_id: Some(job_id),
+            ..Self::new(sender)
+        }
+    }
+}
+
+/// Jobs matching a [`JobQuery`], oldest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobList {
+    pub in_response_to: Uuid,
+    pub jobs: Vec<Job>,
+}
+
+/// When a scheduled request runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Once, at the given time.
+    At { at: DateTime<Utc> },
+    /// Whenever a cron expression matches, in UTC: five fields (minute,
+    /// hour, day of month, month, day of week), or six with seconds first.
+    Cron { expression: String },
+}
+
+/// Request stored by the server to run on a schedule.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledExecution {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub sender_id: String,
+    pub capability_id: String,
+    pub intent: String,
+    pub schedule: Schedule,
+    pub created_at: DateTime<Utc>,
+    pub next_run: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<DateTime<Utc>>,
+    /// Background job started by the last run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_job: Option<Uuid>,
+    #[serde(default)]
+    pub runs: u64,
+}
+
+/// Client query for its scheduled executions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleQuery {
+    pub message_id: Uuid,
+    pub sender: Sender,
+    /// Bearer token or API key, as indicated by `sender.auth_method`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    /// Scheduled execution to cancel; the reply lists it alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancel: Option<Uuid>,
+}
+
+impl ScheduleQuery {
+    /// List all of the sender's scheduled executions.
+    pub fn new(sender: Sender) -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            sender,
+            credential: None,
+            cancel: None,
+        }
+    }
+
+    /// Cancel one of the sender's scheduled executions.
+    pub fn cancel(sender: Sender, id: Uuid) -> Self {
+        Self {
+            cancel: Some(id),
+            ..Self::new(sender)
+        }
+    }
+}
+
+/// Scheduled executions matching a [`ScheduleQuery`], soonest first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleList {
+    pub in_response_to: Uuid,
+    pub schedules: Vec<ScheduledExecution>,
+}
+
+/// Kind of event a client can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    JobCompleted,
+    CatalogChanged,
+    ConversationUpdated,
+}
+
+/// Event pushed by the server to a subscribed client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// One of the sender's background jobs completed.
+    JobCompleted { job: Box<Job> },
+    /// The capabilities available to the sender changed.
+    CatalogChanged { version: String },
+    /// One of the sender's conversations changed state.
+    ConversationUpdated {
+        conversation_id: Uuid,
+        state: ServerState,
+    },
+}
+
+impl Event {
+    /// Kind to subscribe to for this event.
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::JobCompleted { .. } => EventKind::JobCompleted,
+            Self::CatalogChanged { .. } => EventKind::CatalogChanged,
+            Self::ConversationUpdated { .. } => EventKind::ConversationUpdated,
+        }
+    }
+}
+
+/// Client request for events to be pushed on this connection.
+///
+/// Replaces the connection's earlier subscription; no events unsubscribes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscribe {
+    pub message_id: Uuid,
+    pub sender: Sender,
+    /// Bearer token or API key, as indicated by `sender.auth_method`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    pub events: Vec<EventKind>,
+}
+
+impl Subscribe {
+    /// Subscribe to events about the sender.
+    pub fn new(sender: Sender, events: impl IntoIterator<Item = EventKind>) -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            sender,
+            credential: None,
+            events: events.into_iter().collect(),
+        }
+    }
+}
+
+/// Server confirmation of a [`Subscribe`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscribed {
+    pub in_response_to: Uuid,
+    pub events: Vec<EventKind>,
+}
+
+/// One page of the capability catalog.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    pub in_response_to: Uuid,
+    /// Catalog version (ETag) of the sender's view.
+    pub version: String,
+    /// Capabilities on this page, ordered by ID.
+    pub capabilities: Vec<Capability>,
+    /// Total capabilities in the catalog.
+    pub total: usize,
+    /// Deprecated versions among the capabilities on this page.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deprecations: Vec<Deprecation>,
+    /// Cursor for the next page, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// The cached version matches; no capabilities are sent.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub not_modified: bool,
+}
+
+/// Update on a request whose capability is still running.
+///
+/// Sent ahead of the final response when the session agreed on streaming.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub in_response_to: Uuid,
+    /// Position among the updates for the request, from 0.
+    pub sequence: u64,
+    /// Share of the work done, from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fraction: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Part of the result available so far.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial: Option<serde_json::Value>,
 }
 
 /// Server response message.
@@ -208,6 +733,9 @@ pub struct Response {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub alternatives: Option<Vec<Alternative>>,
     pub confidence: f64,
+    /// Φ breakdown, if the server is configured to explain decisions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<Explanation>,
 }
 
 impl Response {
@@ -230,6 +758,7 @@ impl Response {
             action_metadata: None,
             alternatives: None,
             confidence,
+            explanation: None,
         }
     }
 }
@@ -258,12 +787,32 @@ mod tests {
         let req = Request::new(sample_sender(), "Get the weather", 0.85, sample_context());
 
         let json = serde_json::to_string_pretty(&req).unwrap();
-        assert!(json.contains("\"protocol_version\": \"0.1\""));
+        assert!(json.contains("\"protocol_version\": \"0.2\""));
         assert!(json.contains("\"intent\": \"Get the weather\""));
 
         let parsed: Request = serde_json::from_str(&json).unwrap();
         assert_eq!(parsed.intent, req.intent);
         assert_eq!(parsed.confidence, req.confidence);
+        assert!(!json.contains("dry_run"));
+        assert!(!parsed.dry_run);
+        assert!(!json.contains("credential"));
+        assert!(parsed.credential.is_none());
+        assert!(!json.contains("idempotency_key"));
+        assert!(parsed.idempotency_key.is_none());
+        assert!(!json.contains("schedule"));
+    }
+
+    #[test]
+    fn schedule_serialization() {
+        let mut req = Request::new(sample_sender(), "Send the report", 0.9, sample_context());
+        req.schedule = Some(Schedule::Cron {
+            expression: "0 9 * * MON-FRI".to_string(),
+        });
+
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""schedule":{"kind":"cron","expression":"0 9 * * MON-FRI"}"#));
+        let parsed: Request = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.schedule, req.schedule);
     }
 
     #[test]
@@ -280,6 +829,7 @@ mod tests {
         let responder = Responder {
             id: "srv_1".to_string(),
             capabilities: vec!["flight_booking:v1".to_string()],
+            catalog_version: None,
         };
         let interpretation = Interpretation {
             text: "Booking a flight to destination".to_string(),
@@ -291,5 +841,41 @@ mod tests {
         assert_eq!(resp.in_response_to, req.message_id);
         assert_eq!(resp.conversation_id, req.conversation_id);
         assert_eq!(resp.action, Action::Execute);
+        assert!(resp.explanation.is_none());
+    }
+
+    #[test]
+    fn explanation_serialization() {
+        let req = Request::new(sample_sender(), "Book a flight", 0.4, sample_context());
+        let responder = Responder {
+            id: "srv_1".to_string(),
+            capabilities: vec![],
+            catalog_version: None,
+        };
+        let interpretation = Interpretation {
+            text: "Booking a flight".to_string(),
+            confidence: 0.9,
+        };
+
+        let mut resp = Response::to_request(&req, responder, interpretation, Action::Clarify, 0.9);
+        let json = serde_json::to_string(&resp).unwrap();
+        assert!(!json.contains("explanation"));
+
+        resp.explanation = Some(Explanation {
+            capability_id: Some("book_flight:v1".to_string()),
+            rho: 0.95,
+            reliability: 0.95,
+            availability: 1.0,
+            policy_passed: true,
+            client_confidence: 0.4,
+            thresholds: Thresholds::default(),
+            rule: DecisionRule::Clarify,
+        });
+        let json = serde_json::to_string(&resp).unwrap();
+        assert!(json.contains("\"rule\":\"clarify\""));
+        assert!(json.contains("\"tau_clarify\":0.5"));
+
+        let parsed: Response = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.explanation, resp.explanation);
     }
 }
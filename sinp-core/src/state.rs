@@ -16,30 +16,42 @@ pub enum ServerState {
     Interpreting,
     /// Applying decision logic δ(Φ_s, Φ_c).
     Deciding,
+    /// EXECUTE decided, waiting for an operator to approve it.
+    AwaitingApproval,
     /// Awaiting client response to CLARIFY or PROPOSE.
     Negotiating,
     /// Terminal state - action completed.
     Done,
     /// Error state - unrecoverable failure.
     Failed,
+    /// Terminal state - cancelled by the client.
+    Cancelled,
 }
 
 impl ServerState {
     /// Check if this is a terminal state.
     pub fn is_terminal(&self) -> bool {
-        matches!(self, Self::Done | Self::Failed)
+        matches!(self, Self::Done | Self::Failed | Self::Cancelled)
     }
 
     /// Get valid transitions from current state.
     pub fn valid_transitions(&self) -> &'static [ServerState] {
         match self {
-            Self::Received => &[Self::Validating, Self::Failed],
-            Self::Validating => &[Self::Interpreting, Self::Failed],
-            Self::Interpreting => &[Self::Deciding, Self::Failed],
-            Self::Deciding => &[Self::Done, Self::Negotiating, Self::Failed],
-            Self::Negotiating => &[Self::Received, Self::Done, Self::Failed],
+            Self::Received => &[Self::Validating, Self::Failed, Self::Cancelled],
+            Self::Validating => &[Self::Interpreting, Self::Failed, Self::Cancelled],
+            Self::Interpreting => &[Self::Deciding, Self::Failed, Self::Cancelled],
+            Self::Deciding => &[
+                Self::Done,
+                Self::Negotiating,
+                Self::AwaitingApproval,
+                Self::Failed,
+                Self::Cancelled,
+            ],
+            Self::AwaitingApproval => &[Self::Done, Self::Failed, Self::Cancelled],
+            Self::Negotiating => &[Self::Received, Self::Done, Self::Failed, Self::Cancelled],
             Self::Done => &[],
             Self::Failed => &[],
+            Self::Cancelled => &[],
         }
     }
 
@@ -77,7 +89,12 @@ impl ClientState {
     pub fn valid_transitions(&self) -> &'static [ClientState] {
         match self {
             Self::Init => &[Self::Pending, Self::Failed],
-            Self::Pending => &[Self::Refining, Self::Satisfied, Self::Failed],
+            Self::Pending => &[
+                Self::Refining,
+                Self::Satisfied,
+                Self::Abandoned,
+                Self::Failed,
+            ],
             Self::Refining => &[Self::Pending, Self::Abandoned, Self::Failed],
             Self::Satisfied => &[],
             Self::Abandoned => &[],
@@ -110,8 +127,18 @@ pub enum ServerEvent {
     DecisionPropose,
     /// Decision made: REFUSE.
     DecisionRefuse,
+    /// EXECUTE parked for operator approval.
+    ApprovalRequested,
+    /// Operator approved the execution.
+    ApprovalGranted,
+    /// Operator denied the execution (or approval timed out).
+    ApprovalDenied(String),
+    /// Dry run decided; nothing is executed.
+    DryRunCompleted,
     /// Client responded to negotiation.
     ClientResponded,
+    /// Client cancelled the conversation.
+    Cancelled,
     /// Action completed successfully.
     ActionCompleted,
     /// Error occurred.
@@ -155,13 +182,21 @@ mod tests {
         assert!(state.can_transition_to(ServerState::Validating));
         assert!(state.can_transition_to(ServerState::Failed));
         assert!(!state.can_transition_to(ServerState::Done));
+
+        let deciding = ServerState::Deciding;
+        assert!(deciding.can_transition_to(ServerState::AwaitingApproval));
+        assert!(ServerState::AwaitingApproval.can_transition_to(ServerState::Done));
+        assert!(!ServerState::AwaitingApproval.can_transition_to(ServerState::Negotiating));
     }
 
     #[test]
     fn server_terminal_states() {
         assert!(ServerState::Done.is_terminal());
         assert!(ServerState::Failed.is_terminal());
+        assert!(ServerState::Cancelled.is_terminal());
         assert!(!ServerState::Received.is_terminal());
+        assert!(ServerState::Negotiating.can_transition_to(ServerState::Cancelled));
+        assert!(!ServerState::Done.can_transition_to(ServerState::Cancelled));
     }
 
     #[test]
@@ -0,0 +1,180 @@
+//! Capability versions for SINP.
+//!
+//! Capability IDs have the form `name:vN`. Several versions of a capability
+//! can be registered side by side; clients select among them with a
+//! [`VersionRange`] per capability name.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SinpError;
+
+/// Split a capability ID into name and version.
+///
+/// `"echo:v1"` gives `("echo", Some(1))`; IDs without a `:vN` suffix are
+/// unversioned.
+pub fn parse_capability_id(id: &str) -> (&str, Option<u32>) {
+    match id.rsplit_once(':') {
+        Some((name, version)) => match version.strip_prefix('v').and_then(|v| v.parse().ok()) {
+            Some(version) => (name, Some(version)),
+            None => (id, None),
+        },
+        None => (id, None),
+    }
+}
+
+/// Comparison in a [`VersionRange`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// Set of acceptable capability versions, e.g. `">=1, <3"` or `"2"`.
+///
+/// Comparators are separated by commas and must all hold. A bare version
+/// means exactly that version; the `v` prefix is optional.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct VersionRange {
+    comparators: Vec<(Op, u32)>,
+}
+
+impl VersionRange {
+    /// Exactly one version.
+    pub fn exact(version: u32) -> Self {
+        Self {
+            comparators: vec![(Op::Eq, version)],
+        }
+    }
+
+    /// Check whether a version is in the range.
+    pub fn matches(&self, version: u32) -> bool {
+        self.comparators.iter().all(|(op, bound)| match op {
+            Op::Eq => version == *bound,
+            Op::Gt => version > *bound,
+            Op::Ge => version >= *bound,
+            Op::Lt => version < *bound,
+            Op::Le => version <= *bound,
+        })
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = SinpError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SinpError::Validation(format!("Invalid version range: {:?}", s));
+
+        let comparators = s
+            .split(',')
+            .map(|part| {
+                let part = part.trim();
+                let (op, rest) = [
+                    (">=", Op::Ge),
+                    ("<=", Op::Le),
+                    (">", Op::Gt),
+                    ("<", Op::Lt),
+                    ("=", Op::Eq),
+                ]
+                .iter()
+                .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|rest| (*op, rest)))
+                .unwrap_or((Op::Eq, part));
+                let rest = rest.trim();
+                let version = rest.strip_prefix('v').unwrap_or(rest);
+                version.parse().map(|v| (op, v)).map_err(|_| invalid())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { comparators })
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .comparators
+            .iter()
+            .map(|(op, version)| {
+                let op = match op {
+                    Op::Eq => "=",
+                    Op::Gt => ">",
+                    Op::Ge => ">=",
+                    Op::Lt => "<",
+                    Op::Le => "<=",
+                };
+                format!("{}{}", op, version)
+            })
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl TryFrom<String> for VersionRange {
+    type Error = SinpError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<VersionRange> for String {
+    fn from(range: VersionRange) -> Self {
+        range.to_string()
+    }
+}
+
+/// Notice that a capability version is going away.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deprecation {
+    pub capability_id: String,
+    /// After this time the version is no longer served.
+    pub sunset: DateTime<Utc>,
+    /// Version to migrate to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ids() {
+        assert_eq!(parse_capability_id("echo:v1"), ("echo", Some(1)));
+        assert_eq!(parse_capability_id("book_flight:v12"), ("book_flight", Some(12)));
+        assert_eq!(parse_capability_id("echo"), ("echo", None));
+        assert_eq!(parse_capability_id("echo:latest"), ("echo:latest", None));
+    }
+
+    #[test]
+    fn ranges() {
+        let range: VersionRange = ">=1, <3".parse().unwrap();
+        assert!(!range.matches(0));
+        assert!(range.matches(1));
+        assert!(range.matches(2));
+        assert!(!range.matches(3));
+
+        let range: VersionRange = "v2".parse().unwrap();
+        assert_eq!(range, VersionRange::exact(2));
+        assert!(range.matches(2) && !range.matches(3));
+
+        assert!("".parse::<VersionRange>().is_err());
+        assert!(">=x".parse::<VersionRange>().is_err());
+    }
+
+    #[test]
+    fn range_serialization() {
+        let range: VersionRange = ">= v1,< v3".parse().unwrap();
+        let json = serde_json::to_string(&range).unwrap();
+        assert_eq!(json, "\">=1, <3\"");
+        assert_eq!(serde_json::from_str::<VersionRange>(&json).unwrap(), range);
+        assert!(serde_json::from_str::<VersionRange>("\"~1\"").is_err());
+    }
+}
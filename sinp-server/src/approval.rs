@@ -0,0 +1,247 @@
+//! Human-in-the-loop approval for sensitive capabilities.
+//!
+//! EXECUTE decisions for capabilities matched by [`ApprovalConfig`] are parked
+//! in an [`ApprovalQueue`] until an operator approves or denies them.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use sinp_core::{Capability, SinpError, SinpResult};
+
+/// Which capabilities require operator approval before executing.
+#[derive(Debug, Clone)]
+pub struct ApprovalConfig {
+    /// Privacy levels that require approval (default `pii_sensitive`).
+    pub privacy_levels: Vec<String>,
+    /// Cost at or above which approval is required (default 5.0).
+    pub min_cost_units: Option<f64>,
+    /// Capability IDs that always require approval.
+    pub capabilities: Vec<String>,
+    /// How long to wait for a decision before denying.
+    pub timeout: Duration,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self {
+            privacy_levels: vec!["pii_sensitive".to_string()],
+            min_cost_units: Some(5.0),
+            capabilities: Vec::new(),
+            timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl ApprovalConfig {
+    /// Check whether executing a capability requires approval.
+    pub fn requires_approval(&self, capability: &Capability) -> bool {
+        self.capabilities.contains(&capability.id)
+            || self.privacy_levels.contains(&capability.privacy_level)
+            || self
+                .min_cost_units
+                .is_some_and(|min| capability.cost_units >= min)
+    }
+}
+
+/// Execution waiting for operator approval.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingApproval {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub sender_id: String,
+    pub capability_id: String,
+    pub intent: String,
+    pub estimated_cost: f64,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Operator decision on a pending approval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    Approved,
+    Denied(String),
+}
+
+struct QueuedApproval {
+    approval: PendingApproval,
+    decision: oneshot::Sender<ApprovalDecision>,
+}
+
+/// Shared queue of executions awaiting approval.
+///
+/// Cloning yields another handle to the same queue, so the operator side
+/// can hold one while connection handlers submit to another.
+#[derive(Clone, Default)]
+pub struct ApprovalQueue {
+    pending: Arc<Mutex<HashMap<Uuid, QueuedApproval>>>,
+}
+
+impl ApprovalQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Park an execution until it is approved or denied.
+    pub fn submit(&self, approval: PendingApproval) -> ApprovalTicket {
+        let (tx, rx) = oneshot::channel();
+        let id = approval.id;
+        tracing::info!(
+            "Approval required for {} (request {}, sender {})",
+            approval.capability_id,
+            id,
+            approval.sender_id
+        );
+        self.lock().insert(
+            id,
+            QueuedApproval {
+                approval,
+                decision: tx,
+            },
+        );
+        ApprovalTicket {
+            id,
+            decision: rx,
+            queue: self.clone(),
+        }
+    }
+
+    /// List executions awaiting approval, oldest first.
+    pub fn pending(&self) -> Vec<PendingApproval> {
+        let mut pending: Vec<_> = self.lock().values().map(|q| q.approval.clone()).collect();
+        pending.sort_by_key(|a| a.requested_at);
+        pending
+    }
+
+    /// Approve a pending execution.
+    pub fn approve(&self, id: Uuid) -> SinpResult<()> {
+        self.decide(id, ApprovalDecision::Approved)
+    }
+
+    /// Deny a pending execution.
+    pub fn deny(&self, id: Uuid, reason: impl Into<String>) -> SinpResult<()> {
+        self.decide(id, ApprovalDecision::Denied(reason.into()))
+    }
+
+    fn decide(&self, id: Uuid, decision: ApprovalDecision) -> SinpResult<()> {
+        let queued = self
+            .lock()
+            .remove(&id)
+            .ok_or_else(|| SinpError::Protocol(format!("No pending approval: {}", id)))?;
+        // The waiting connection may have gone away; nothing left to notify
+        let _ = queued.decision.send(decision);
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, QueuedApproval>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Handle to await the decision on a submitted approval.
+pub struct ApprovalTicket {
+    id: Uuid,
+    decision: oneshot::Receiver<ApprovalDecision>,
+    queue: ApprovalQueue,
+}
+
+impl ApprovalTicket {
+    /// ID of the pending approval.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Wait for the operator decision, denying after `timeout`.
+    pub async fn wait(self, timeout: Duration) -> ApprovalDecision {
+        match tokio::time::timeout(timeout, self.decision).await {
+            Ok(Ok(decision)) => decision,
+            Ok(Err(_)) => ApprovalDecision::Denied("approval queue closed".to_string()),
+            Err(_) => {
+                self.queue.lock().remove(&self.id);
+                ApprovalDecision::Denied("approval timed out".to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_capability(privacy_level: &str, cost_units: f64) -> Capability {
+        Capability {
+            id: "book_flight:v1".to_string(),
+            description: "Book a flight".to_string(),
+            inputs: vec![],
+            privacy_level: privacy_level.to_string(),
+            cost_units,
+        }
+    }
+
+    fn sample_approval() -> PendingApproval {
+        PendingApproval {
+            id: Uuid::new_v4(),
+            conversation_id: Uuid::new_v4(),
+            sender_id: "client_1".to_string(),
+            capability_id: "book_flight:v1".to_string(),
+            intent: "Book a flight to NYC".to_string(),
+            estimated_cost: 5.0,
+            requested_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn requires_approval() {
+        let config = ApprovalConfig::default();
+        assert!(config.requires_approval(&sample_capability("pii_sensitive", 0.1)));
+        assert!(config.requires_approval(&sample_capability("public", 5.0)));
+        assert!(!config.requires_approval(&sample_capability("public", 1.0)));
+
+        let config = ApprovalConfig {
+            privacy_levels: vec![],
+            min_cost_units: None,
+            capabilities: vec!["book_flight:v1".to_string()],
+            ..Default::default()
+        };
+        assert!(config.requires_approval(&sample_capability("public", 0.1)));
+    }
+
+    #[tokio::test]
+    async fn approve_and_deny() {
+        let queue = ApprovalQueue::new();
+
+        let ticket = queue.submit(sample_approval());
+        assert_eq!(queue.pending().len(), 1);
+        queue.approve(ticket.id()).unwrap();
+        assert!(queue.pending().is_empty());
+        assert_eq!(
+            ticket.wait(Duration::from_secs(1)).await,
+            ApprovalDecision::Approved
+        );
+
+        let ticket = queue.submit(sample_approval());
+        queue.deny(ticket.id(), "not allowed").unwrap();
+        assert_eq!(
+            ticket.wait(Duration::from_secs(1)).await,
+            ApprovalDecision::Denied("not allowed".to_string())
+        );
+
+        assert!(queue.approve(Uuid::new_v4()).is_err());
+    }
+
+    #[tokio::test]
+    async fn approval_timeout() {
+        let queue = ApprovalQueue::new();
+        let ticket = queue.submit(sample_approval());
+
+        let decision = ticket.wait(Duration::from_millis(10)).await;
+        assert!(matches!(decision, ApprovalDecision::Denied(_)));
+        assert!(queue.pending().is_empty());
+    }
+}
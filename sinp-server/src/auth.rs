@@ -0,0 +1,516 @@
+//! Sender authentication for SINP server.
+//!
+//! Binds the `Sender` claimed in a request to the identity established by
+//! the transport (a verified client certificate) or to the credential the
+//! request carries (an API key or signed token).
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rustls::pki_types::CertificateDer;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use sinp_core::message::{AuthMethod, Sender};
+use sinp_core::{RefusalCode, Request, SinpError, SinpResult};
+
+/// Identity of a verified client certificate.
+///
+/// Uses the subject common name, or the full subject if there is none.
+pub fn certificate_identity(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = X509Certificate::from_der(cert.as_ref()).ok()?;
+    let subject = cert.subject();
+    let common_name = subject
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(String::from);
+    Some(common_name.unwrap_or_else(|| subject.to_string()))
+}
+
+/// Check the claimed sender against the connection's certificate identity.
+///
+/// With a verified certificate, `Sender.id` must equal its identity. Without
+/// one, a sender must not claim certificate authentication.
+pub fn check_certificate_identity(sender: &Sender, peer_identity: Option<&str>) -> SinpResult<()> {
+    match peer_identity {
+        Some(identity) if sender.id != identity => Err(authentication_failed(format!(
+            "sender {} does not match client certificate {}",
+            sender.id, identity
+        ))),
+        None if sender.auth_method == AuthMethod::Certificate => Err(authentication_failed(
+            "no client certificate presented".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Verifies the credential presented by a sender.
+pub trait Authenticator: Send + Sync {
+    /// Check that `credential` authenticates `sender`.
+    fn authenticate(&self, sender: &Sender, credential: &str) -> SinpResult<()>;
+}
+
+/// Authenticators for the credential-based auth methods.
+///
+/// Senders claiming `token` or `api_key` authentication are refused unless
+/// the matching authenticator is configured and accepts their credential.
+/// Once any authenticator is configured, unauthenticated senders are refused
+/// too, unless the connection presented a verified client certificate.
+#[derive(Clone, Default)]
+pub struct Authenticators {
+    token: Option<Arc<dyn Authenticator>>,
+    api_key: Option<Arc<dyn Authenticator>>,
+}
+
+impl Authenticators {
+    /// Create an empty set (only `none` and `certificate` senders accepted).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authenticate `token` senders.
+    pub fn with_token(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.token = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Authenticate `api_key` senders.
+    pub fn with_api_key(mut self, authenticator: impl Authenticator + 'static) -> Self {
+        self.api_key = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Authenticate a request received on a connection with the given
+    /// certificate identity.
+    pub fn authenticate(&self, request: &Request, peer_identity: Option<&str>) -> SinpResult<()> {
+        self.authenticate_sender(
+            &request.sender,
+            request.credential.as_deref(),
+            peer_identity,
+        )
+    }
+
+    /// Authenticate a sender and the credential it presented.
+    pub fn authenticate_sender(
+        &self,
+        sender: &Sender,
+        credential: Option<&str>,
+        peer_identity: Option<&str>,
+    ) -> SinpResult<()> {
+        check_certificate_identity(sender, peer_identity)?;
+
+        let (authenticator, method) = match sender.auth_method {
+            AuthMethod::Token => (&self.token, "token"),
+            AuthMethod::ApiKey => (&self.api_key, "API key"),
+            // Already bound to the verified certificate
+            AuthMethod::Certificate => return Ok(()),
+            AuthMethod::None if self.is_required() && peer_identity.is_none() => {
+                return Err(authentication_failed(
+                    "unauthenticated senders are not accepted".to_string(),
+                ));
+            }
+            AuthMethod::None => return Ok(()),
+        };
+        let authenticator = authenticator.as_ref().ok_or_else(|| {
+            authentication_failed(format!("{} authentication is not enabled", method))
+        })?;
+        let credential =
+            credential.ok_or_else(|| authentication_failed(format!("no {} presented", method)))?;
+        authenticator.authenticate(sender, credential)
+    }
+
+    /// Whether any credential-based authenticator is configured.
+    fn is_required(&self) -> bool {
+        self.token.is_some() || self.api_key.is_some()
+    }
+}
+
+/// API keys per sender ID.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeyAuthenticator {
+    keys: HashMap<String, String>,
+}
+
+impl ApiKeyAuthenticator {
+    /// Create an authenticator with no keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `key` for `sender_id`.
+    pub fn with_key(mut self, sender_id: impl Into<String>, key: impl Into<String>) -> Self {
+        self.keys.insert(sender_id.into(), key.into());
+        self
+    }
+
+    /// Load keys from a file with one `<sender_id> <key>` pair per line.
+    ///
+    /// Blank lines and lines starting with `#` are ignored.
+    pub fn from_file(path: impl AsRef<Path>) -> SinpResult<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            SinpError::Validation(format!(
+                "Failed to read API key file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let mut authenticator = Self::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [sender_id, key] => authenticator = authenticator.with_key(sender_id, key),
+                _ => {
+                    return Err(SinpError::Validation(format!(
+                        "{}:{}: expected `<sender_id> <key>`",
+                        path.display(),
+                        number + 1
+                    )))
+                }
+            }
+        }
+        Ok(authenticator)
+    }
+}
+
+impl Authenticator for ApiKeyAuthenticator {
+    fn authenticate(&self, sender: &Sender, credential: &str) -> SinpResult<()> {
+        match self.keys.get(&sender.id) {
+            Some(key) if constant_time_eq(key.as_bytes(), credential.as_bytes()) => Ok(()),
+            _ => Err(authentication_failed(format!(
+                "invalid API key for {}",
+                sender.id
+            ))),
+        }
+    }
+}
+
+/// Claims carried by a signed token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    /// Sender ID the token was issued to.
+    pub sub: String,
+    /// Expiry as seconds since the Unix epoch.
+    pub exp: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TokenHeader {
+    alg: String,
+    typ: String,
+}
+
+/// HMAC-SHA256 signed tokens in JWT compact form (`HS256`).
+///
+/// The `sub` claim must equal the sender ID and `exp` must be in the future.
+pub struct TokenAuthenticator {
+    secret: Vec<u8>,
+}
+
+impl TokenAuthenticator {
+    /// Create an authenticator with a shared secret.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    /// Issue a token for `sender_id`, valid for `ttl`.
+    pub fn issue(&self, sender_id: impl Into<String>, ttl: Duration) -> String {
+        let now = chrono::Utc::now();
+        let expires = chrono::Duration::from_std(ttl)
+            .ok()
+            .and_then(|ttl| now.checked_add_signed(ttl))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MAX_UTC);
+        let claims = TokenClaims {
+            sub: sender_id.into(),
+            exp: expires.timestamp(),
+        };
+        let header = TokenHeader {
+            alg: "HS256".to_string(),
+            typ: "JWT".to_string(),
+        };
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header).unwrap_or_default()),
+            URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap_or_default())
+        );
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&signing_input).finalize().into_bytes());
+        format!("{}.{}", signing_input, signature)
+    }
+
+    /// Verify a token's signature and expiry, returning its claims.
+    pub fn verify(&self, token: &str) -> SinpResult<TokenClaims> {
+        let invalid = || authentication_failed("malformed token".to_string());
+
+        let (signing_input, signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+        let (header, claims) = signing_input.split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(signing_input)
+            .verify_slice(&signature)
+            .map_err(|_| authentication_failed("invalid token signature".to_string()))?;
+
+        let header: TokenHeader = URL_SAFE_NO_PAD
+            .decode(header)
+            .ok()
+            .and_then(|h| serde_json::from_slice(&h).ok())
+            .ok_or_else(invalid)?;
+        if header.alg != "HS256" {
+            return Err(authentication_failed(format!(
+                "unsupported token algorithm {}",
+                header.alg
+            )));
+        }
+
+        let claims: TokenClaims = URL_SAFE_NO_PAD
+            .decode(claims)
+            .ok()
+            .and_then(|c| serde_json::from_slice(&c).ok())
+            .ok_or_else(invalid)?;
+        if claims.exp <= chrono::Utc::now().timestamp() {
+            return Err(authentication_failed("token expired".to_string()));
+        }
+        Ok(claims)
+    }
+
+    fn mac(&self, signing_input: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(signing_input.as_bytes());
+        mac
+    }
+}
+
+impl Authenticator for TokenAuthenticator {
+    fn authenticate(&self, sender: &Sender, credential: &str) -> SinpResult<()> {
+        let claims = self.verify(credential)?;
+        if claims.sub != sender.id {
+            return Err(authentication_failed(format!(
+                "token issued to {}, not {}",
+                claims.sub, sender.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Compare secrets without short-circuiting on the first differing byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn authentication_failed(reason: String) -> SinpError {
+    SinpError::Refused {
+        code: RefusalCode::AuthenticationFailed,
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, DnType, KeyPair};
+    use sinp_core::message::{Context, ContextType, Sender};
+
+    fn sample_request(sender_id: &str, auth_method: AuthMethod) -> Request {
+        Request::new(
+            Sender {
+                id: sender_id.to_string(),
+                auth_method,
+            },
+            "test",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn identity_from_common_name() {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "client_1");
+        let cert = params.self_signed(&key).unwrap();
+
+        assert_eq!(
+            certificate_identity(cert.der()).as_deref(),
+            Some("client_1")
+        );
+    }
+
+    #[test]
+    fn identity_without_common_name() {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        params
+            .distinguished_name
+            .push(DnType::OrganizationName, "Acme");
+        let cert = params.self_signed(&key).unwrap();
+
+        assert_eq!(certificate_identity(cert.der()).as_deref(), Some("O=Acme"));
+    }
+
+    #[test]
+    fn sender_must_match_certificate() {
+        let request = sample_request("client_1", AuthMethod::Certificate);
+        assert!(check_certificate_identity(&request.sender, Some("client_1")).is_ok());
+
+        let err = check_certificate_identity(&request.sender, Some("client_2")).unwrap_err();
+        assert!(matches!(
+            err,
+            SinpError::Refused {
+                code: RefusalCode::AuthenticationFailed,
+                ..
+            }
+        ));
+
+        // Claiming certificate auth without a certificate
+        assert!(check_certificate_identity(&request.sender, None).is_err());
+
+        let request = sample_request("client_1", AuthMethod::None);
+        assert!(check_certificate_identity(&request.sender, None).is_ok());
+    }
+
+    fn assert_refused(result: SinpResult<()>) {
+        assert!(
+            matches!(
+                result,
+                Err(SinpError::Refused {
+                    code: RefusalCode::AuthenticationFailed,
+                    ..
+                })
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn api_key_authentication() {
+        let file = std::env::temp_dir().join(format!("sinp-api-keys-{}", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &file,
+            "# sender key\nclient_1 secret-1\n\nclient_2 secret-2\n",
+        )
+        .unwrap();
+        let authenticators =
+            Authenticators::new().with_api_key(ApiKeyAuthenticator::from_file(&file).unwrap());
+        std::fs::remove_file(&file).unwrap();
+
+        let mut request = sample_request("client_1", AuthMethod::ApiKey);
+        assert_refused(authenticators.authenticate(&request, None));
+
+        request.credential = Some("secret-1".to_string());
+        assert!(authenticators.authenticate(&request, None).is_ok());
+
+        request.credential = Some("secret-2".to_string());
+        assert_refused(authenticators.authenticate(&request, None));
+
+        request.sender.id = "client_3".to_string();
+        assert_refused(authenticators.authenticate(&request, None));
+    }
+
+    #[test]
+    fn invalid_api_key_file() {
+        let file = std::env::temp_dir().join(format!("sinp-api-keys-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&file, "client_1\n").unwrap();
+        let result = ApiKeyAuthenticator::from_file(&file);
+        std::fs::remove_file(&file).unwrap();
+        assert!(matches!(result, Err(SinpError::Validation(_))));
+    }
+
+    #[test]
+    fn token_authentication() {
+        let tokens = TokenAuthenticator::new("shared-secret");
+        let token = tokens.issue("client_1", Duration::from_secs(60));
+        let authenticators =
+            Authenticators::new().with_token(TokenAuthenticator::new("shared-secret"));
+
+        let mut request = sample_request("client_1", AuthMethod::Token);
+        request.credential = Some(token.clone());
+        assert!(authenticators.authenticate(&request, None).is_ok());
+
+        // Issued to someone else
+        request.sender.id = "client_2".to_string();
+        assert_refused(authenticators.authenticate(&request, None));
+
+        // Signed with another secret
+        request.sender.id = "client_1".to_string();
+        request.credential =
+            Some(TokenAuthenticator::new("other").issue("client_1", Duration::from_secs(60)));
+        assert_refused(authenticators.authenticate(&request, None));
+
+        // Tampered claims
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let claims = URL_SAFE_NO_PAD.encode(br#"{"sub":"client_1","exp":9999999999}"#);
+        let header = token.split('.').next().unwrap();
+        request.credential = Some(format!("{}.{}.{}", header, claims, signature));
+        assert_refused(authenticators.authenticate(&request, None));
+
+        request.credential = Some("not-a-token".to_string());
+        assert_refused(authenticators.authenticate(&request, None));
+    }
+
+    #[test]
+    fn expired_token() {
+        let tokens = TokenAuthenticator::new("shared-secret");
+        let token = tokens.issue("client_1", Duration::ZERO);
+        assert_refused(tokens.verify(&token).map(|_| ()));
+    }
+
+    #[test]
+    fn unbounded_token_lifetime() {
+        let tokens = TokenAuthenticator::new("shared-secret");
+        let token = tokens.issue("client_1", Duration::MAX);
+        assert_eq!(tokens.verify(&token).unwrap().sub, "client_1");
+    }
+
+    #[test]
+    fn unconfigured_method_is_refused() {
+        let mut request = sample_request("client_1", AuthMethod::Token);
+        request.credential = Some("anything".to_string());
+        assert_refused(Authenticators::new().authenticate(&request, None));
+
+        let request = sample_request("client_1", AuthMethod::None);
+        assert!(Authenticators::new().authenticate(&request, None).is_ok());
+    }
+
+    #[test]
+    fn downgrade_is_refused() {
+        let authenticators =
+            Authenticators::new().with_token(TokenAuthenticator::new("shared-secret"));
+
+        // Claiming no authentication to skip the token check
+        let request = sample_request("client_1", AuthMethod::None);
+        assert_refused(authenticators.authenticate(&request, None));
+        assert!(authenticators
+            .authenticate(&request, Some("client_1"))
+            .is_ok());
+        assert_refused(authenticators.authenticate(&request, Some("client_2")));
+
+        // Claiming certificate authentication without a certificate
+        let request = sample_request("client_1", AuthMethod::Certificate);
+        assert_refused(authenticators.authenticate(&request, None));
+        assert!(authenticators
+            .authenticate(&request, Some("client_1"))
+            .is_ok());
+    }
+}
@@ -1,24 +1,63 @@
 //! Capability registry for SINP server.
 
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
 use sinp_core::{
-    Capability, Context, Request, SinpResult,
-    interpreter::{InterpretationResult, Interpreter, KeywordInterpreter},
+    parse_capability_id, Capability, Deprecation, RefusalCode, Request, Sender, SinpResult,
+    Thresholds,
+    interpreter::{extract_parameters, InterpretationResult, Interpreter, KeywordInterpreter},
 };
 
+use crate::progress::ProgressSink;
+
 /// Handler function type for capability execution.
-pub type CapabilityHandler = Box<dyn Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync>;
+pub type CapabilityHandler = Arc<dyn Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync>;
+
+/// Handler that reports progress while it runs.
+pub type StreamingHandler =
+    Arc<dyn Fn(&Request, &ProgressSink) -> SinpResult<serde_json::Value> + Send + Sync>;
+
+/// Handler describing the effect of a capability without performing it.
+pub type PreviewHandler = Arc<dyn Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync>;
+
+/// Handler undoing the effect of a capability, given the request it
+/// executed and its result.
+pub type CompensationHandler =
+    Arc<dyn Fn(&Request, &serde_json::Value) -> SinpResult<serde_json::Value> + Send + Sync>;
 
 /// Registry of server capabilities.
+///
+/// Cloning is cheap: handlers and the interpreter are shared.
+#[derive(Clone)]
 pub struct CapabilityRegistry {
     capabilities: HashMap<String, RegisteredCapability>,
-    interpreter: Box<dyn Interpreter>,
+    interpreter: Arc<dyn Interpreter>,
 }
 
+#[derive(Clone)]
 struct RegisteredCapability {
     capability: Capability,
     handler: CapabilityHandler,
+    streaming: Option<StreamingHandler>,
+    preview: Option<PreviewHandler>,
+    compensation: Option<CompensationHandler>,
     reliability: f64,
+    thresholds: Option<Thresholds>,
+    allowed_senders: Option<Vec<String>>,
+    stable: bool,
+    deprecation: Option<Deprecation>,
+    required_inputs: Vec<String>,
+}
+
+impl RegisteredCapability {
+    /// Whether the version is past its sunset date and no longer served.
+    fn is_sunset(&self) -> bool {
+        self.deprecation
+            .as_ref()
+            .is_some_and(|d| d.sunset <= Utc::now())
+    }
 }
 
 impl CapabilityRegistry {
@@ -26,7 +65,7 @@ impl CapabilityRegistry {
     pub fn new() -> Self {
         Self {
             capabilities: HashMap::new(),
-            interpreter: Box::new(KeywordInterpreter::default()),
+            interpreter: Arc::new(KeywordInterpreter::default()),
         }
     }
 
@@ -34,7 +73,7 @@ impl CapabilityRegistry {
     pub fn with_interpreter(interpreter: Box<dyn Interpreter>) -> Self {
         Self {
             capabilities: HashMap::new(),
-            interpreter,
+            interpreter: Arc::from(interpreter),
         }
     }
 
@@ -43,21 +82,254 @@ impl CapabilityRegistry {
     where
         F: Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync + 'static,
     {
+        self.register_handler(capability, Arc::new(handler), reliability);
+    }
+
+    /// Register a capability with a shared handler.
+    pub fn register_handler(
+        &mut self,
+        capability: Capability,
+        handler: CapabilityHandler,
+        reliability: f64,
+    ) {
         self.capabilities.insert(
             capability.id.clone(),
             RegisteredCapability {
                 capability,
-                handler: Box::new(handler),
+                handler,
+                streaming: None,
+                preview: None,
+                compensation: None,
                 reliability: reliability.clamp(0.0, 1.0),
+                thresholds: None,
+                allowed_senders: None,
+                stable: true,
+                deprecation: None,
+                required_inputs: Vec::new(),
             },
         );
     }
 
+    /// Register a capability whose handler reports progress while it runs
+    /// and can stop early when the client cancels.
+    ///
+    /// Without a streaming session the reports are dropped and the handler
+    /// behaves like one registered with [`Self::register`].
+    pub fn register_streaming<F>(&mut self, capability: Capability, handler: F, reliability: f64)
+    where
+        F: Fn(&Request, &ProgressSink) -> SinpResult<serde_json::Value> + Send + Sync + 'static,
+    {
+        let id = capability.id.clone();
+        let streaming: StreamingHandler = Arc::new(handler);
+        let plain = Arc::clone(&streaming);
+        self.register(
+            capability,
+            move |request| plain(request, &ProgressSink::disabled()),
+            reliability,
+        );
+        if let Some(registered) = self.capabilities.get_mut(&id) {
+            registered.streaming = Some(streaming);
+        }
+    }
+
+    /// Register a capability with handler and explicit decision thresholds.
+    ///
+    /// The thresholds replace both the server-wide thresholds and the
+    /// defaults derived from the capability's privacy level and cost.
+    pub fn register_with_thresholds<F>(
+        &mut self,
+        capability: Capability,
+        handler: F,
+        reliability: f64,
+        thresholds: Thresholds,
+    ) where
+        F: Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync + 'static,
+    {
+        let id = capability.id.clone();
+        self.register(capability, handler, reliability);
+        if let Some(registered) = self.capabilities.get_mut(&id) {
+            registered.thresholds = Some(thresholds);
+        }
+    }
+
+    /// Attach a preview handler to a registered capability.
+    ///
+    /// Dry-run requests call it instead of the capability handler.
+    pub fn register_preview<F>(&mut self, id: &str, preview: F) -> SinpResult<()>
+    where
+        F: Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync + 'static,
+    {
+        self.get_mut(id)?.preview = Some(Arc::new(preview));
+        Ok(())
+    }
+
+    /// Attach a compensating handler to a registered capability.
+    ///
+    /// When a later step of a plan fails, it is called to undo this
+    /// capability's completed execution.
+    pub fn register_compensation<F>(&mut self, id: &str, compensation: F) -> SinpResult<()>
+    where
+        F: Fn(&Request, &serde_json::Value) -> SinpResult<serde_json::Value>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.get_mut(id)?.compensation = Some(Arc::new(compensation));
+        Ok(())
+    }
+
+    fn get_mut(&mut self, id: &str) -> SinpResult<&mut RegisteredCapability> {
+        self.capabilities
+            .get_mut(id)
+            .ok_or_else(|| sinp_core::SinpError::Protocol(format!("Capability not found: {}", id)))
+    }
+
+    /// Set explicit decision thresholds for a registered capability.
+    pub fn set_thresholds(&mut self, id: &str, thresholds: Thresholds) -> SinpResult<()> {
+        self.get_mut(id)?.thresholds = Some(thresholds);
+        Ok(())
+    }
+
+    /// Only allow the given sender IDs to use and discover a capability.
+    pub fn restrict<I, S>(&mut self, id: &str, senders: I) -> SinpResult<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.get_mut(id)?.allowed_senders = Some(senders.into_iter().map(Into::into).collect());
+        Ok(())
+    }
+
+    /// Declare inputs a capability cannot run without.
+    ///
+    /// Plan steps missing one are negotiated with the client before the plan
+    /// is proposed.
+    pub fn require_inputs<I, S>(&mut self, id: &str, inputs: I) -> SinpResult<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.get_mut(id)?.required_inputs = inputs.into_iter().map(Into::into).collect();
+        Ok(())
+    }
+
+    /// Inputs a capability cannot run without.
+    pub fn required_inputs(&self, id: &str) -> &[String] {
+        self.capabilities
+            .get(id)
+            .map(|r| r.required_inputs.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Mark a capability version as stable or pre-release.
+    ///
+    /// Pre-release versions are only used when a request's version range
+    /// selects them.
+    pub fn set_stable(&mut self, id: &str, stable: bool) -> SinpResult<()> {
+        self.get_mut(id)?.stable = stable;
+        Ok(())
+    }
+
+    /// Deprecate a capability version.
+    ///
+    /// Responses matching it carry the deprecation until `sunset`, after
+    /// which the version is no longer served.
+    pub fn deprecate(
+        &mut self,
+        id: &str,
+        sunset: DateTime<Utc>,
+        replacement: Option<&str>,
+    ) -> SinpResult<()> {
+        self.get_mut(id)?.deprecation = Some(Deprecation {
+            capability_id: id.to_string(),
+            sunset,
+            replacement: replacement.map(String::from),
+        });
+        Ok(())
+    }
+
+    /// Deprecation of a capability version, if any.
+    pub fn deprecation(&self, id: &str) -> Option<Deprecation> {
+        self.capabilities.get(id).and_then(|r| r.deprecation.clone())
+    }
+
+    /// Select the capability versions a request can be matched against.
+    ///
+    /// Per capability name this is the highest version in the request's
+    /// range for that name, or else the latest stable version. Versions past
+    /// their sunset date are never selected.
+    pub fn resolve(&self, request: &Request) -> Vec<&Capability> {
+        let ranges = request.constraints.as_ref().map(|c| &c.versions);
+
+        let mut by_name: BTreeMap<&str, Vec<(Option<u32>, &RegisteredCapability)>> =
+            BTreeMap::new();
+        for registered in self.capabilities.values().filter(|r| !r.is_sunset()) {
+            let (name, version) = parse_capability_id(&registered.capability.id);
+            by_name.entry(name).or_default().push((version, registered));
+        }
+
+        by_name
+            .into_iter()
+            .filter_map(|(name, mut versions)| {
+                versions.sort_by_key(|(version, _)| std::cmp::Reverse(*version));
+                let selected = match ranges.and_then(|r| r.get(name)) {
+                    Some(range) => versions
+                        .iter()
+                        .find(|(version, _)| version.is_some_and(|v| range.matches(v))),
+                    None => versions
+                        .iter()
+                        .find(|(_, r)| r.stable)
+                        .or_else(|| versions.first()),
+                };
+                selected.map(|(_, r)| &r.capability)
+            })
+            .collect()
+    }
+
+    /// Check whether a sender may use a capability.
+    pub fn is_permitted(&self, id: &str, sender: &Sender) -> bool {
+        self.capabilities.get(id).is_some_and(|r| match r.allowed_senders {
+            Some(ref allowed) => allowed.contains(&sender.id),
+            None => true,
+        })
+    }
+
+    /// Capabilities a sender may use, ordered by ID.
+    pub fn capabilities_for(&self, sender: &Sender) -> Vec<&Capability> {
+        let mut capabilities: Vec<_> = self
+            .capabilities
+            .values()
+            .filter(|r| !r.is_sunset())
+            .map(|r| &r.capability)
+            .filter(|c| self.is_permitted(&c.id, sender))
+            .collect();
+        capabilities.sort_by(|a, b| a.id.cmp(&b.id));
+        capabilities
+    }
+
+    /// Version of a sender's view of the catalog.
+    ///
+    /// Changes whenever a capability visible to the sender is added, removed
+    /// or modified.
+    pub fn catalog_version(&self, sender: &Sender) -> String {
+        let mut hasher = Sha256::new();
+        for capability in self.capabilities_for(sender) {
+            hasher.update(serde_json::to_vec(capability).unwrap_or_default());
+            hasher.update(serde_json::to_vec(&self.deprecation(&capability.id)).unwrap_or_default());
+        }
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
+
     /// Get all capability IDs.
     pub fn capability_ids(&self) -> Vec<String> {
         self.capabilities.keys().cloned().collect()
     }
 
+    /// Get a capability by ID.
+    pub fn capability(&self, id: &str) -> Option<&Capability> {
+        self.capabilities.get(id).map(|r| &r.capability)
+    }
+
     /// Get all capabilities.
     pub fn capabilities(&self) -> Vec<&Capability> {
         self.capabilities.values().map(|r| &r.capability).collect()
@@ -71,29 +343,112 @@ impl CapabilityRegistry {
             .unwrap_or(0.0)
     }
 
-    /// Check policy for request (stub - always returns true).
-    pub fn check_policy(&self, _request: &Request) -> bool {
-        // TODO: Implement policy checks
-        true
+    /// Get decision thresholds for a capability.
+    ///
+    /// Returns the capability's override if one was registered, otherwise
+    /// `base` adjusted for the capability's privacy level and cost.
+    pub fn thresholds_for(&self, id: &str, base: &Thresholds) -> Thresholds {
+        match self.capabilities.get(id) {
+            Some(RegisteredCapability {
+                thresholds: Some(thresholds),
+                ..
+            }) => *thresholds,
+            Some(registered) => base.for_capability(&registered.capability),
+            None => *base,
+        }
     }
 
-    /// Interpret intent using registered capabilities.
-    pub fn interpret(&self, intent: &str, context: &Context) -> InterpretationResult {
-        let caps: Vec<Capability> = self
-            .capabilities
-            .values()
-            .map(|r| r.capability.clone())
-            .collect();
-        self.interpreter.interpret(intent, context, &caps)
+    /// Check policy for executing a capability on behalf of a request.
+    pub fn check_policy(&self, id: &str, request: &Request) -> bool {
+        self.is_permitted(id, &request.sender)
+    }
+
+    /// Interpret a request's intent against the versions it resolves to.
+    pub fn interpret(&self, request: &Request) -> InterpretationResult {
+        let caps: Vec<Capability> = self.resolve(request).into_iter().cloned().collect();
+        self.interpreter
+            .interpret(&request.intent, &request.context, &caps)
     }
 
     /// Execute a capability.
     pub fn execute(&self, id: &str, request: &Request) -> SinpResult<serde_json::Value> {
+        (self.executable(id)?.handler)(request)
+    }
+
+    /// Execute a capability, passing it where to report progress.
+    pub fn execute_with_progress(
+        &self,
+        id: &str,
+        request: &Request,
+        progress: &ProgressSink,
+    ) -> SinpResult<serde_json::Value> {
+        let registered = self.executable(id)?;
+        match registered.streaming {
+            Some(ref streaming) => streaming(request, progress),
+            None => (registered.handler)(request),
+        }
+    }
+
+    /// Look up a capability to execute, refusing versions past their sunset.
+    ///
+    /// Executions decided earlier, such as scheduled runs or approved
+    /// requests, may reach a version after it was sunset.
+    fn executable(&self, id: &str) -> SinpResult<&RegisteredCapability> {
+        let registered = self
+            .capabilities
+            .get(id)
+            .ok_or_else(|| sinp_core::SinpError::Protocol(format!("Capability not found: {}", id)))?;
+        match registered.deprecation {
+            Some(ref deprecation) if registered.is_sunset() => {
+                let replacement = match deprecation.replacement {
+                    Some(ref replacement) => format!("use {} instead", replacement),
+                    None => "no replacement is available".to_string(),
+                };
+                Err(sinp_core::SinpError::Refused {
+                    code: RefusalCode::CapabilityMissing,
+                    reason: format!(
+                        "Capability {} was sunset on {}; {}",
+                        id, deprecation.sunset, replacement
+                    ),
+                })
+            }
+            _ => Ok(registered),
+        }
+    }
+
+    /// Describe the effect of a capability without executing it.
+    ///
+    /// Returns `None` if the capability has no preview handler.
+    pub fn preview(&self, id: &str, request: &Request) -> SinpResult<Option<serde_json::Value>> {
+        let registered = self
+            .capabilities
+            .get(id)
+            .ok_or_else(|| sinp_core::SinpError::Protocol(format!("Capability not found: {}", id)))?;
+        registered
+            .preview
+            .as_ref()
+            .map(|preview| preview(request))
+            .transpose()
+    }
+
+    /// Undo an execution of a capability given its request and result.
+    ///
+    /// Returns `None` if the capability has no compensating handler.
+    pub fn compensate(
+        &self,
+        id: &str,
+        request: &Request,
+        result: &serde_json::Value,
+    ) -> SinpResult<Option<serde_json::Value>> {
         let registered = self
             .capabilities
             .get(id)
             .ok_or_else(|| sinp_core::SinpError::Protocol(format!("Capability not found: {}", id)))?;
-        (registered.handler)(request)
+        registered
+            .compensation
+            .as_ref()
+            .map(|compensation| compensation(request, result))
+            .transpose()
     }
 }
 
@@ -103,10 +458,71 @@ impl Default for CapabilityRegistry {
     }
 }
 
+/// Inputs of a capability for a request: those extracted from the intent,
+/// overridden by those the request gives explicitly.
+pub fn request_parameters(
+    capability: &Capability,
+    request: &Request,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut parameters = extract_parameters(&request.intent, &capability.inputs);
+    parameters.extend(request.parameters.clone());
+    parameters
+}
+
+/// Request as passed to handlers outside the server process: capability
+/// ID, intent, parameters, context, sender and conversation.
+pub fn handler_input(capability: &Capability, request: &Request) -> serde_json::Value {
+    serde_json::json!({
+        "capability_id": capability.id,
+        "intent": request.intent,
+        "parameters": request_parameters(capability, request),
+        "context": request.context,
+        "sender": request.sender.id,
+        "conversation_id": request.conversation_id,
+    })
+}
+
+/// Live registry shared by all connections.
+///
+/// Each frame is handled against a snapshot, so replacing the registry
+/// never affects a request already being processed.
+#[derive(Clone)]
+pub struct SharedRegistry {
+    current: Arc<RwLock<Arc<CapabilityRegistry>>>,
+    replaced: Arc<tokio::sync::watch::Sender<()>>,
+}
+
+impl SharedRegistry {
+    /// Share a registry.
+    pub fn new(registry: CapabilityRegistry) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(registry))),
+            replaced: Arc::new(tokio::sync::watch::Sender::new(())),
+        }
+    }
+
+    /// Snapshot of the current registry.
+    pub fn current(&self) -> Arc<CapabilityRegistry> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Atomically replace the registry.
+    pub fn replace(&self, registry: CapabilityRegistry) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(registry);
+        self.replaced.send_replace(());
+    }
+
+    /// Receiver notified whenever the registry is replaced.
+    pub fn changes(&self) -> tokio::sync::watch::Receiver<()> {
+        self.replaced.subscribe()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sinp_core::message::{AuthMethod, ContextType, Sender};
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+    use sinp_core::{Constraints, VersionRange};
 
     fn sample_capability() -> Capability {
         Capability {
@@ -144,4 +560,251 @@ mod tests {
         let result = registry.execute("test:v1", &request).unwrap();
         assert_eq!(result["status"], "ok");
     }
+
+    #[test]
+    fn per_capability_thresholds() {
+        let mut registry = CapabilityRegistry::new();
+        let base = Thresholds::default();
+
+        registry.register(sample_capability(), |_req| Ok(serde_json::Value::Null), 0.9);
+        let derived = registry.thresholds_for("test:v1", &base);
+        assert!((derived.tau_exec - 0.87).abs() < 1e-9);
+
+        let mut email = sample_capability();
+        email.id = "send_email:v1".to_string();
+        registry.register_with_thresholds(
+            email,
+            |_req| Ok(serde_json::Value::Null),
+            0.9,
+            Thresholds::new(0.95, 0.6, 0.8),
+        );
+        assert_eq!(
+            registry.thresholds_for("send_email:v1", &base),
+            Thresholds::new(0.95, 0.6, 0.8)
+        );
+
+        assert_eq!(registry.thresholds_for("missing:v1", &base), base);
+    }
+
+    #[test]
+    fn preview_handler() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            sample_capability(),
+            |_req| panic!("handler must not run for a preview"),
+            0.9,
+        );
+
+        let ctx = Context {
+            context_type: ContextType::Transcript,
+            content: "test".to_string(),
+            semantic_hash: "hash".to_string(),
+        };
+        let sender = Sender {
+            id: "test".to_string(),
+            auth_method: AuthMethod::Token,
+        };
+        let request = Request::new(sender, "test", 0.9, ctx);
+
+        assert_eq!(registry.preview("test:v1", &request).unwrap(), None);
+
+        registry
+            .register_preview("test:v1", |req| Ok(serde_json::json!({"would_echo": req.intent})))
+            .unwrap();
+        let effect = registry.preview("test:v1", &request).unwrap().unwrap();
+        assert_eq!(effect["would_echo"], "test");
+
+        assert!(registry.register_preview("missing:v1", |_| Ok(serde_json::Value::Null)).is_err());
+    }
+
+    #[test]
+    fn streaming_handler() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register_streaming(
+            sample_capability(),
+            |req, progress| {
+                progress.report(0.5, "halfway");
+                Ok(serde_json::json!({"echo": req.intent}))
+            },
+            0.9,
+        );
+
+        let ctx = Context {
+            context_type: ContextType::Transcript,
+            content: "test".to_string(),
+            semantic_hash: "hash".to_string(),
+        };
+        let sender = Sender {
+            id: "test".to_string(),
+            auth_method: AuthMethod::Token,
+        };
+        let request = Request::new(sender, "test", 0.9, ctx);
+
+        // Plain execution drops the reports
+        assert_eq!(registry.execute("test:v1", &request).unwrap()["echo"], "test");
+
+        let (sink, mut updates) = ProgressSink::channel(request.message_id, 4);
+        let result = registry.execute_with_progress("test:v1", &request, &sink).unwrap();
+        assert_eq!(result["echo"], "test");
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.in_response_to, request.message_id);
+        assert_eq!(update.message.as_deref(), Some("halfway"));
+    }
+
+    #[test]
+    fn restricted_capability() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            sample_capability(),
+            |_req| Ok(serde_json::json!({"status": "ok"})),
+            0.9,
+        );
+        let ctx = Context {
+            context_type: ContextType::Transcript,
+            content: String::new(),
+            semantic_hash: String::new(),
+        };
+        let mut request = Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            "test",
+            0.9,
+            ctx,
+        );
+        let before = registry.catalog_version(&request.sender);
+        assert!(registry.check_policy("test:v1", &request));
+
+        registry.restrict("test:v1", ["admin"]).unwrap();
+        assert!(!registry.check_policy("test:v1", &request));
+        assert!(registry.capabilities_for(&request.sender).is_empty());
+        assert_ne!(registry.catalog_version(&request.sender), before);
+
+        request.sender.id = "admin".to_string();
+        assert!(registry.check_policy("test:v1", &request));
+        assert!(registry.restrict("missing:v1", ["admin"]).is_err());
+    }
+
+    fn versioned_registry() -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        for id in ["echo:v1", "echo:v2", "echo:v3", "help"] {
+            let mut capability = sample_capability();
+            capability.id = id.to_string();
+            registry.register(capability, |_req| Ok(serde_json::json!({})), 0.9);
+        }
+        registry.set_stable("echo:v3", false).unwrap();
+        registry
+    }
+
+    fn versioned_request(range: Option<&str>) -> Request {
+        let mut request = Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            "test",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        if let Some(range) = range {
+            let mut constraints = Constraints::default();
+            constraints
+                .versions
+                .insert("echo".to_string(), range.parse::<VersionRange>().unwrap());
+            request.constraints = Some(constraints);
+        }
+        request
+    }
+
+    fn resolved_ids(registry: &CapabilityRegistry, request: &Request) -> Vec<String> {
+        registry
+            .resolve(request)
+            .into_iter()
+            .map(|c| c.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn resolve_versions() {
+        let registry = versioned_registry();
+
+        // Latest stable version; unversioned IDs are kept as they are
+        assert_eq!(
+            resolved_ids(&registry, &versioned_request(None)),
+            vec!["echo:v2", "help"]
+        );
+        // Highest version in range, pre-releases included
+        assert_eq!(
+            resolved_ids(&registry, &versioned_request(Some(">=2"))),
+            vec!["echo:v3", "help"]
+        );
+        assert_eq!(
+            resolved_ids(&registry, &versioned_request(Some("<2"))),
+            vec!["echo:v1", "help"]
+        );
+        // Nothing in range
+        assert_eq!(
+            resolved_ids(&registry, &versioned_request(Some(">5"))),
+            vec!["help"]
+        );
+
+        let mut request = versioned_request(Some("1"));
+        request.intent = "echo".to_string();
+        let result = registry.interpret(&request);
+        assert_eq!(result.capability.unwrap().id, "echo:v1");
+    }
+
+    #[test]
+    fn deprecated_versions() {
+        let mut registry = versioned_registry();
+        let sunset = Utc::now() + chrono::Duration::days(30);
+        registry.deprecate("echo:v2", sunset, Some("echo:v3")).unwrap();
+
+        let deprecation = registry.deprecation("echo:v2").unwrap();
+        assert_eq!(deprecation.sunset, sunset);
+        assert_eq!(deprecation.replacement.as_deref(), Some("echo:v3"));
+        assert_eq!(
+            resolved_ids(&registry, &versioned_request(None)),
+            vec!["echo:v2", "help"]
+        );
+
+        // Past the sunset the version is gone
+        registry
+            .deprecate("echo:v2", Utc::now() - chrono::Duration::days(1), None)
+            .unwrap();
+        assert_eq!(
+            resolved_ids(&registry, &versioned_request(None)),
+            vec!["echo:v1", "help"]
+        );
+        let sender = versioned_request(None).sender;
+        assert!(registry
+            .capabilities_for(&sender)
+            .iter()
+            .all(|c| c.id != "echo:v2"));
+
+        // Executions decided before the sunset are refused too
+        registry
+            .deprecate(
+                "echo:v2",
+                Utc::now() - chrono::Duration::days(1),
+                Some("echo:v3"),
+            )
+            .unwrap();
+        let err = registry
+            .execute("echo:v2", &versioned_request(None))
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            sinp_core::SinpError::Refused {
+                code: RefusalCode::CapabilityMissing,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("use echo:v3"), "{}", err);
+    }
 }
@@ -1,6 +1,8 @@
 //! Server configuration for SINP.
 
-use sinp_core::Thresholds;
+use sinp_core::{Feature, Hello, Thresholds};
+
+use crate::approval::ApprovalConfig;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -22,6 +24,27 @@ pub struct ServerConfig {
     pub write_timeout: Duration,
     /// Max message size in bytes.
     pub max_message_size: usize,
+    /// Include the Φ breakdown in responses.
+    pub explain_decisions: bool,
+    /// Operator approval for sensitive capabilities (disabled if `None`).
+    pub approval: Option<ApprovalConfig>,
+    /// Versions, encodings and features announced in HELLO.
+    pub hello: Hello,
+    /// Maximum capabilities per discovery page.
+    pub discovery_page_size: usize,
+    /// How long responses to requests with an idempotency key are kept.
+    pub idempotency_retention: Duration,
+    /// Progress updates buffered per request before the handler waits for
+    /// the client to catch up.
+    pub progress_buffer: usize,
+    /// How long results of background jobs are kept after they complete.
+    pub job_retention: Duration,
+    /// File background jobs are persisted to; kept in memory if `None`.
+    pub job_store: Option<PathBuf>,
+    /// Events buffered per subscribed connection before it misses some.
+    pub event_buffer: usize,
+    /// File scheduled executions are persisted to; kept in memory if `None`.
+    pub schedule_store: Option<PathBuf>,
 }
 
 impl Default for ServerConfig {
@@ -34,6 +57,16 @@ impl Default for ServerConfig {
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
             max_message_size: 1024 * 1024, // 1MB
+            explain_decisions: false,
+            approval: None,
+            hello: Hello::default().with_features([Feature::Streaming]),
+            discovery_page_size: 50,
+            idempotency_retention: Duration::from_secs(24 * 60 * 60),
+            progress_buffer: 16,
+            job_retention: Duration::from_secs(7 * 24 * 60 * 60),
+            job_store: None,
+            event_buffer: 64,
+            schedule_store: None,
         }
     }
 }
@@ -52,15 +85,84 @@ impl ServerConfig {
         self.tls = Some(TlsConfig {
             cert_path,
             key_path,
+            client_ca_path: None,
+            require_client_cert: false,
         });
         self
     }
 
+    /// Verify client certificates against a CA bundle (PEM).
+    ///
+    /// Must be called after [`ServerConfig::with_tls`]; without TLS there are
+    /// no client certificates and the setting has no effect. If `required` is
+    /// false, clients without a certificate are still accepted.
+    pub fn with_client_auth(mut self, client_ca_path: PathBuf, required: bool) -> Self {
+        if let Some(ref mut tls) = self.tls {
+            tls.client_ca_path = Some(client_ca_path);
+            tls.require_client_cert = required;
+        }
+        self
+    }
+
     /// Set custom thresholds.
     pub fn with_thresholds(mut self, thresholds: Thresholds) -> Self {
         self.thresholds = thresholds;
         self
     }
+
+    /// Require operator approval before executing sensitive capabilities.
+    pub fn with_approval(mut self, approval: ApprovalConfig) -> Self {
+        self.approval = Some(approval);
+        self
+    }
+
+    /// Set what the server announces in HELLO.
+    pub fn with_hello(mut self, hello: Hello) -> Self {
+        self.hello = hello;
+        self
+    }
+
+    /// Set how long responses to requests with an idempotency key are kept.
+    pub fn with_idempotency_retention(mut self, retention: Duration) -> Self {
+        self.idempotency_retention = retention;
+        self
+    }
+
+    /// Set how many progress updates are buffered per request.
+    pub fn with_progress_buffer(mut self, updates: usize) -> Self {
+        self.progress_buffer = updates;
+        self
+    }
+
+    /// Set how many events are buffered per subscribed connection.
+    pub fn with_event_buffer(mut self, events: usize) -> Self {
+        self.event_buffer = events;
+        self
+    }
+
+    /// Set how long results of background jobs are kept.
+    pub fn with_job_retention(mut self, retention: Duration) -> Self {
+        self.job_retention = retention;
+        self
+    }
+
+    /// Persist background jobs to a file, so results survive a restart.
+    pub fn with_job_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.job_store = Some(path.into());
+        self
+    }
+
+    /// Persist scheduled executions to a file, so they survive a restart.
+    pub fn with_schedule_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.schedule_store = Some(path.into());
+        self
+    }
+
+    /// Include decision explanations in responses.
+    pub fn with_explanations(mut self, enabled: bool) -> Self {
+        self.explain_decisions = enabled;
+        self
+    }
 }
 
 /// TLS configuration.
@@ -70,6 +172,10 @@ pub struct TlsConfig {
     pub cert_path: PathBuf,
     /// Path to private key file (PEM).
     pub key_path: PathBuf,
+    /// CA bundle (PEM) for verifying client certificates (disabled if `None`).
+    pub client_ca_path: Option<PathBuf>,
+    /// Reject clients that present no certificate.
+    pub require_client_cert: bool,
 }
 
 #[cfg(test)]
@@ -81,14 +187,34 @@ mod tests {
         let config = ServerConfig::default();
         assert_eq!(config.bind_addr.port(), 9000);
         assert!(config.tls.is_none());
+        assert!(!config.explain_decisions);
+        assert!(config.approval.is_none());
+        assert_eq!(config.idempotency_retention, Duration::from_secs(86400));
+        assert!(config.job_store.is_none());
+        assert!(config.schedule_store.is_none());
     }
 
     #[test]
     fn custom_config() {
         let config = ServerConfig::with_addr("0.0.0.0:8080".parse::<SocketAddr>().unwrap())
-            .with_thresholds(Thresholds::new(0.9, 0.6, 0.6));
+            .with_thresholds(Thresholds::new(0.9, 0.6, 0.6))
+            .with_explanations(true);
 
         assert_eq!(config.bind_addr.port(), 8080);
         assert_eq!(config.thresholds.tau_exec, 0.9);
+        assert!(config.explain_decisions);
+    }
+
+    #[test]
+    fn client_auth_config() {
+        let config = ServerConfig::default().with_client_auth("ca.pem".into(), true);
+        assert!(config.tls.is_none());
+
+        let config = ServerConfig::default()
+            .with_tls("cert.pem".into(), "key.pem".into())
+            .with_client_auth("ca.pem".into(), true);
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.client_ca_path, Some(PathBuf::from("ca.pem")));
+        assert!(tls.require_client_cert);
     }
 }
@@ -0,0 +1,194 @@
+//! Capability discovery for SINP server.
+//!
+//! Serves the sender's view of the capability catalog in pages. Cursors are
+//! bound to the catalog version, so a catalog that changes mid-listing is
+//! reported instead of yielding a mix of old and new pages.
+
+use sinp_core::{Catalog, DiscoveryRequest, SinpError, SinpResult};
+
+use crate::capability::CapabilityRegistry;
+
+/// Build the catalog page answering a discovery request.
+pub fn discover(
+    registry: &CapabilityRegistry,
+    request: &DiscoveryRequest,
+    max_page_size: usize,
+) -> SinpResult<Catalog> {
+    let version = registry.catalog_version(&request.sender);
+    let capabilities = registry.capabilities_for(&request.sender);
+    let total = capabilities.len();
+
+    if request.if_none_match.as_deref() == Some(version.as_str()) && request.cursor.is_none() {
+        return Ok(Catalog {
+            in_response_to: request.message_id,
+            version,
+            capabilities: Vec::new(),
+            total,
+            deprecations: Vec::new(),
+            next_cursor: None,
+            not_modified: true,
+        });
+    }
+
+    let offset = match request.cursor {
+        Some(ref cursor) => parse_cursor(cursor, &version)?,
+        None => 0,
+    };
+    if offset > total {
+        return Err(SinpError::Validation(format!(
+            "Cursor offset {} is past the end of the catalog",
+            offset
+        )));
+    }
+    let limit = request
+        .limit
+        .unwrap_or(max_page_size)
+        .clamp(1, max_page_size.max(1));
+    let end = offset.saturating_add(limit).min(total);
+
+    let page = capabilities.get(offset..end).unwrap_or_default();
+    Ok(Catalog {
+        in_response_to: request.message_id,
+        capabilities: page.iter().map(|c| (*c).clone()).collect(),
+        total,
+        deprecations: page
+            .iter()
+            .filter_map(|c| registry.deprecation(&c.id))
+            .collect(),
+        next_cursor: (end < total).then(|| format!("{}:{}", version, end)),
+        version,
+        not_modified: false,
+    })
+}
+
+/// Parse a `<version>:<offset>` cursor issued for the current version.
+fn parse_cursor(cursor: &str, version: &str) -> SinpResult<usize> {
+    let (cursor_version, offset) = cursor
+        .split_once(':')
+        .ok_or_else(|| SinpError::Validation(format!("Invalid cursor: {}", cursor)))?;
+    if cursor_version != version {
+        return Err(SinpError::Protocol(
+            "Catalog changed during listing; restart discovery".to_string(),
+        ));
+    }
+    offset
+        .parse()
+        .map_err(|_| SinpError::Validation(format!("Invalid cursor: {}", cursor)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Sender};
+    use sinp_core::Capability;
+
+    fn sample_registry(count: usize) -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        for i in 0..count {
+            registry.register(
+                Capability {
+                    id: format!("cap_{}:v1", i),
+                    description: format!("Capability {}", i),
+                    inputs: vec![],
+                    privacy_level: "public".to_string(),
+                    cost_units: 0.1,
+                },
+                |_req| Ok(serde_json::json!({})),
+                0.9,
+            );
+        }
+        registry
+    }
+
+    fn sender(id: &str) -> Sender {
+        Sender {
+            id: id.to_string(),
+            auth_method: AuthMethod::None,
+        }
+    }
+
+    #[test]
+    fn pagination() {
+        let registry = sample_registry(5);
+        let mut request = DiscoveryRequest::new(sender("client_1"));
+        request.limit = Some(2);
+
+        let mut ids = Vec::new();
+        loop {
+            let page = discover(&registry, &request, 50).unwrap();
+            assert_eq!(page.total, 5);
+            assert!(page.capabilities.len() <= 2);
+            ids.extend(page.capabilities.into_iter().map(|c| c.id));
+            match page.next_cursor {
+                Some(cursor) => request.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(
+            ids,
+            vec!["cap_0:v1", "cap_1:v1", "cap_2:v1", "cap_3:v1", "cap_4:v1"]
+        );
+
+        // The server caps the page size
+        request.cursor = None;
+        request.limit = Some(100);
+        let page = discover(&registry, &request, 3).unwrap();
+        assert_eq!(page.capabilities.len(), 3);
+    }
+
+    #[test]
+    fn filtered_by_policy() {
+        let mut registry = sample_registry(3);
+        registry.restrict("cap_1:v1", ["admin"]).unwrap();
+
+        let request = DiscoveryRequest::new(sender("client_1"));
+        let page = discover(&registry, &request, 50).unwrap();
+        assert_eq!(page.total, 2);
+        assert!(page.capabilities.iter().all(|c| c.id != "cap_1:v1"));
+
+        let admin = discover(&registry, &DiscoveryRequest::new(sender("admin")), 50).unwrap();
+        assert_eq!(admin.total, 3);
+        assert_ne!(admin.version, page.version);
+    }
+
+    #[test]
+    fn not_modified_and_stale_cursor() {
+        let registry = sample_registry(3);
+        let mut request = DiscoveryRequest::new(sender("client_1"));
+        request.limit = Some(1);
+        let page = discover(&registry, &request, 50).unwrap();
+
+        request.if_none_match = Some(page.version.clone());
+        let cached = discover(&registry, &request, 50).unwrap();
+        assert!(cached.not_modified);
+        assert!(cached.capabilities.is_empty());
+
+        // A cursor from an older catalog is refused
+        let changed = sample_registry(4);
+        request.if_none_match = None;
+        request.cursor = page.next_cursor;
+        assert!(matches!(
+            discover(&changed, &request, 50),
+            Err(SinpError::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn cursor_past_the_end() {
+        let registry = sample_registry(3);
+        let version = registry.catalog_version(&sender("client_1"));
+        let mut request = DiscoveryRequest::new(sender("client_1"));
+
+        request.cursor = Some(format!("{}:{}", version, usize::MAX));
+        assert!(matches!(
+            discover(&registry, &request, 50),
+            Err(SinpError::Validation(_))
+        ));
+
+        // The end of the catalog is an empty last page
+        request.cursor = Some(format!("{}:3", version));
+        let page = discover(&registry, &request, 50).unwrap();
+        assert!(page.capabilities.is_empty());
+        assert!(page.next_cursor.is_none());
+    }
+}
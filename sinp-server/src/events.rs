@@ -0,0 +1,216 @@
+//! Event subscriptions.
+//!
+//! A client subscribes on its connection to kinds of [`Event`] about its
+//! sender. The server then pushes each matching event as it happens, on the
+//! same connection and unrelated to any request.
+
+use std::sync::Arc;
+
+use tokio::sync::{broadcast, watch};
+
+use sinp_core::{Event, EventKind, Job, Sender, Subscribe};
+
+use crate::capability::SharedRegistry;
+use crate::jobs::JobStore;
+
+/// Events published for one sender.
+#[derive(Debug, Clone)]
+struct Notice {
+    recipient: String,
+    event: Event,
+}
+
+/// Server-wide channel for events raised by connections.
+///
+/// Cloning yields another handle to the same bus.
+#[derive(Clone)]
+pub struct EventBus {
+    notices: Arc<broadcast::Sender<Notice>>,
+}
+
+impl EventBus {
+    /// Create a bus buffering `capacity` events per slow subscriber.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            notices: Arc::new(broadcast::Sender::new(capacity)),
+        }
+    }
+
+    /// Publish an event about the sender with `recipient` as id.
+    pub fn publish(&self, recipient: &str, event: Event) {
+        // Nobody may be subscribed
+        let _ = self.notices.send(Notice {
+            recipient: recipient.to_string(),
+            event,
+        });
+    }
+}
+
+/// Events a connection subscribed to.
+pub struct Subscription {
+    sender: Sender,
+    events: Vec<EventKind>,
+    jobs: broadcast::Receiver<Job>,
+    registry: SharedRegistry,
+    catalog: watch::Receiver<()>,
+    catalog_version: String,
+    notices: broadcast::Receiver<Notice>,
+}
+
+impl Subscription {
+    /// Start receiving the events `subscribe` asks for.
+    pub fn new(
+        subscribe: &Subscribe,
+        registry: &SharedRegistry,
+        jobs: &JobStore,
+        bus: &EventBus,
+    ) -> Self {
+        let mut events = Vec::new();
+        for kind in &subscribe.events {
+            if !events.contains(kind) {
+                events.push(*kind);
+            }
+        }
+        Self {
+            sender: subscribe.sender.clone(),
+            events,
+            jobs: jobs.completions(),
+            registry: registry.clone(),
+            catalog: registry.changes(),
+            catalog_version: registry.current().catalog_version(&subscribe.sender),
+            notices: bus.notices.subscribe(),
+        }
+    }
+
+    /// Kinds of events subscribed to, without duplicates.
+    pub fn events(&self) -> &[EventKind] {
+        &self.events
+    }
+
+    /// Wait for the next event for the subscribed sender.
+    ///
+    /// Cancel-safe: an event is never lost by dropping the future.
+    pub async fn next(&mut self) -> Event {
+        let jobs = self.events.contains(&EventKind::JobCompleted);
+        let catalog = self.events.contains(&EventKind::CatalogChanged);
+        let conversations = self.events.contains(&EventKind::ConversationUpdated);
+        loop {
+            tokio::select! {
+                job = self.jobs.recv(), if jobs => match job {
+                    Ok(job) if job.sender_id == self.sender.id => {
+                        return Event::JobCompleted { job: Box::new(job) };
+                    }
+                    Ok(_) => {}
+                    Err(e) => self.missed(e).await,
+                },
+                changed = self.catalog.changed(), if catalog => {
+                    if changed.is_err() {
+                        std::future::pending::<()>().await;
+                    }
+                    // Only a change in what the sender sees is reported
+                    let version = self.registry.current().catalog_version(&self.sender);
+                    if version != self.catalog_version {
+                        self.catalog_version = version.clone();
+                        return Event::CatalogChanged { version };
+                    }
+                }
+                notice = self.notices.recv(), if conversations => match notice {
+                    Ok(notice) if notice.recipient == self.sender.id => {
+                        return notice.event;
+                    }
+                    Ok(_) => {}
+                    Err(e) => self.missed(e).await,
+                },
+                else => std::future::pending::<()>().await,
+            }
+        }
+    }
+
+    /// Log events dropped for falling behind, or wait forever once closed.
+    async fn missed(&self, error: broadcast::error::RecvError) {
+        match error {
+            broadcast::error::RecvError::Lagged(skipped) => {
+                tracing::warn!("Subscriber {} missed {} events", self.sender.id, skipped);
+            }
+            broadcast::error::RecvError::Closed => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capability::CapabilityRegistry;
+    use sinp_core::message::AuthMethod;
+    use sinp_core::{Capability, ServerState};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    fn sender(id: &str) -> Sender {
+        Sender {
+            id: id.to_string(),
+            auth_method: AuthMethod::None,
+        }
+    }
+
+    fn catalog(ids: &[&str]) -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        for id in ids {
+            registry.register(
+                Capability {
+                    id: id.to_string(),
+                    description: "Echo message".to_string(),
+                    inputs: vec![],
+                    privacy_level: "public".to_string(),
+                    cost_units: 0.1,
+                },
+                |_| Ok(serde_json::Value::Null),
+                0.95,
+            );
+        }
+        registry
+    }
+
+    #[tokio::test]
+    async fn events_are_filtered_by_kind_and_sender() {
+        let registry = SharedRegistry::new(catalog(&["echo:v1"]));
+        let jobs = JobStore::new(Duration::from_secs(60));
+        let bus = EventBus::new(16);
+        let subscribe = Subscribe::new(
+            sender("client_1"),
+            [
+                EventKind::ConversationUpdated,
+                EventKind::CatalogChanged,
+                EventKind::ConversationUpdated,
+            ],
+        );
+        let mut subscription = Subscription::new(&subscribe, &registry, &jobs, &bus);
+        assert_eq!(
+            subscription.events(),
+            [EventKind::ConversationUpdated, EventKind::CatalogChanged]
+        );
+
+        let conversation_id = Uuid::new_v4();
+        let updated = |state| Event::ConversationUpdated {
+            conversation_id,
+            state,
+        };
+        bus.publish("client_2", updated(ServerState::Done));
+        bus.publish("client_1", updated(ServerState::Negotiating));
+        assert_eq!(subscription.next().await, updated(ServerState::Negotiating));
+
+        registry.replace(catalog(&["echo:v1", "echo:v2"]));
+        let Event::CatalogChanged { version } = subscription.next().await else {
+            panic!("expected a catalog change");
+        };
+        assert_eq!(
+            version,
+            registry.current().catalog_version(&sender("client_1"))
+        );
+
+        // Not subscribed to job completions
+        let unsubscribed =
+            tokio::time::timeout(Duration::from_millis(20), subscription.next()).await;
+        assert!(unsubscribed.is_err());
+    }
+}
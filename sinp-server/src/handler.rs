@@ -1,23 +1,51 @@
 //! TCP/TLS connection handler for SINP server.
 
+use rustls::server::WebPkiClientVerifier;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use tokio_rustls::TlsAcceptor;
 
-use sinp_core::{Request, Response, SinpError, SinpResult};
+use sinp_core::handshake::{FrameError, HelloReject};
+use sinp_core::{
+    Cancel, CancelAck, ClientFrame, Event, Feature, JobList, JobQuery, Progress, RefusalCode,
+    Request, Response, ScheduleList, ServerFrame, ServerState, Session, SinpError, SinpResult,
+    Subscribed,
+};
 
-use crate::capability::CapabilityRegistry;
+use crate::approval::{ApprovalDecision, ApprovalQueue};
+use crate::auth::{self, Authenticators};
+use crate::capability::{CapabilityRegistry, SharedRegistry};
 use crate::config::ServerConfig;
+use crate::discovery;
+use crate::events::{EventBus, Subscription};
+use crate::idempotency::{Begin, IdempotencyStore};
+use crate::jobs::JobStore;
+use crate::progress::{self, CancellationToken, ProgressSink};
+use crate::scheduler::Scheduler;
 use crate::state_machine::ServerStateMachine;
 
 /// SINP Server.
 pub struct Server {
-    config: ServerConfig,
-    registry: Arc<CapabilityRegistry>,
+    shared: Shared,
     tls_acceptor: Option<TlsAcceptor>,
 }
 
+/// Server-wide state handed to every connection.
+#[derive(Clone)]
+struct Shared {
+    config: ServerConfig,
+    registry: SharedRegistry,
+    approvals: ApprovalQueue,
+    authenticators: Arc<Authenticators>,
+    idempotency: IdempotencyStore,
+    jobs: JobStore,
+    events: EventBus,
+    schedules: Scheduler,
+}
+
 impl Server {
     /// Create a new server.
     pub fn new(config: ServerConfig, registry: CapabilityRegistry) -> SinpResult<Self> {
@@ -27,13 +55,56 @@ impl Server {
             None
         };
 
+        let jobs = match config.job_store {
+            Some(ref path) => JobStore::open(path, config.job_retention)?,
+            None => JobStore::new(config.job_retention),
+        };
+        let schedules = match config.schedule_store {
+            Some(ref path) => Scheduler::open(path)?,
+            None => Scheduler::new(),
+        };
+
         Ok(Self {
-            config,
-            registry: Arc::new(registry),
+            shared: Shared {
+                idempotency: IdempotencyStore::new(config.idempotency_retention),
+                jobs,
+                events: EventBus::new(config.event_buffer),
+                schedules,
+                config,
+                registry: SharedRegistry::new(registry),
+                approvals: ApprovalQueue::new(),
+                authenticators: Arc::new(Authenticators::new()),
+            },
             tls_acceptor,
         })
     }
 
+    /// Set the authenticators for token and API-key senders.
+    pub fn with_authenticators(mut self, authenticators: Authenticators) -> Self {
+        self.shared.authenticators = Arc::new(authenticators);
+        self
+    }
+
+    /// Handle to the live capability registry, for reloading it.
+    pub fn registry(&self) -> SharedRegistry {
+        self.shared.registry.clone()
+    }
+
+    /// Handle to the queue of executions awaiting operator approval.
+    pub fn approvals(&self) -> ApprovalQueue {
+        self.shared.approvals.clone()
+    }
+
+    /// Handle to the background jobs.
+    pub fn jobs(&self) -> JobStore {
+        self.shared.jobs.clone()
+    }
+
+    /// Handle to the scheduled executions.
+    pub fn schedules(&self) -> Scheduler {
+        self.shared.schedules.clone()
+    }
+
     /// Create TLS acceptor from config.
     fn create_tls_acceptor(tls_config: &crate::config::TlsConfig) -> SinpResult<TlsAcceptor> {
         use rustls_pemfile::{certs, private_key};
@@ -53,8 +124,37 @@ impl Server {
             .map_err(|e| SinpError::Transport(format!("Failed to read key: {}", e)))?
             .ok_or_else(|| SinpError::Transport("No private key found".to_string()))?;
 
-        let config = rustls::ServerConfig::builder()
-            .with_no_client_auth()
+        let builder = rustls::ServerConfig::builder();
+        let builder = match tls_config.client_ca_path {
+            Some(ref ca_path) => {
+                let ca_file = File::open(ca_path).map_err(|e| {
+                    SinpError::Transport(format!("Failed to open client CA: {}", e))
+                })?;
+                let mut roots = rustls::RootCertStore::empty();
+                for cert in rustls_pemfile::certs(&mut BufReader::new(ca_file)) {
+                    let cert = cert.map_err(|e| {
+                        SinpError::Transport(format!("Failed to read client CA: {}", e))
+                    })?;
+                    roots.add(cert).map_err(|e| {
+                        SinpError::Transport(format!("Invalid client CA certificate: {}", e))
+                    })?;
+                }
+
+                let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+                let verifier = if tls_config.require_client_cert {
+                    verifier
+                } else {
+                    verifier.allow_unauthenticated()
+                };
+                let verifier = verifier
+                    .build()
+                    .map_err(|e| SinpError::Transport(format!("Client verifier error: {}", e)))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
             .with_single_cert(certs, key)
             .map_err(|e| SinpError::Transport(format!("TLS config error: {}", e)))?;
 
@@ -63,11 +163,17 @@ impl Server {
 
     /// Run the server.
     pub async fn run(self) -> SinpResult<()> {
-        let listener = TcpListener::bind(&self.config.bind_addr)
+        let listener = TcpListener::bind(&self.shared.config.bind_addr)
             .await
             .map_err(|e| SinpError::Transport(format!("Failed to bind: {}", e)))?;
 
-        tracing::info!("SINP server listening on {}", self.config.bind_addr);
+        tracing::info!("SINP server listening on {}", self.shared.config.bind_addr);
+        tokio::spawn(
+            self.shared
+                .schedules
+                .clone()
+                .run(self.shared.registry.clone(), self.shared.jobs.clone()),
+        );
 
         loop {
             let (stream, addr) = listener
@@ -77,13 +183,11 @@ impl Server {
 
             tracing::debug!("Connection from {}", addr);
 
-            let registry = Arc::clone(&self.registry);
-            let config = self.config.clone();
+            let shared = self.shared.clone();
             let tls_acceptor = self.tls_acceptor.clone();
 
             tokio::spawn(async move {
-                if let Err(e) = Self::handle_connection(stream, config, registry, tls_acceptor).await
-                {
+                if let Err(e) = Self::handle_connection(stream, shared, tls_acceptor).await {
                     tracing::error!("Connection error from {}: {}", addr, e);
                 }
             });
@@ -93,8 +197,7 @@ impl Server {
     /// Handle a single connection.
     async fn handle_connection(
         stream: TcpStream,
-        config: ServerConfig,
-        registry: Arc<CapabilityRegistry>,
+        shared: Shared,
         tls_acceptor: Option<TlsAcceptor>,
     ) -> SinpResult<()> {
         if let Some(acceptor) = tls_acceptor {
@@ -102,70 +205,370 @@ impl Server {
                 .accept(stream)
                 .await
                 .map_err(|e| SinpError::Transport(format!("TLS handshake failed: {}", e)))?;
-            Self::handle_stream(tls_stream, config, registry).await
+
+            // Client certificate was verified during the handshake
+            let peer_identity = tls_stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(auth::certificate_identity);
+            if let Some(ref identity) = peer_identity {
+                tracing::debug!("Client certificate identity: {}", identity);
+            }
+
+            Self::handle_stream(tls_stream, shared, peer_identity).await
         } else {
-            Self::handle_stream(stream, config, registry).await
+            Self::handle_stream(stream, shared, None).await
         }
     }
 
     /// Handle message stream.
     async fn handle_stream<S>(
-        mut stream: S,
-        config: ServerConfig,
-        registry: Arc<CapabilityRegistry>,
+        stream: S,
+        shared: Shared,
+        peer_identity: Option<String>,
     ) -> SinpResult<()>
     where
-        S: AsyncReadExt + AsyncWriteExt + Unpin,
+        S: AsyncReadExt + AsyncWriteExt + Unpin + Send + 'static,
     {
+        let Shared {
+            config,
+            registry: live_registry,
+            approvals,
+            authenticators,
+            idempotency,
+            jobs,
+            events,
+            schedules,
+        } = shared.clone();
         let mut state_machine = ServerStateMachine::new(config.clone());
-        let mut buf = vec![0u8; 4];
+        // Agreed on the first frame: HELLO, or a bare request for legacy 0.1
+        let mut session: Option<Session> = None;
+        let (reader, mut stream) = tokio::io::split(stream);
+        let mut frames = Frames::spawn(reader, config.max_message_size);
+        // Served between requests and while one awaits approval
+        let mut sideband = Sideband::new(shared, peer_identity.clone());
 
         loop {
-            // Read length prefix (4 bytes, big-endian)
-            match stream.read_exact(&mut buf).await {
-                Ok(_) => {}
-                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                    tracing::debug!("Client disconnected");
-                    break;
+            let frame = tokio::select! {
+                frame = frames.next() => frame?,
+                pushed = sideband.next() => {
+                    write_frame(&mut stream, &pushed).await?;
+                    continue;
                 }
-                Err(e) => return Err(SinpError::Transport(format!("Read error: {}", e))),
-            }
-
-            let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            };
+            let Some(frame) = frame else {
+                break;
+            };
+            // Reloads take effect from the next frame on
+            let registry = live_registry.current();
+            let request = match decode_frame(&frame, session.as_ref())? {
+                ClientFrame::Hello(hello) => {
+                    if session.is_some() {
+                        return Err(SinpError::Protocol("Unexpected HELLO".to_string()));
+                    }
+                    match config.hello.negotiate(&hello) {
+                        Ok(agreed) => {
+                            tracing::debug!("Negotiated session: {:?}", agreed);
+                            write_frame(&mut stream, &ServerFrame::HelloAck(agreed.clone()))
+                                .await?;
+                            session = Some(agreed);
+                            continue;
+                        }
+                        Err(reason) => {
+                            let reject = ServerFrame::HelloReject(HelloReject {
+                                reason: reason.clone(),
+                                supported: config.hello.clone(),
+                            });
+                            write_frame(&mut stream, &reject).await?;
+                            return Err(SinpError::Protocol(format!(
+                                "Handshake failed: {}",
+                                reason
+                            )));
+                        }
+                    }
+                }
+                ClientFrame::Cancel(cancel) => {
+                    let reply = authenticators
+                        .authenticate_sender(
+                            &cancel.sender,
+                            cancel.credential.as_deref(),
+                            peer_identity.as_deref(),
+                        )
+                        .and_then(|()| state_machine.cancel(cancel.conversation_id))
+                        .map(|()| {
+                            events.publish(
+                                &cancel.sender.id,
+                                Event::ConversationUpdated {
+                                    conversation_id: cancel.conversation_id,
+                                    state: ServerState::Cancelled,
+                                },
+                            );
+                            ServerFrame::CancelAck(CancelAck {
+                                in_response_to: cancel.message_id,
+                                conversation_id: cancel.conversation_id,
+                                interrupted: false,
+                            })
+                        })
+                        .unwrap_or_else(|e| {
+                            ServerFrame::Error(FrameError::new(cancel.message_id, &e))
+                        });
+                    if state_machine.state().is_terminal() {
+                        state_machine.reset();
+                    }
+                    write_frame(&mut stream, &reply).await?;
+                    continue;
+                }
+                ClientFrame::Request(request) => *request,
+                frame => {
+                    if let Some(reply) = sideband.answer(frame).await? {
+                        write_frame(&mut stream, &reply).await?;
+                    }
+                    continue;
+                }
+            };
+            let session = &*session.get_or_insert_with(Session::legacy);
+            tracing::debug!("Received request: {:?}", request.message_id);
 
-            if len > config.max_message_size {
-                return Err(SinpError::Validation(format!(
-                    "Message too large: {} > {}",
-                    len, config.max_message_size
-                )));
+            if request.protocol_version != session.version {
+                let e = SinpError::Protocol(format!(
+                    "Protocol version {} does not match session version {}",
+                    request.protocol_version, session.version
+                ));
+                send_response(&mut stream, session, &create_error_response(&request, &e)).await?;
+                continue;
             }
 
-            // Read message body
-            let mut msg_buf = vec![0u8; len];
-            stream
-                .read_exact(&mut msg_buf)
-                .await
-                .map_err(|e| SinpError::Transport(format!("Read error: {}", e)))?;
+            // Bind the claimed sender to its certificate or credential
+            if let Err(e) = authenticators.authenticate(&request, peer_identity.as_deref()) {
+                tracing::warn!("Authentication failed: {}", e);
+                send_response(&mut stream, session, &create_error_response(&request, &e)).await?;
+                continue;
+            }
 
-            // Parse request
-            let request: Request = serde_json::from_slice(&msg_buf)?;
-            tracing::debug!("Received request: {:?}", request.message_id);
+            // Dry runs are decided in isolation and never touch the conversation
+            if request.dry_run {
+                let response = ServerStateMachine::new(config.clone())
+                    .process_request(&request, &registry)
+                    .unwrap_or_else(|e| create_error_response(&request, &e));
+                send_response(&mut stream, session, &response).await?;
+                continue;
+            }
 
-            // Process request
-            let response = match state_machine.process_request(&request, &registry) {
-                Ok(resp) => resp,
+            // A retry of a keyed request gets the original response
+            let claim = match idempotency.begin(&request) {
+                Ok(Begin::Replay(original)) => {
+                    send_response(&mut stream, session, &original).await?;
+                    continue;
+                }
+                Ok(Begin::Process(claim)) => claim,
                 Err(e) => {
-                    tracing::error!("Processing error: {}", e);
-                    // Send error response
-                    let error_response = create_error_response(&request, &e);
-                    send_response(&mut stream, &error_response).await?;
-                    state_machine.reset();
+                    send_response(&mut stream, session, &create_error_response(&request, &e))
+                        .await?;
                     continue;
                 }
             };
 
+            // Process request, holding sensitive executions until an
+            // operator decides. A CANCEL for the conversation stops both.
+            let streaming = !session.is_legacy() && session.supports(Feature::Streaming);
+            let token = CancellationToken::new();
+            let progress = || {
+                let (sink, updates) = if streaming {
+                    let (sink, updates) =
+                        ProgressSink::channel(request.message_id, config.progress_buffer);
+                    (sink, Some(updates))
+                } else {
+                    (ProgressSink::disabled(), None)
+                };
+                (sink.with_cancellation(token.clone()), updates)
+            };
+            let watch = |frame: &[u8]| match decode_frame(frame, Some(session)) {
+                Ok(ClientFrame::Cancel(cancel))
+                    if cancel.conversation_id == request.conversation_id
+                        && cancel.sender == request.sender
+                        && authenticators
+                            .authenticate_sender(
+                                &cancel.sender,
+                                cancel.credential.as_deref(),
+                                peer_identity.as_deref(),
+                            )
+                            .is_ok() =>
+                {
+                    Some(cancel)
+                }
+                _ => None,
+            };
+            let work = {
+                let (request, registry) = (request.clone(), Arc::clone(&registry));
+                move |machine: &mut ServerStateMachine| machine.process_request(&request, &registry)
+            };
+            let (machine, response, mut cancel) = run_reporting(
+                &mut stream,
+                &mut frames,
+                &watch,
+                &token,
+                progress(),
+                state_machine,
+                work,
+            )
+            .await?;
+            state_machine = machine;
+            let response = match (response, state_machine.pending_approval()) {
+                (Ok(response), Some(approval)) if cancel.is_none() => {
+                    let timeout = config
+                        .approval
+                        .as_ref()
+                        .map(|a| a.timeout)
+                        .unwrap_or_default();
+                    let ticket = approvals.submit(approval);
+                    let id = ticket.id();
+                    let decision = ticket.wait(timeout);
+                    tokio::pin!(decision);
+                    // The connection is served while the operator decides
+                    let waited: SinpResult<Waited> = async {
+                        loop {
+                            let frame = tokio::select! {
+                                decision = &mut decision => return Ok(Waited::Decided(decision)),
+                                frame = frames.next() => frame?,
+                                pushed = sideband.next() => {
+                                    write_frame(&mut stream, &pushed).await?;
+                                    continue;
+                                }
+                            };
+                            let Some(frame) = frame else {
+                                return Ok(Waited::Disconnected);
+                            };
+                            if let Some(withdrawn) = watch(&frame) {
+                                return Ok(Waited::Withdrawn(withdrawn));
+                            }
+                            let reply = match decode_frame(&frame, Some(session))? {
+                                // The conversation is held by the waiting request
+                                ClientFrame::Request(other) => {
+                                    let e = SinpError::Protocol(format!(
+                                        "Request {} is awaiting approval",
+                                        request.message_id
+                                    ));
+                                    let refused = create_error_response(&other, &e);
+                                    send_response(&mut stream, session, &refused).await?;
+                                    continue;
+                                }
+                                ClientFrame::Cancel(other) => {
+                                    let e = authenticators
+                                        .authenticate_sender(
+                                            &other.sender,
+                                            other.credential.as_deref(),
+                                            peer_identity.as_deref(),
+                                        )
+                                        .err()
+                                        .unwrap_or_else(|| {
+                                            SinpError::Validation(format!(
+                                                "No conversation {} of {} in progress",
+                                                other.conversation_id, other.sender.id
+                                            ))
+                                        });
+                                    Some(ServerFrame::Error(FrameError::new(other.message_id, &e)))
+                                }
+                                frame => sideband.answer(frame).await?,
+                            };
+                            if let Some(reply) = reply {
+                                write_frame(&mut stream, &reply).await?;
+                            }
+                        }
+                    }
+                    .await;
+                    match waited {
+                        Ok(Waited::Decided(decision)) => {
+                            let registry = Arc::clone(&registry);
+                            let work = move |machine: &mut ServerStateMachine| {
+                                machine.resolve_approval(response, decision, &registry)
+                            };
+                            let (machine, response, late) = run_reporting(
+                                &mut stream,
+                                &mut frames,
+                                &watch,
+                                &token,
+                                progress(),
+                                state_machine,
+                                work,
+                            )
+                            .await?;
+                            state_machine = machine;
+                            cancel = late;
+                            response
+                        }
+                        Ok(Waited::Withdrawn(withdrawn)) => {
+                            // Take the execution off the operator's queue
+                            let _ = approvals.deny(id, "cancelled by client");
+                            cancel = Some(withdrawn);
+                            Ok(response)
+                        }
+                        other => {
+                            let _ = approvals.deny(id, "client disconnected");
+                            return other.map(|_| ());
+                        }
+                    }
+                }
+                (response, _) => response,
+            };
+            // Unless it completed, the conversation ends cancelled
+            let response = match cancel {
+                Some(ref cancel) if state_machine.cancel(cancel.conversation_id).is_ok() => {
+                    Err(progress::cancelled())
+                }
+                _ => response,
+            };
+            // Background and scheduled executions start once the response
+            // is settled
+            let response = match (response, state_machine.take_deferred()) {
+                (Ok(mut response), Some(deferred)) if deferred.request.schedule.is_some() => {
+                    schedules.schedule(deferred).map(|scheduled| {
+                        response
+                            .action_metadata
+                            .get_or_insert_with(Default::default)
+                            .scheduled = Some(scheduled);
+                        response
+                    })
+                }
+                (Ok(mut response), Some(deferred)) => {
+                    let job = jobs.spawn(deferred, Arc::clone(&registry));
+                    response
+                        .action_metadata
+                        .get_or_insert_with(Default::default)
+                        .job = Some(job);
+                    Ok(response)
+                }
+                (response, _) => response,
+            };
+            let updated = match response {
+                Ok(_) => state_machine.state(),
+                Err(_) => ServerState::Failed,
+            };
+            let response = response.unwrap_or_else(|e| {
+                tracing::error!("Processing error: {}", e);
+                state_machine.reset();
+                create_error_response(&request, &e)
+            });
+            claim.complete(&response);
+
             // Send response
-            send_response(&mut stream, &response).await?;
+            send_response(&mut stream, session, &response).await?;
+            if let Some(cancel) = cancel {
+                let ack = CancelAck {
+                    in_response_to: cancel.message_id,
+                    conversation_id: cancel.conversation_id,
+                    interrupted: true,
+                };
+                write_frame(&mut stream, &ServerFrame::CancelAck(ack)).await?;
+            }
+            events.publish(
+                &request.sender.id,
+                Event::ConversationUpdated {
+                    conversation_id: request.conversation_id,
+                    state: updated,
+                },
+            );
 
             // Reset for next conversation if done
             if state_machine.state().is_terminal() {
@@ -177,12 +580,370 @@ impl Server {
     }
 }
 
-/// Send a response message.
-async fn send_response<S>(stream: &mut S, response: &Response) -> SinpResult<()>
+/// Frames a connection serves besides its conversation: discovery, job and
+/// schedule queries, and event subscriptions.
+///
+/// They are answered between requests and while a request awaits approval.
+struct Sideband {
+    shared: Shared,
+    peer_identity: Option<String>,
+    /// Events are pushed between responses, never inside an exchange
+    subscription: Option<Subscription>,
+    /// Job queries waiting for completion, answered as their jobs finish
+    waits: JoinSet<ServerFrame>,
+}
+
+impl Sideband {
+    fn new(shared: Shared, peer_identity: Option<String>) -> Self {
+        Self {
+            shared,
+            peer_identity,
+            subscription: None,
+            waits: JoinSet::new(),
+        }
+    }
+
+    /// Next frame to push unasked: a subscribed event, or the answer to a
+    /// job query that waited for completion.
+    async fn next(&mut self) -> ServerFrame {
+        loop {
+            tokio::select! {
+                event = next_event(&mut self.subscription) => return ServerFrame::Event(event),
+                Some(joined) = self.waits.join_next() => match joined {
+                    Ok(reply) => return reply,
+                    Err(e) => tracing::error!("Job query failed: {}", e),
+                },
+            }
+        }
+    }
+
+    /// Answer a frame outside the conversation. A job query that waits is
+    /// answered later by [`Self::next`], and gets `None` here.
+    async fn answer(&mut self, frame: ClientFrame) -> SinpResult<Option<ServerFrame>> {
+        let Self {
+            shared,
+            peer_identity,
+            subscription,
+            waits,
+        } = self;
+        let Shared {
+            config,
+            registry: live_registry,
+            authenticators,
+            jobs,
+            events,
+            schedules,
+            ..
+        } = shared;
+        let reply = match frame {
+            ClientFrame::Discover(discovery) => authenticators
+                .authenticate_sender(
+                    &discovery.sender,
+                    discovery.credential.as_deref(),
+                    peer_identity.as_deref(),
+                )
+                .and_then(|()| {
+                    discovery::discover(
+                        &live_registry.current(),
+                        &discovery,
+                        config.discovery_page_size,
+                    )
+                })
+                .map(ServerFrame::Catalog)
+                .unwrap_or_else(|e| ServerFrame::Error(FrameError::new(discovery.message_id, &e))),
+            ClientFrame::Jobs(query) => {
+                match authenticators.authenticate_sender(
+                    &query.sender,
+                    query.credential.as_deref(),
+                    peer_identity.as_deref(),
+                ) {
+                    // Other frames are served while the job runs
+                    Ok(()) if query.wait => {
+                        waits.spawn(answer_jobs(jobs.clone(), query));
+                        return Ok(None);
+                    }
+                    Ok(()) => answer_jobs(jobs.clone(), query).await,
+                    Err(e) => ServerFrame::Error(FrameError::new(query.message_id, &e)),
+                }
+            }
+            ClientFrame::Subscribe(subscribe) => {
+                match authenticators.authenticate_sender(
+                    &subscribe.sender,
+                    subscribe.credential.as_deref(),
+                    peer_identity.as_deref(),
+                ) {
+                    Ok(()) => {
+                        let subscribed = Subscription::new(&subscribe, live_registry, jobs, events);
+                        let reply = ServerFrame::Subscribed(Subscribed {
+                            in_response_to: subscribe.message_id,
+                            events: subscribed.events().to_vec(),
+                        });
+                        // Subscribing to nothing unsubscribes
+                        *subscription = Some(subscribed).filter(|s| !s.events().is_empty());
+                        reply
+                    }
+                    Err(e) => ServerFrame::Error(FrameError::new(subscribe.message_id, &e)),
+                }
+            }
+            ClientFrame::Schedules(query) => authenticators
+                .authenticate_sender(
+                    &query.sender,
+                    query.credential.as_deref(),
+                    peer_identity.as_deref(),
+                )
+                .and_then(|()| schedules.query(&query))
+                .map(|schedules| {
+                    ServerFrame::Schedules(ScheduleList {
+                        in_response_to: query.message_id,
+                        schedules,
+                    })
+                })
+                .unwrap_or_else(|e| ServerFrame::Error(FrameError::new(query.message_id, &e))),
+            ClientFrame::Hello(_) => {
+                return Err(SinpError::Protocol("Unexpected HELLO".to_string()))
+            }
+            ClientFrame::Cancel(_) | ClientFrame::Request(_) => {
+                return Err(SinpError::Protocol(
+                    "CANCEL and requests belong to the conversation".to_string(),
+                ))
+            }
+        };
+        Ok(Some(reply))
+    }
+}
+
+/// How waiting for an operator's decision on a request ended.
+enum Waited {
+    Decided(ApprovalDecision),
+    /// The client cancelled the request.
+    Withdrawn(Cancel),
+    Disconnected,
+}
+
+/// Frames read from the client by a background task, so that a CANCEL is
+/// seen while a request is being processed.
+struct Frames {
+    receiver: mpsc::Receiver<SinpResult<Vec<u8>>>,
+    /// Frame that arrived while watching for a CANCEL, handled next.
+    held: Option<SinpResult<Vec<u8>>>,
+    reader: tokio::task::JoinHandle<()>,
+}
+
+impl Frames {
+    /// Start reading frames from `reader`.
+    fn spawn<R>(mut reader: R, max_message_size: usize) -> Self
+    where
+        R: AsyncReadExt + Unpin + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(1);
+        let reader = tokio::spawn(async move {
+            while let Some(frame) = read_frame(&mut reader, max_message_size).await.transpose() {
+                let failed = frame.is_err();
+                if sender.send(frame).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Self {
+            receiver,
+            held: None,
+            reader,
+        }
+    }
+
+    /// Next frame, or `None` once the client disconnects.
+    async fn next(&mut self) -> SinpResult<Option<Vec<u8>>> {
+        match self.held.take() {
+            Some(frame) => frame.map(Some),
+            None => self.receiver.recv().await.transpose(),
+        }
+    }
+
+    /// Wait for a CANCEL that `accept` takes.
+    ///
+    /// Any other frame is held for [`Self::next`] and ends the watch, as does
+    /// the client disconnecting; both return `None`.
+    async fn cancel(
+        &mut self,
+        accept: &(dyn Fn(&[u8]) -> Option<Cancel> + Sync),
+    ) -> Option<Cancel> {
+        if self.held.is_some() {
+            return None;
+        }
+        let frame = self.receiver.recv().await?;
+        if let Some(cancel) = frame.as_deref().ok().and_then(accept) {
+            return Some(cancel);
+        }
+        self.held = Some(frame);
+        None
+    }
+}
+
+impl Drop for Frames {
+    fn drop(&mut self) {
+        // Release the connection even if the client never sends again
+        self.reader.abort();
+    }
+}
+
+/// Next event for the connection's subscription, if it has one.
+async fn next_event(subscription: &mut Option<Subscription>) -> Event {
+    match subscription {
+        Some(subscription) => subscription.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Run request processing on the blocking pool, sending the progress it
+/// reports until it finishes.
+///
+/// Capability handlers block; running them here keeps them from stalling
+/// other connections, and lets progress be written and a CANCEL accepted by
+/// `watch` be received while they run. A CANCEL trips `token`. Returns the
+/// state machine along with the outcome and the CANCEL, if any.
+async fn run_reporting<S, F>(
+    stream: &mut S,
+    frames: &mut Frames,
+    watch: &(dyn Fn(&[u8]) -> Option<Cancel> + Sync),
+    token: &CancellationToken,
+    (sink, mut updates): (ProgressSink, Option<mpsc::Receiver<Progress>>),
+    mut state_machine: ServerStateMachine,
+    work: F,
+) -> SinpResult<(ServerStateMachine, SinpResult<Response>, Option<Cancel>)>
+where
+    S: AsyncWriteExt + Unpin,
+    F: FnOnce(&mut ServerStateMachine) -> SinpResult<Response> + Send + 'static,
+{
+    let mut task = tokio::task::spawn_blocking(move || {
+        state_machine.set_progress(sink);
+        let response = work(&mut state_machine);
+        // Closes the progress channel
+        state_machine.set_progress(ProgressSink::disabled());
+        (state_machine, response)
+    });
+
+    let mut cancel = None;
+    loop {
+        tokio::select! {
+            joined = &mut task => {
+                let (state_machine, response) = joined.map_err(|e| {
+                    SinpError::Execution(format!("Request processing failed: {}", e))
+                })?;
+                // Updates reported just before the handler returned
+                if let Some(ref mut updates) = updates {
+                    while let Some(progress) = updates.recv().await {
+                        write_frame(stream, &ServerFrame::Progress(progress)).await?;
+                    }
+                }
+                return Ok((state_machine, response, cancel));
+            }
+            Some(progress) = next_update(&mut updates) => {
+                write_frame(stream, &ServerFrame::Progress(progress)).await?;
+            }
+            Some(received) = frames.cancel(watch), if cancel.is_none() => {
+                tracing::debug!("Cancelling conversation {}", received.conversation_id);
+                token.cancel();
+                cancel = Some(received);
+            }
+        }
+    }
+}
+
+/// Answer a job query, waiting for completion if it asks to.
+async fn answer_jobs(jobs: JobStore, query: JobQuery) -> ServerFrame {
+    match jobs.query(&query).await {
+        Ok(jobs) => ServerFrame::Jobs(JobList {
+            in_response_to: query.message_id,
+            jobs,
+        }),
+        Err(e) => ServerFrame::Error(FrameError::new(query.message_id, &e)),
+    }
+}
+
+/// Next progress update, if progress is streamed.
+async fn next_update(updates: &mut Option<mpsc::Receiver<Progress>>) -> Option<Progress> {
+    match updates {
+        Some(updates) => updates.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Read a length-prefixed frame, or `None` once the client disconnects.
+async fn read_frame<S>(stream: &mut S, max_message_size: usize) -> SinpResult<Option<Vec<u8>>>
+where
+    S: AsyncReadExt + Unpin,
+{
+    // Read length prefix (4 bytes, big-endian)
+    let mut buf = [0u8; 4];
+    match stream.read_exact(&mut buf).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            tracing::debug!("Client disconnected");
+            return Ok(None);
+        }
+        Err(e) => return Err(SinpError::Transport(format!("Read error: {}", e))),
+    }
+
+    let len = u32::from_be_bytes(buf) as usize;
+
+    if len > max_message_size {
+        return Err(SinpError::Validation(format!(
+            "Message too large: {} > {}",
+            len, max_message_size
+        )));
+    }
+
+    // Read message body
+    let mut msg_buf = vec![0u8; len];
+    stream
+        .read_exact(&mut msg_buf)
+        .await
+        .map_err(|e| SinpError::Transport(format!("Read error: {}", e)))?;
+
+    Ok(Some(msg_buf))
+}
+
+/// Decode a client frame according to the session framing.
+///
+/// Before a session is agreed, anything that is not a tagged frame is taken
+/// to be a legacy 0.1 request.
+fn decode_frame(frame: &[u8], session: Option<&Session>) -> SinpResult<ClientFrame> {
+    match session {
+        Some(session) if session.is_legacy() => Ok(ClientFrame::Request(Box::new(
+            serde_json::from_slice(frame)?,
+        ))),
+        Some(_) => Ok(serde_json::from_slice(frame)?),
+        None => match serde_json::from_slice(frame) {
+            Ok(hello @ ClientFrame::Hello(_)) => Ok(hello),
+            Ok(_) => Err(SinpError::Protocol(
+                "HELLO required before tagged frames".to_string(),
+            )),
+            Err(_) => Ok(ClientFrame::Request(Box::new(serde_json::from_slice(
+                frame,
+            )?))),
+        },
+    }
+}
+
+/// Send a response message, tagged unless the session is legacy.
+async fn send_response<S>(stream: &mut S, session: &Session, response: &Response) -> SinpResult<()>
+where
+    S: AsyncWriteExt + Unpin,
+{
+    if session.is_legacy() {
+        write_frame(stream, response).await
+    } else {
+        write_frame(stream, &ServerFrame::Response(Box::new(response.clone()))).await
+    }
+}
+
+/// Write a length-prefixed JSON frame.
+async fn write_frame<S, T>(stream: &mut S, message: &T) -> SinpResult<()>
 where
     S: AsyncWriteExt + Unpin,
+    T: serde::Serialize,
 {
-    let json = serde_json::to_vec(response)?;
+    let json = serde_json::to_vec(message)?;
     let len = json.len() as u32;
 
     // Write length prefix
@@ -207,7 +968,13 @@ where
 
 /// Create an error response.
 fn create_error_response(request: &Request, error: &SinpError) -> Response {
-    use sinp_core::{Action, ActionMetadata, Interpretation, RefusalCode, Responder};
+    use sinp_core::{Action, ActionMetadata, Interpretation, Responder};
+
+    let (code, reason) = match error {
+        SinpError::Refused { code, reason } => (*code, reason.clone()),
+        SinpError::Execution(reason) => (RefusalCode::ExecutionFailed, reason.clone()),
+        other => (RefusalCode::MalformedContext, other.to_string()),
+    };
 
     Response {
         message_id: uuid::Uuid::new_v4(),
@@ -217,6 +984,7 @@ fn create_error_response(request: &Request, error: &SinpError) -> Response {
         responder: Responder {
             id: "sinp-server".to_string(),
             capabilities: vec![],
+            catalog_version: None,
         },
         interpretation: Interpretation {
             text: "Error processing request".to_string(),
@@ -224,11 +992,706 @@ fn create_error_response(request: &Request, error: &SinpError) -> Response {
         },
         action: Action::Refuse,
         action_metadata: Some(ActionMetadata {
-            reason_code: Some(RefusalCode::MalformedContext),
-            reason: Some(error.to_string()),
+            reason_code: Some(code),
+            reason: Some(reason),
             ..Default::default()
         }),
         alternatives: None,
         confidence: 0.0,
+        explanation: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+    use sinp_core::{Action, Capability, DiscoveryRequest, Hello};
+    use tokio::io::DuplexStream;
+
+    fn sample_registry() -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |req| Ok(serde_json::json!({ "echo": req.intent })),
+            0.95,
+        );
+        registry
+    }
+
+    fn sample_request(protocol_version: &str) -> Request {
+        let mut request = Request::new(
+            Sender {
+                id: "test".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            "echo message",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        request.protocol_version = protocol_version.to_string();
+        request
+    }
+
+    /// Serve one connection over an in-memory stream.
+    fn connect() -> (DuplexStream, tokio::task::JoinHandle<SinpResult<()>>) {
+        connect_with(
+            sample_registry(),
+            IdempotencyStore::new(std::time::Duration::from_secs(60)),
+        )
+    }
+
+    fn connect_with(
+        registry: CapabilityRegistry,
+        idempotency: IdempotencyStore,
+    ) -> (DuplexStream, tokio::task::JoinHandle<SinpResult<()>>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let config =
+            ServerConfig::default().with_thresholds(sinp_core::Thresholds::new(0.5, 0.3, 0.5));
+        let shared = Shared {
+            config,
+            registry: SharedRegistry::new(registry),
+            approvals: ApprovalQueue::new(),
+            authenticators: Arc::new(Authenticators::new()),
+            idempotency,
+            jobs: JobStore::new(std::time::Duration::from_secs(60)),
+            events: EventBus::new(16),
+            schedules: Scheduler::new(),
+        };
+        let handle = tokio::spawn(Server::handle_stream(server, shared, None));
+        (client, handle)
+    }
+
+    async fn exchange<T: serde::de::DeserializeOwned>(
+        stream: &mut DuplexStream,
+        message: &impl serde::Serialize,
+    ) -> T {
+        write_frame(stream, message).await.unwrap();
+        let frame = read_frame(stream, 1024 * 1024).await.unwrap().unwrap();
+        serde_json::from_slice(&frame).unwrap()
+    }
+
+    #[tokio::test]
+    async fn hello_then_tagged_request() {
+        let (mut stream, _) = connect();
+
+        let ack: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let ServerFrame::HelloAck(session) = ack else {
+            panic!("expected HELLO ack, got {:?}", ack);
+        };
+        assert_eq!(session.version, sinp_core::PROTOCOL_VERSION);
+
+        let request = sample_request(sinp_core::PROTOCOL_VERSION);
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(response.in_response_to, request.message_id);
+        assert_eq!(response.action, Action::Execute);
+    }
+
+    #[tokio::test]
+    async fn legacy_framing() {
+        let (mut stream, _) = connect();
+
+        let request = sample_request("0.1");
+        let response: Response = exchange(&mut stream, &request).await;
+        assert_eq!(response.in_response_to, request.message_id);
+        assert_eq!(response.action, Action::Execute);
+    }
+
+    #[tokio::test]
+    async fn incompatible_hello_is_rejected() {
+        let (mut stream, handle) = connect();
+
+        let hello = Hello {
+            versions: vec!["9.0".to_string()],
+            ..Default::default()
+        };
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(hello)).await;
+        let ServerFrame::HelloReject(reject) = reply else {
+            panic!("expected rejection, got {:?}", reply);
+        };
+        assert!(reject.reason.contains("no common protocol version"));
+        assert!(reject
+            .supported
+            .versions
+            .contains(&sinp_core::PROTOCOL_VERSION.to_string()));
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn request_version_must_match_session() {
+        let (mut stream, _) = connect();
+
+        // 0.2 requests need a HELLO first
+        let response: Response =
+            exchange(&mut stream, &sample_request(sinp_core::PROTOCOL_VERSION)).await;
+        assert_eq!(response.action, Action::Refuse);
+        assert!(response
+            .action_metadata
+            .and_then(|m| m.reason)
+            .is_some_and(|r| r.contains("does not match")));
+    }
+
+    #[tokio::test]
+    async fn discovery_frames() {
+        let (mut stream, _) = connect();
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+
+        let sender = sample_request(sinp_core::PROTOCOL_VERSION).sender;
+        let discovery = DiscoveryRequest::new(sender.clone());
+        let reply: ServerFrame =
+            exchange(&mut stream, &ClientFrame::Discover(discovery.clone())).await;
+        let ServerFrame::Catalog(catalog) = reply else {
+            panic!("expected catalog, got {:?}", reply);
+        };
+        assert_eq!(catalog.in_response_to, discovery.message_id);
+        assert_eq!(catalog.capabilities[0].id, "echo:v1");
+
+        // Responses carry the same catalog version
+        let request = sample_request(sinp_core::PROTOCOL_VERSION);
+        let reply: ServerFrame =
+            exchange(&mut stream, &ClientFrame::Request(Box::new(request))).await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(response.responder.catalog_version, Some(catalog.version));
+
+        // Failures are reported in an error frame
+        let mut discovery = DiscoveryRequest::new(sender);
+        discovery.cursor = Some("bogus".to_string());
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Discover(discovery)).await;
+        assert!(matches!(reply, ServerFrame::Error(_)), "{:?}", reply);
+    }
+
+    #[tokio::test]
+    async fn retried_request_executes_once() {
+        let executions = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut registry = sample_registry();
+        let counter = Arc::clone(&executions);
+        registry.register(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            move |req| {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Ok(serde_json::json!({ "echo": req.intent }))
+            },
+            0.95,
+        );
+        let store = IdempotencyStore::new(std::time::Duration::from_secs(60));
+
+        let mut request = sample_request("0.1");
+        request.idempotency_key = Some("retry-1".to_string());
+        let (mut first, _) = connect_with(registry.clone(), store.clone());
+        let original: Response = exchange(&mut first, &request).await;
+        assert_eq!(original.action, Action::Execute);
+
+        // The client gave up on the first connection and retries on another
+        let (mut second, _) = connect_with(registry, store);
+        let retried: Response = exchange(&mut second, &request).await;
+        assert_eq!(retried, original);
+        assert_eq!(executions.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let mut reused = sample_request("0.1");
+        reused.intent = "echo something else".to_string();
+        reused.idempotency_key = Some("retry-1".to_string());
+        let conflict: Response = exchange(&mut second, &reused).await;
+        assert_eq!(conflict.action, Action::Refuse);
+        assert_eq!(
+            conflict.action_metadata.unwrap().reason_code,
+            Some(RefusalCode::IdempotencyConflict)
+        );
+    }
+
+    #[tokio::test]
+    async fn dropped_connection_releases_idempotency_key() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register_streaming(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |req, progress| {
+                // Reported once the client has gone
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                progress.partial(serde_json::json!({ "chunk": 1 }));
+                Ok(serde_json::json!({ "echo": req.intent }))
+            },
+            0.95,
+        );
+        let store = IdempotencyStore::new(std::time::Duration::from_secs(60));
+
+        let (mut first, handle) = connect_with(registry.clone(), store.clone());
+        let hello = Hello::default().with_features([Feature::Streaming]);
+        let _: ServerFrame = exchange(&mut first, &ClientFrame::Hello(hello)).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        request.idempotency_key = Some("retry-1".to_string());
+        write_frame(&mut first, &ClientFrame::Request(Box::new(request.clone())))
+            .await
+            .unwrap();
+        drop(first);
+        assert!(handle.await.unwrap().is_err());
+
+        // The retry runs instead of being refused as still in progress
+        let (mut second, _) = connect_with(registry, store);
+        request.protocol_version = "0.1".to_string();
+        let retried: Response = exchange(&mut second, &request).await;
+        assert_eq!(retried.action, Action::Execute);
+    }
+
+    #[tokio::test]
+    async fn progress_precedes_response() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register_streaming(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |req, progress| {
+                for i in 1..=3 {
+                    progress.partial(serde_json::json!({ "chunk": i }));
+                }
+                Ok(serde_json::json!({ "echo": req.intent }))
+            },
+            0.95,
+        );
+        let store = IdempotencyStore::new(std::time::Duration::from_secs(60));
+
+        let (mut stream, _) = connect_with(registry.clone(), store.clone());
+        let hello = Hello::default().with_features([Feature::Streaming]);
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(hello)).await;
+        let request = sample_request(sinp_core::PROTOCOL_VERSION);
+        write_frame(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await
+        .unwrap();
+        for sequence in 0..3 {
+            let frame = read_frame(&mut stream, 1024 * 1024).await.unwrap().unwrap();
+            let ServerFrame::Progress(progress) = serde_json::from_slice(&frame).unwrap() else {
+                panic!("expected progress");
+            };
+            assert_eq!(progress.in_response_to, request.message_id);
+            assert_eq!(progress.sequence, sequence);
+            assert_eq!(
+                progress.partial,
+                Some(serde_json::json!({ "chunk": sequence + 1 }))
+            );
+        }
+        let frame = read_frame(&mut stream, 1024 * 1024).await.unwrap().unwrap();
+        let ServerFrame::Response(response) = serde_json::from_slice(&frame).unwrap() else {
+            panic!("expected response");
+        };
+        assert_eq!(response.action, Action::Execute);
+
+        // Without streaming only the response is sent
+        let (mut stream, _) = connect_with(registry, store);
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(sample_request(sinp_core::PROTOCOL_VERSION))),
+        )
+        .await;
+        assert!(matches!(reply, ServerFrame::Response(_)), "{:?}", reply);
+    }
+
+    #[tokio::test]
+    async fn cancel_negotiation() {
+        let (mut stream, _) = connect_with(
+            crate::plan::tests::compose_registry(),
+            IdempotencyStore::new(std::time::Duration::from_secs(60)),
+        );
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        request.intent = "get weather city=Oslo then email it to=bob".to_string();
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        assert!(matches!(reply, ServerFrame::Response(_)), "{:?}", reply);
+
+        let cancel = Cancel::new(request.sender.clone(), request.conversation_id);
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Cancel(cancel.clone())).await;
+        let ServerFrame::CancelAck(ack) = reply else {
+            panic!("expected acknowledgement, got {:?}", reply);
+        };
+        assert_eq!(ack.in_response_to, cancel.message_id);
+        assert_eq!(ack.conversation_id, request.conversation_id);
+        assert!(!ack.interrupted);
+
+        // The conversation is over
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Cancel(cancel)).await;
+        let ServerFrame::Error(error) = reply else {
+            panic!("expected error, got {:?}", reply);
+        };
+        assert!(error.reason.contains("No conversation"), "{}", error.reason);
+    }
+
+    #[tokio::test]
+    async fn cancel_running_execution() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register_streaming(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |req, progress| {
+                progress.report(0.0, "started");
+                for _ in 0..1000 {
+                    progress.check_cancelled()?;
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                }
+                Ok(serde_json::json!({ "echo": req.intent }))
+            },
+            0.95,
+        );
+        let (mut stream, _) = connect_with(
+            registry,
+            IdempotencyStore::new(std::time::Duration::from_secs(60)),
+        );
+        let hello = Hello::default().with_features([Feature::Streaming]);
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(hello)).await;
+        let request = sample_request(sinp_core::PROTOCOL_VERSION);
+        let started: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        assert!(matches!(started, ServerFrame::Progress(_)), "{:?}", started);
+
+        let cancel = Cancel::new(request.sender.clone(), request.conversation_id);
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Cancel(cancel.clone())).await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(response.in_response_to, request.message_id);
+        assert_eq!(response.action, Action::Refuse);
+        assert_eq!(
+            response.action_metadata.unwrap().reason_code,
+            Some(RefusalCode::Cancelled)
+        );
+        let frame = read_frame(&mut stream, 1024 * 1024).await.unwrap().unwrap();
+        let ServerFrame::CancelAck(ack) = serde_json::from_slice(&frame).unwrap() else {
+            panic!("expected acknowledgement");
+        };
+        assert_eq!(ack.in_response_to, cancel.message_id);
+        assert!(ack.interrupted);
+
+        // The connection serves the next conversation
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Cancel(Cancel::new(request.sender, uuid::Uuid::new_v4())),
+        )
+        .await;
+        assert!(matches!(reply, ServerFrame::Error(_)), "{:?}", reply);
+    }
+
+    #[tokio::test]
+    async fn background_job_result_is_fetched_later() {
+        let (mut stream, _) = connect();
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        request.background = true;
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(response.action, Action::Execute);
+        let metadata = response.action_metadata.unwrap();
+        assert!(metadata.result.is_none());
+        let job = metadata.job.unwrap();
+        assert_eq!(job.capability_id, "echo:v1");
+
+        let mut query = sinp_core::JobQuery::job(request.sender.clone(), job.id);
+        query.wait = true;
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Jobs(query.clone())).await;
+        let ServerFrame::Jobs(list) = reply else {
+            panic!("expected jobs, got {:?}", reply);
+        };
+        assert_eq!(list.in_response_to, query.message_id);
+        assert_eq!(list.jobs[0].status, sinp_core::JobStatus::Succeeded);
+        assert_eq!(
+            list.jobs[0].result,
+            Some(serde_json::json!({ "echo": "echo message" }))
+        );
+
+        // Only the sender's own jobs are visible
+        let mut other = request.sender.clone();
+        other.id = "someone-else".to_string();
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Jobs(sinp_core::JobQuery::job(other, job.id)),
+        )
+        .await;
+        assert!(matches!(reply, ServerFrame::Error(_)), "{:?}", reply);
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_job_keeps_serving_frames() {
+        let (release, gate) = std::sync::mpsc::channel::<()>();
+        let gate = std::sync::Mutex::new(gate);
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            move |req| {
+                gate.lock().unwrap().recv().unwrap();
+                Ok(serde_json::json!({ "echo": req.intent }))
+            },
+            0.95,
+        );
+        let (mut stream, _) = connect_with(
+            registry,
+            IdempotencyStore::new(std::time::Duration::from_secs(60)),
+        );
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        request.background = true;
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        let job = response.action_metadata.unwrap().job.unwrap();
+
+        let mut query = sinp_core::JobQuery::job(request.sender.clone(), job.id);
+        query.wait = true;
+        write_frame(&mut stream, &ClientFrame::Jobs(query.clone()))
+            .await
+            .unwrap();
+
+        // Answered while the job is still running
+        let discovery = DiscoveryRequest::new(request.sender.clone());
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Discover(discovery)).await;
+        assert!(matches!(reply, ServerFrame::Catalog(_)), "{:?}", reply);
+
+        release.send(()).unwrap();
+        let frame = read_frame(&mut stream, 1024 * 1024).await.unwrap().unwrap();
+        let ServerFrame::Jobs(list) = serde_json::from_slice(&frame).unwrap() else {
+            panic!("expected jobs");
+        };
+        assert_eq!(list.in_response_to, query.message_id);
+        assert_eq!(list.jobs[0].status, sinp_core::JobStatus::Succeeded);
+    }
+
+    #[tokio::test]
+    async fn awaiting_approval_keeps_serving_frames() {
+        let (mut stream, server) = tokio::io::duplex(64 * 1024);
+        let config = ServerConfig::default()
+            .with_thresholds(sinp_core::Thresholds::new(0.5, 0.3, 0.5))
+            .with_approval(crate::approval::ApprovalConfig {
+                capabilities: vec!["echo:v1".to_string()],
+                ..Default::default()
+            });
+        let approvals = ApprovalQueue::new();
+        let shared = Shared {
+            config,
+            registry: SharedRegistry::new(sample_registry()),
+            approvals: approvals.clone(),
+            authenticators: Arc::new(Authenticators::new()),
+            idempotency: IdempotencyStore::new(std::time::Duration::from_secs(60)),
+            jobs: JobStore::new(std::time::Duration::from_secs(60)),
+            events: EventBus::new(16),
+            schedules: Scheduler::new(),
+        };
+        tokio::spawn(Server::handle_stream(server, shared, None));
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let request = sample_request(sinp_core::PROTOCOL_VERSION);
+        write_frame(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await
+        .unwrap();
+        while approvals.pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        // Answered while the operator has yet to decide
+        let discovery = DiscoveryRequest::new(request.sender.clone());
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Discover(discovery)).await;
+        assert!(matches!(reply, ServerFrame::Catalog(_)), "{:?}", reply);
+        let mut other = sample_request(sinp_core::PROTOCOL_VERSION);
+        other.conversation_id = uuid::Uuid::new_v4();
+        let reply: ServerFrame =
+            exchange(&mut stream, &ClientFrame::Request(Box::new(other.clone()))).await;
+        let ServerFrame::Response(refused) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(refused.in_response_to, other.message_id);
+        assert_eq!(refused.action, Action::Refuse);
+
+        approvals.approve(approvals.pending()[0].id).unwrap();
+        let frame = read_frame(&mut stream, 1024 * 1024).await.unwrap().unwrap();
+        let ServerFrame::Response(response) = serde_json::from_slice(&frame).unwrap() else {
+            panic!("expected response");
+        };
+        assert_eq!(response.in_response_to, request.message_id);
+        assert_eq!(response.action, Action::Execute);
+    }
+
+    #[tokio::test]
+    async fn subscribed_events_are_pushed() {
+        let (mut stream, _) = connect();
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        let subscribe = sinp_core::Subscribe::new(
+            request.sender.clone(),
+            [
+                sinp_core::EventKind::JobCompleted,
+                sinp_core::EventKind::ConversationUpdated,
+            ],
+        );
+        let reply: ServerFrame =
+            exchange(&mut stream, &ClientFrame::Subscribe(subscribe.clone())).await;
+        let ServerFrame::Subscribed(subscribed) = reply else {
+            panic!("expected subscribed, got {:?}", reply);
+        };
+        assert_eq!(subscribed.in_response_to, subscribe.message_id);
+        assert_eq!(subscribed.events, subscribe.events);
+
+        request.background = true;
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        let job = response.action_metadata.unwrap().job.unwrap();
+
+        // Both follow the response, in whichever order they happened
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            let frame = read_frame(&mut stream, 1024 * 1024).await.unwrap().unwrap();
+            let ServerFrame::Event(event) = serde_json::from_slice(&frame).unwrap() else {
+                panic!("expected an event");
+            };
+            events.push(event);
+        }
+        events.sort_by_key(|event| event.kind() == sinp_core::EventKind::ConversationUpdated);
+        let Event::JobCompleted { job: ref completed } = events[0] else {
+            panic!("expected a job completion, got {:?}", events);
+        };
+        assert_eq!(completed.id, job.id);
+        assert_eq!(completed.status, sinp_core::JobStatus::Succeeded);
+        assert_eq!(
+            events[1],
+            Event::ConversationUpdated {
+                conversation_id: request.conversation_id,
+                state: ServerState::Done,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn scheduled_execution_is_listed_and_cancelled() {
+        let (mut stream, _) = connect();
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        request.schedule = Some(sinp_core::Schedule::Cron {
+            expression: "0 9 * * *".to_string(),
+        });
+        let reply: ServerFrame = exchange(
+            &mut stream,
+            &ClientFrame::Request(Box::new(request.clone())),
+        )
+        .await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(response.action, Action::Execute);
+        let metadata = response.action_metadata.unwrap();
+        assert!(metadata.result.is_none() && metadata.job.is_none());
+        let scheduled = metadata.scheduled.unwrap();
+        assert_eq!(scheduled.capability_id, "echo:v1");
+        assert_eq!(scheduled.runs, 0);
+
+        let query = sinp_core::ScheduleQuery::new(request.sender.clone());
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Schedules(query)).await;
+        let ServerFrame::Schedules(list) = reply else {
+            panic!("expected schedules, got {:?}", reply);
+        };
+        assert_eq!(list.schedules, vec![scheduled.clone()]);
+
+        // Only the sender can cancel it
+        let mut other = request.sender.clone();
+        other.id = "someone-else".to_string();
+        let query = sinp_core::ScheduleQuery::cancel(other, scheduled.id);
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Schedules(query)).await;
+        assert!(matches!(reply, ServerFrame::Error(_)), "{:?}", reply);
+
+        let query = sinp_core::ScheduleQuery::cancel(request.sender.clone(), scheduled.id);
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Schedules(query)).await;
+        let ServerFrame::Schedules(list) = reply else {
+            panic!("expected schedules, got {:?}", reply);
+        };
+        assert_eq!(list.schedules, vec![scheduled]);
+        let query = sinp_core::ScheduleQuery::new(request.sender);
+        let reply: ServerFrame = exchange(&mut stream, &ClientFrame::Schedules(query)).await;
+        let ServerFrame::Schedules(list) = reply else {
+            panic!("expected schedules, got {:?}", reply);
+        };
+        assert!(list.schedules.is_empty());
+    }
+
+    #[tokio::test]
+    async fn invalid_schedule_is_refused() {
+        let (mut stream, _) = connect();
+        let _: ServerFrame = exchange(&mut stream, &ClientFrame::Hello(Hello::default())).await;
+        let mut request = sample_request(sinp_core::PROTOCOL_VERSION);
+        request.schedule = Some(sinp_core::Schedule::Cron {
+            expression: "whenever".to_string(),
+        });
+        let reply: ServerFrame =
+            exchange(&mut stream, &ClientFrame::Request(Box::new(request))).await;
+        let ServerFrame::Response(response) = reply else {
+            panic!("expected response, got {:?}", reply);
+        };
+        assert_eq!(response.action, Action::Refuse);
     }
 }
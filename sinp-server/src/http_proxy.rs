@@ -0,0 +1,500 @@
+//! Capabilities that forward to HTTP backends.
+//!
+//! The inputs extracted from the intent are mapped onto a request to a
+//! backend service: `{input}` placeholders in the URL template are filled
+//! in, and each remaining input goes to the query string, a header or the
+//! JSON body. The backend's JSON response is the execution result.
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sinp_core::{Capability, Request, SinpError, SinpResult};
+
+use crate::capability::{request_parameters, CapabilityHandler};
+
+/// Characters left unescaped in a path segment.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Where an input goes in the backend request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterLocation {
+    Path,
+    Query,
+    Header,
+    Body,
+}
+
+/// JSON type an input is converted to before forwarding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    String,
+    Integer,
+    Number,
+    Boolean,
+}
+
+impl ParameterType {
+    /// Convert an extracted value, which is usually a string.
+    fn convert(self, input: &str, value: &serde_json::Value) -> SinpResult<serde_json::Value> {
+        let text = text(value);
+        let converted = match self {
+            Self::String => Some(serde_json::Value::String(text.clone())),
+            Self::Integer => text.parse::<i64>().ok().map(Into::into),
+            Self::Number => text
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(serde_json::Value::Number),
+            Self::Boolean => text.parse::<bool>().ok().map(Into::into),
+        };
+        converted.ok_or_else(|| {
+            SinpError::Validation(format!(
+                "Input {} is not a valid {:?}: {}",
+                input, self, text
+            ))
+        })
+    }
+}
+
+/// Placement of one input in the backend request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterMapping {
+    #[serde(rename = "in")]
+    pub location: ParameterLocation,
+    /// Name in the backend request; the input name if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Type to convert the input to; sent as extracted if unset.
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ParameterType>,
+}
+
+/// How to forward a capability to an HTTP backend.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpProxyConfig {
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// Backend URL with `{input}` placeholders.
+    pub url: String,
+    /// Placement of inputs. Unmapped inputs fill URL placeholders of the
+    /// same name, else go to the query string for `GET`, `HEAD` and
+    /// `DELETE`, and to the JSON body otherwise.
+    #[serde(default)]
+    pub parameters: BTreeMap<String, ParameterMapping>,
+    /// Headers sent with every request.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+impl HttpProxyConfig {
+    /// Forward to `url` with `method`.
+    pub fn new(method: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            method: method.into().to_ascii_uppercase(),
+            url: url.into(),
+            parameters: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+
+    /// Place an input in the backend request.
+    pub fn with_parameter(
+        mut self,
+        input: impl Into<String>,
+        location: ParameterLocation,
+        name: Option<&str>,
+    ) -> Self {
+        self.parameters.insert(
+            input.into(),
+            ParameterMapping {
+                location,
+                name: name.map(String::from),
+                kind: None,
+            },
+        );
+        self
+    }
+
+    /// Place an input in the backend request, converted to `kind`.
+    pub fn with_typed_parameter(
+        mut self,
+        input: impl Into<String>,
+        location: ParameterLocation,
+        kind: ParameterType,
+    ) -> Self {
+        self.parameters.insert(
+            input.into(),
+            ParameterMapping {
+                location,
+                name: None,
+                kind: Some(kind),
+            },
+        );
+        self
+    }
+
+    /// Send a header with every request.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set the timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    /// Problems with this configuration, if any.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE"]
+            .contains(&self.method.to_ascii_uppercase().as_str())
+        {
+            errors.push(format!("unsupported method {:?}", self.method));
+        }
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            errors.push(format!("url {:?} is not an http(s) URL", self.url));
+        }
+        if self.timeout_ms == 0 {
+            errors.push("timeout_ms must be positive".to_string());
+        }
+        for (input, mapping) in &self.parameters {
+            if mapping.location == ParameterLocation::Path
+                && !self
+                    .placeholders()
+                    .contains(&mapping.name.as_deref().unwrap_or(input))
+            {
+                errors.push(format!("path parameter {:?} has no URL placeholder", input));
+            }
+        }
+        errors
+    }
+
+    /// Names of the `{placeholder}`s in the URL template.
+    fn placeholders(&self) -> Vec<&str> {
+        self.url
+            .split('{')
+            .skip(1)
+            .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    fn has_body(&self) -> bool {
+        !matches!(
+            self.method.to_ascii_uppercase().as_str(),
+            "GET" | "HEAD" | "DELETE"
+        )
+    }
+
+    /// Send the backend request for `parameters` and return its JSON body.
+    pub fn forward(
+        &self,
+        agent: &ureq::Agent,
+        parameters: &serde_json::Map<String, serde_json::Value>,
+    ) -> SinpResult<serde_json::Value> {
+        let method = self.method.to_ascii_uppercase();
+        let mut url = self.url.clone();
+        let mut query = Vec::new();
+        let mut headers: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let mut body = serde_json::Map::new();
+
+        for (input, value) in parameters {
+            let mapping = self.parameters.get(input);
+            let value = match mapping.and_then(|m| m.kind) {
+                Some(kind) => &kind.convert(input, value)?,
+                None => value,
+            };
+            let name = mapping
+                .and_then(|m| m.name.clone())
+                .unwrap_or_else(|| input.clone());
+            let location = match mapping {
+                Some(m) => m.location,
+                None if self.placeholders().contains(&input.as_str()) => ParameterLocation::Path,
+                None if self.has_body() => ParameterLocation::Body,
+                None => ParameterLocation::Query,
+            };
+            match location {
+                ParameterLocation::Path => {
+                    let encoded = utf8_percent_encode(&text(value), PATH_SEGMENT).to_string();
+                    url = url.replace(&format!("{{{}}}", name), &encoded);
+                }
+                ParameterLocation::Query => query.push((name, text(value))),
+                ParameterLocation::Header => headers.push((name, text(value))),
+                ParameterLocation::Body => {
+                    body.insert(name, value.clone());
+                }
+            }
+        }
+
+        if let Some(missing) = self
+            .placeholders()
+            .into_iter()
+            .find(|p| url.contains(&format!("{{{}}}", p)))
+        {
+            return Err(SinpError::Validation(format!(
+                "Missing input for URL parameter {}",
+                missing
+            )));
+        }
+
+        let mut request = agent.request(&method, &url);
+        for (name, value) in &query {
+            request = request.query(name, value);
+        }
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        let result = if self.has_body() {
+            request.send_json(serde_json::Value::Object(body))
+        } else {
+            request.call()
+        };
+
+        let response = match result {
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                return Err(SinpError::Execution(format!(
+                    "{} {} returned {}: {}",
+                    method,
+                    self.url,
+                    status,
+                    body.trim()
+                )));
+            }
+            Err(e) => {
+                return Err(SinpError::Execution(format!(
+                    "{} {} failed: {}",
+                    method, self.url, e
+                )))
+            }
+        };
+
+        let body = response.into_string().map_err(|e| {
+            SinpError::Execution(format!("Failed to read response from {}: {}", self.url, e))
+        })?;
+        if body.trim().is_empty() {
+            return Ok(serde_json::Value::Null);
+        }
+        serde_json::from_str(&body)
+            .map_err(|e| SinpError::Execution(format!("{} returned invalid JSON: {}", self.url, e)))
+    }
+}
+
+/// Parameter value as text for URLs and headers.
+fn text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Handler forwarding a capability to an HTTP backend.
+pub fn handler(capability: &Capability, config: HttpProxyConfig) -> CapabilityHandler {
+    let capability = capability.clone();
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .build();
+    Arc::new(move |request: &Request| {
+        config.forward(&agent, &request_parameters(&capability, request))
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// Request received by the stub backend.
+    #[derive(Debug)]
+    pub(crate) struct Received {
+        pub(crate) request_line: String,
+        pub(crate) headers: Vec<String>,
+        pub(crate) body: String,
+    }
+
+    /// Serve one HTTP request with a canned response.
+    pub(crate) fn stub_backend(
+        status: u16,
+        response: &'static str,
+    ) -> (String, mpsc::Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = Vec::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                headers.push(line);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            tx.send(Received {
+                request_line: request_line.trim_end().to_string(),
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            })
+            .unwrap();
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            )
+            .unwrap();
+        });
+        (format!("http://{}", addr), rx)
+    }
+
+    fn capability(inputs: &[&str]) -> Capability {
+        Capability {
+            id: "flights:v1".to_string(),
+            description: "Search flights".to_string(),
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            privacy_level: "public".to_string(),
+            cost_units: 0.1,
+        }
+    }
+
+    fn request(intent: &str) -> Request {
+        Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn get_with_path_and_query() {
+        let (base, received) = stub_backend(200, r#"{"flights": 3}"#);
+        let config = HttpProxyConfig::new("GET", format!("{}/flights/{{origin}}", base))
+            .with_parameter("day", ParameterLocation::Query, Some("date"))
+            .with_parameter("user", ParameterLocation::Header, Some("X-User"))
+            .with_header("Accept", "application/json");
+        let handler = handler(&capability(&["origin", "day", "user"]), config);
+
+        let result = handler(&request(
+            r#"find flights origin="New York" day=2025-01-01 user=ada"#,
+        ))
+        .unwrap();
+        assert_eq!(result["flights"], 3);
+
+        let received = received.recv().unwrap();
+        assert_eq!(
+            received.request_line,
+            "GET /flights/New%20York?date=2025-01-01 HTTP/1.1"
+        );
+        assert!(received.headers.iter().any(|h| h == "X-User: ada"));
+        assert!(received
+            .headers
+            .iter()
+            .any(|h| h == "Accept: application/json"));
+    }
+
+    #[test]
+    fn post_json_body() {
+        let (base, received) = stub_backend(201, r#"{"booked": true}"#);
+        let config = HttpProxyConfig::new("post", format!("{}/bookings", base));
+        let handler = handler(&capability(&["origin", "destination"]), config);
+
+        let result = handler(&request("book origin=LHR destination=JFK")).unwrap();
+        assert_eq!(result["booked"], true);
+
+        let received = received.recv().unwrap();
+        assert_eq!(received.request_line, "POST /bookings HTTP/1.1");
+        let body: serde_json::Value = serde_json::from_str(&received.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"origin": "LHR", "destination": "JFK"})
+        );
+    }
+
+    #[test]
+    fn typed_parameters() {
+        let (base, received) = stub_backend(200, "{}");
+        let config = HttpProxyConfig::new("PUT", format!("{}/seats", base))
+            .with_typed_parameter("count", ParameterLocation::Body, ParameterType::Integer)
+            .with_typed_parameter("window", ParameterLocation::Body, ParameterType::Boolean);
+        let handler = handler(&capability(&["count", "window"]), config);
+
+        handler(&request("reserve count=2 window=true")).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&received.recv().unwrap().body).unwrap();
+        assert_eq!(body, serde_json::json!({"count": 2, "window": true}));
+
+        let err = handler(&request("reserve count=two")).unwrap_err();
+        assert!(matches!(err, SinpError::Validation(_)), "{}", err);
+    }
+
+    #[test]
+    fn backend_errors() {
+        let (base, _received) = stub_backend(503, r#"{"error": "maintenance"}"#);
+        let config = HttpProxyConfig::new("GET", format!("{}/status", base));
+        let err = handler(&capability(&[]), config)(&request("status")).unwrap_err();
+        assert!(matches!(err, SinpError::Execution(_)));
+        assert!(err.to_string().contains("503") && err.to_string().contains("maintenance"));
+
+        // Required path input missing from the intent
+        let config = HttpProxyConfig::new("GET", "http://127.0.0.1:1/flights/{origin}");
+        let err = handler(&capability(&["origin"]), config)(&request("flights")).unwrap_err();
+        assert!(err.to_string().contains("origin"), "{}", err);
+
+        let config = HttpProxyConfig::new("FETCH", "ftp://example.com")
+            .with_timeout(Duration::ZERO)
+            .with_parameter("x", ParameterLocation::Path, None);
+        assert_eq!(config.validate().len(), 4, "{:?}", config.validate());
+    }
+}
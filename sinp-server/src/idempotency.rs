@@ -0,0 +1,295 @@
+//! Idempotency keys for safe retries.
+//!
+//! A request carrying an `idempotency_key` is processed once per sender and
+//! key. Until the retention window passes, a retry gets the original
+//! response instead of running the capability again.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use sinp_core::{RefusalCode, Request, Response, SinpError, SinpResult};
+
+#[derive(Debug)]
+struct Entry {
+    fingerprint: String,
+    /// When the key was claimed, or when its response was stored.
+    stored_at: Instant,
+    /// `None` while the original request is still being processed.
+    response: Option<Response>,
+}
+
+/// Shared store of responses by sender and idempotency key.
+///
+/// Cloning yields another handle to the same store, so all connections see
+/// each other's keys.
+#[derive(Clone)]
+pub struct IdempotencyStore {
+    entries: Arc<Mutex<HashMap<(String, String), Entry>>>,
+    retention: Duration,
+}
+
+/// Outcome of [`IdempotencyStore::begin`].
+#[derive(Debug)]
+pub enum Begin {
+    /// Process the request, then complete the claim with its response.
+    Process(Claim),
+    /// The request was already processed; send its original response.
+    Replay(Box<Response>),
+}
+
+/// A request's claim on its idempotency key.
+///
+/// Dropping the claim without completing it releases the key, so a retry
+/// can run after the connection drops or processing fails.
+#[derive(Debug)]
+pub struct Claim {
+    held: Option<Held>,
+}
+
+#[derive(Debug)]
+struct Held {
+    entries: Arc<Mutex<HashMap<(String, String), Entry>>>,
+    key: (String, String),
+    fingerprint: String,
+    claimed_at: Instant,
+}
+
+impl IdempotencyStore {
+    /// Create an empty store keeping responses for `retention`.
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            retention,
+        }
+    }
+
+    /// Claim a request's key before processing it.
+    ///
+    /// Returns the original response if the key was already used for the
+    /// same request. Reusing a key for a different request, or while the
+    /// original is still being processed, is refused. A claim left
+    /// unfinished for longer than the retention window expires.
+    pub fn begin(&self, request: &Request) -> SinpResult<Begin> {
+        let Some(key) = Self::key(request) else {
+            return Ok(Begin::Process(Claim { held: None }));
+        };
+        let fingerprint = Self::fingerprint(request);
+
+        let mut entries = lock(&self.entries);
+        let retention = self.retention;
+        entries.retain(|_, entry| entry.stored_at.elapsed() < retention);
+
+        match entries.get(&key) {
+            Some(entry) if entry.fingerprint != fingerprint => Err(conflict(
+                "idempotency key was already used for a different request",
+            )),
+            Some(Entry {
+                response: Some(response),
+                ..
+            }) => {
+                tracing::debug!("Replaying response for idempotency key {}", key.1);
+                Ok(Begin::Replay(Box::new(response.clone())))
+            }
+            Some(_) => Err(conflict(
+                "request with this idempotency key is still being processed",
+            )),
+            None => {
+                let claimed_at = Instant::now();
+                entries.insert(
+                    key.clone(),
+                    Entry {
+                        fingerprint: fingerprint.clone(),
+                        stored_at: claimed_at,
+                        response: None,
+                    },
+                );
+                Ok(Begin::Process(Claim {
+                    held: Some(Held {
+                        entries: Arc::clone(&self.entries),
+                        key,
+                        fingerprint,
+                        claimed_at,
+                    }),
+                }))
+            }
+        }
+    }
+
+    fn key(request: &Request) -> Option<(String, String)> {
+        request
+            .idempotency_key
+            .as_ref()
+            .map(|key| (request.sender.id.clone(), key.clone()))
+    }
+
+    /// What a retry must repeat: the intent and explicit inputs.
+    fn fingerprint(request: &Request) -> String {
+        serde_json::json!([request.intent, request.parameters]).to_string()
+    }
+}
+
+impl Claim {
+    /// Record the response to the claimed request.
+    pub fn complete(mut self, response: &Response) {
+        let Some(held) = self.held.take() else {
+            return;
+        };
+        lock(&held.entries).insert(
+            held.key,
+            Entry {
+                fingerprint: held.fingerprint,
+                stored_at: Instant::now(),
+                response: Some(response.clone()),
+            },
+        );
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let Some(held) = self.held.take() else {
+            return;
+        };
+        let mut entries = lock(&held.entries);
+        // Unless it expired and was claimed again meanwhile
+        if entries
+            .get(&held.key)
+            .is_some_and(|entry| entry.response.is_none() && entry.stored_at == held.claimed_at)
+        {
+            tracing::debug!("Releasing idempotency key {}", held.key.1);
+            entries.remove(&held.key);
+        }
+    }
+}
+
+fn lock(
+    entries: &Mutex<HashMap<(String, String), Entry>>,
+) -> std::sync::MutexGuard<'_, HashMap<(String, String), Entry>> {
+    entries.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn conflict(reason: &str) -> SinpError {
+    SinpError::Refused {
+        code: RefusalCode::IdempotencyConflict,
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Interpretation, Responder, Sender};
+    use sinp_core::Action;
+
+    fn request(sender: &str, intent: &str, key: Option<&str>) -> Request {
+        let mut request = Request::new(
+            Sender {
+                id: sender.to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        request.idempotency_key = key.map(String::from);
+        request
+    }
+
+    fn response(request: &Request) -> Response {
+        Response::to_request(
+            request,
+            Responder {
+                id: "sinp-server".to_string(),
+                capabilities: vec![],
+                catalog_version: None,
+            },
+            Interpretation {
+                text: "sent".to_string(),
+                confidence: 0.9,
+            },
+            Action::Execute,
+            0.9,
+        )
+    }
+
+    fn claim(store: &IdempotencyStore, request: &Request) -> Claim {
+        match store.begin(request).unwrap() {
+            Begin::Process(claim) => claim,
+            Begin::Replay(response) => panic!("unexpected replay of {:?}", response),
+        }
+    }
+
+    #[test]
+    fn retry_gets_original_response() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let original = request("client_1", "send email to=bob", Some("k1"));
+        let claimed = claim(&store, &original);
+
+        let err = store.begin(&original).unwrap_err().to_string();
+        assert!(err.contains("still being processed"), "{}", err);
+
+        let sent = response(&original);
+        claimed.complete(&sent);
+        let retry = request("client_1", "send email to=bob", Some("k1"));
+        assert!(matches!(store.begin(&retry).unwrap(), Begin::Replay(r) if *r == sent));
+
+        // Keys are scoped to the sender
+        let other = request("client_2", "send email to=bob", Some("k1"));
+        let _other = claim(&store, &other);
+        // Requests without a key are never stored
+        let unkeyed = request("client_1", "send email to=bob", None);
+        let _first = claim(&store, &unkeyed);
+        let _second = claim(&store, &unkeyed);
+    }
+
+    #[test]
+    fn abandoned_claim_is_released() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let original = request("client_1", "send email to=bob", Some("k1"));
+        drop(claim(&store, &original));
+        claim(&store, &original).complete(&response(&original));
+    }
+
+    #[test]
+    fn stale_claim_expires() {
+        let store = IdempotencyStore::new(Duration::from_millis(20));
+        let original = request("client_1", "send email to=bob", Some("k1"));
+        let stale = claim(&store, &original);
+        std::thread::sleep(Duration::from_millis(40));
+        let fresh = claim(&store, &original);
+
+        // Releasing the stale claim leaves the fresh one in place
+        drop(stale);
+        assert!(store.begin(&original).is_err());
+        fresh.complete(&response(&original));
+    }
+
+    #[test]
+    fn key_reuse_is_refused() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let original = request("client_1", "send email to=bob", Some("k1"));
+        claim(&store, &original).complete(&response(&original));
+
+        let different = request("client_1", "send email to=alice", Some("k1"));
+        match store.begin(&different) {
+            Err(SinpError::Refused { code, .. }) => {
+                assert_eq!(code, RefusalCode::IdempotencyConflict)
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn entries_expire() {
+        let store = IdempotencyStore::new(Duration::from_millis(20));
+        let original = request("client_1", "send email to=bob", Some("k1"));
+        claim(&store, &original).complete(&response(&original));
+        std::thread::sleep(Duration::from_millis(40));
+        claim(&store, &original);
+    }
+}
@@ -0,0 +1,388 @@
+//! Background jobs for long-running executions.
+//!
+//! A request with `background` set is answered with a [`Job`] handle as soon
+//! as it is negotiated to EXECUTE. The capability then runs on the blocking
+//! pool and its outcome is kept in the [`JobStore`], where the sender can
+//! poll for it or wait on it from any connection until it expires, or be
+//! told through a subscription.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use sinp_core::{Capability, Job, JobQuery, JobStatus, Request, SinpError, SinpResult};
+
+use crate::capability::CapabilityRegistry;
+
+/// Execution negotiated to run as a background job.
+#[derive(Debug, Clone)]
+pub struct DeferredExecution {
+    pub request: Request,
+    pub capability: Capability,
+}
+
+/// Shared store of background jobs.
+///
+/// Cloning yields another handle to the same store. Completed jobs are kept
+/// for the retention period; with a backing file they also survive a
+/// restart, while jobs still running at shutdown are recorded as failed.
+#[derive(Clone)]
+pub struct JobStore {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    jobs: Mutex<HashMap<Uuid, Job>>,
+    path: Option<PathBuf>,
+    retention: Duration,
+    completed: broadcast::Sender<Job>,
+}
+
+impl JobStore {
+    /// Create an empty in-memory store keeping results for `retention`.
+    pub fn new(retention: Duration) -> Self {
+        Self::with_jobs(HashMap::new(), None, retention)
+    }
+
+    /// Open a store persisted to `path`, creating it if it does not exist.
+    pub fn open(path: impl Into<PathBuf>, retention: Duration) -> SinpResult<Self> {
+        let path = path.into();
+        let jobs = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice::<Vec<Job>>(&contents).map_err(|e| {
+                SinpError::Validation(format!("Invalid job store {}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(SinpError::Execution(format!(
+                    "Failed to read job store {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        let jobs = jobs
+            .into_iter()
+            .map(|mut job| {
+                if !job.is_complete() {
+                    finish(&mut job, Err("Interrupted by a server restart".to_string()), retention);
+                }
+                (job.id, job)
+            })
+            .collect();
+
+        let store = Self::with_jobs(jobs, Some(path), retention);
+        store.save(&store.lock());
+        Ok(store)
+    }
+
+    fn with_jobs(jobs: HashMap<Uuid, Job>, path: Option<PathBuf>, retention: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                jobs: Mutex::new(jobs),
+                path,
+                retention,
+                completed: broadcast::Sender::new(64),
+            }),
+        }
+    }
+
+    /// Start running a deferred execution and return its job.
+    ///
+    /// Must be called from within the Tokio runtime.
+    pub fn spawn(&self, deferred: DeferredExecution, registry: Arc<CapabilityRegistry>) -> Job {
+        let DeferredExecution {
+            request,
+            capability,
+        } = deferred;
+        let job = Job {
+            id: Uuid::new_v4(),
+            conversation_id: request.conversation_id,
+            sender_id: request.sender.id.clone(),
+            capability_id: capability.id.clone(),
+            intent: request.intent.clone(),
+            status: JobStatus::Running,
+            submitted_at: Utc::now(),
+            completed_at: None,
+            expires_at: None,
+            result: None,
+            error: None,
+        };
+        tracing::info!(
+            "Job {} started: {} for {}",
+            job.id,
+            job.capability_id,
+            job.sender_id
+        );
+        {
+            let mut jobs = self.lock();
+            jobs.insert(job.id, job.clone());
+            self.save(&jobs);
+        }
+
+        let (store, id) = (self.clone(), job.id);
+        let task = tokio::task::spawn_blocking(move || {
+            registry
+                .execute(&capability.id, &request)
+                .map_err(|e| e.to_string())
+        });
+        // A panicking capability fails the job instead of leaving it running
+        tokio::spawn(async move {
+            let outcome = task
+                .await
+                .unwrap_or_else(|e| Err(format!("Capability failed: {}", e)));
+            store.complete(id, outcome);
+        });
+        job
+    }
+
+    /// Answer a query for the sender's jobs.
+    ///
+    /// Jobs of other senders are reported as missing. With `wait` set, the
+    /// queried job is returned once it has completed.
+    pub async fn query(&self, query: &JobQuery) -> SinpResult<Vec<Job>> {
+        let sender_id = query.sender.id.as_str();
+        let Some(id) = query.job_id else {
+            return Ok(self.list(sender_id));
+        };
+        // Subscribed before checking, so a completion in between is seen
+        let mut completed = self.completions();
+        loop {
+            let job = self.get(sender_id, id)?;
+            if job.is_complete() || !query.wait {
+                return Ok(vec![job]);
+            }
+            // Missed completions only mean checking again
+            let _ = completed.recv().await;
+        }
+    }
+
+    /// Receiver of jobs as they complete, of all senders.
+    pub fn completions(&self) -> broadcast::Receiver<Job> {
+        self.inner.completed.subscribe()
+    }
+
+    /// Look up one of the sender's jobs.
+    pub fn get(&self, sender_id: &str, id: Uuid) -> SinpResult<Job> {
+        self.lock()
+            .get(&id)
+            .filter(|job| job.sender_id == sender_id)
+            .cloned()
+            .ok_or_else(|| SinpError::Validation(format!("No job {}", id)))
+    }
+
+    /// List the sender's jobs, oldest first.
+    pub fn list(&self, sender_id: &str) -> Vec<Job> {
+        let mut jobs: Vec<_> = self
+            .lock()
+            .values()
+            .filter(|job| job.sender_id == sender_id)
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|job| job.submitted_at);
+        jobs
+    }
+
+    /// Record a job's outcome and tell whoever waits on it.
+    fn complete(&self, id: Uuid, outcome: Result<serde_json::Value, String>) {
+        let completed = {
+            let mut jobs = self.lock();
+            let completed = jobs.get_mut(&id).map(|job| {
+                finish(job, outcome, self.inner.retention);
+                tracing::info!("Job {} {:?}", id, job.status);
+                job.clone()
+            });
+            self.save(&jobs);
+            completed
+        };
+        if let Some(job) = completed {
+            // Nobody may be listening
+            let _ = self.inner.completed.send(job);
+        }
+    }
+
+    /// Lock the jobs, dropping those that expired.
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Job>> {
+        let mut jobs = self.inner.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+        jobs.retain(|_, job| job.expires_at.is_none_or(|expires_at| expires_at > now));
+        jobs
+    }
+
+    /// Write the jobs to the backing file, if any.
+    ///
+    /// Failures are logged: the jobs are still served from memory.
+    fn save(&self, jobs: &HashMap<Uuid, Job>) {
+        let Some(ref path) = self.inner.path else {
+            return;
+        };
+        let mut jobs: Vec<_> = jobs.values().collect();
+        jobs.sort_by_key(|job| job.submitted_at);
+        if let Err(e) = write_atomically(path, &jobs) {
+            tracing::warn!("Failed to save job store {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn finish(job: &mut Job, outcome: Result<serde_json::Value, String>, retention: Duration) {
+    let now = Utc::now();
+    match outcome {
+        Ok(result) => {
+            job.status = JobStatus::Succeeded;
+            job.result = Some(result);
+        }
+        Err(error) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(error);
+        }
+    }
+    job.completed_at = Some(now);
+    job.expires_at = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| now.checked_add_signed(retention));
+}
+
+/// Replace `path` with the JSON of `value` without leaving a partial file.
+pub(crate) fn write_atomically(path: &Path, value: &impl serde::Serialize) -> std::io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, serde_json::to_vec_pretty(value)?)?;
+    std::fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+
+    fn registry() -> Arc<CapabilityRegistry> {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            capability(),
+            |req| {
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(serde_json::json!({ "echo": req.intent }))
+            },
+            0.95,
+        );
+        Arc::new(registry)
+    }
+
+    fn capability() -> Capability {
+        Capability {
+            id: "echo:v1".to_string(),
+            description: "Echo message".to_string(),
+            inputs: vec![],
+            privacy_level: "public".to_string(),
+            cost_units: 0.1,
+        }
+    }
+
+    fn sender(id: &str) -> Sender {
+        Sender {
+            id: id.to_string(),
+            auth_method: AuthMethod::None,
+        }
+    }
+
+    fn deferred(sender_id: &str) -> DeferredExecution {
+        let request = Request::new(
+            sender(sender_id),
+            "echo report",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        DeferredExecution {
+            request,
+            capability: capability(),
+        }
+    }
+
+    #[tokio::test]
+    async fn wait_for_completion() {
+        let store = JobStore::new(Duration::from_secs(60));
+        let job = store.spawn(deferred("client_1"), registry());
+        assert_eq!(job.status, JobStatus::Running);
+        assert!(job.expires_at.is_none());
+
+        let polled = store
+            .query(&JobQuery::job(sender("client_1"), job.id))
+            .await
+            .unwrap();
+        assert_eq!(polled[0].status, JobStatus::Running);
+
+        let mut query = JobQuery::job(sender("client_1"), job.id);
+        query.wait = true;
+        let done = store.query(&query).await.unwrap().remove(0);
+        assert_eq!(done.status, JobStatus::Succeeded);
+        assert_eq!(
+            done.result,
+            Some(serde_json::json!({ "echo": "echo report" }))
+        );
+        assert!(done.expires_at.is_some());
+
+        // Other senders neither see nor list it
+        assert!(store.get("client_2", job.id).is_err());
+        assert!(store.list("client_2").is_empty());
+        assert_eq!(store.list("client_1"), vec![done]);
+    }
+
+    #[tokio::test]
+    async fn panicking_capability_fails_job() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(capability(), |_| panic!("report generator crashed"), 0.95);
+        let store = JobStore::new(Duration::from_secs(60));
+        let job = store.spawn(deferred("client_1"), Arc::new(registry));
+
+        let mut query = JobQuery::job(sender("client_1"), job.id);
+        query.wait = true;
+        let done = store.query(&query).await.unwrap().remove(0);
+        assert_eq!(done.status, JobStatus::Failed);
+        assert!(done.error.unwrap().contains("report generator crashed"));
+    }
+
+    #[tokio::test]
+    async fn jobs_expire() {
+        let store = JobStore::new(Duration::from_millis(10));
+        let job = store.spawn(deferred("client_1"), registry());
+        let mut query = JobQuery::job(sender("client_1"), job.id);
+        query.wait = true;
+        store.query(&query).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(store.get("client_1", job.id).is_err());
+    }
+
+    #[tokio::test]
+    async fn results_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("sinp-jobs-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("jobs.json");
+
+        let store = JobStore::open(&path, Duration::from_secs(60)).unwrap();
+        let job = store.spawn(deferred("client_1"), registry());
+        let mut query = JobQuery::job(sender("client_1"), job.id);
+        query.wait = true;
+        let done = store.query(&query).await.unwrap().remove(0);
+
+        // A job cut off by the restart is reported as failed
+        let mut interrupted = done.clone();
+        interrupted.id = Uuid::new_v4();
+        interrupted.status = JobStatus::Running;
+        std::fs::write(&path, serde_json::to_vec(&[&done, &interrupted]).unwrap()).unwrap();
+
+        let reopened = JobStore::open(&path, Duration::from_secs(60)).unwrap();
+        assert_eq!(reopened.get("client_1", job.id).unwrap(), done);
+        let interrupted = reopened.get("client_1", interrupted.id).unwrap();
+        assert_eq!(interrupted.status, JobStatus::Failed);
+        assert!(interrupted.error.unwrap().contains("restart"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
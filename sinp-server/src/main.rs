@@ -1,23 +1,60 @@
 //! SINP Server - Semantic Intent Negotiation Protocol server implementation.
 
+mod approval;
+mod auth;
 mod capability;
 mod config;
+mod discovery;
+mod events;
 mod handler;
+mod http_proxy;
+mod idempotency;
+mod jobs;
+mod manifest;
+mod openapi;
+mod plan;
+mod plugin;
+mod progress;
+mod scheduler;
 mod state_machine;
+mod subprocess;
 
-pub use capability::CapabilityRegistry;
+pub use approval::{ApprovalConfig, ApprovalDecision, ApprovalQueue, PendingApproval};
+pub use auth::{ApiKeyAuthenticator, Authenticator, Authenticators, TokenAuthenticator, TokenClaims};
+pub use capability::{CapabilityRegistry, SharedRegistry};
 pub use config::{ServerConfig, TlsConfig};
 pub use handler::Server;
+pub use http_proxy::{HttpProxyConfig, ParameterLocation, ParameterMapping, ParameterType};
+pub use idempotency::IdempotencyStore;
+pub use jobs::JobStore;
+pub use manifest::{HandlerSpec, Manifest, ManifestEntry, ManifestLoader};
+pub use plugin::{Plugin, PluginHost, PluginLimits};
+pub use progress::ProgressSink;
+pub use scheduler::Scheduler;
 pub use state_machine::ServerStateMachine;
+pub use subprocess::SubprocessConfig;
 
-use sinp_core::{Capability, Request, SinpResult};
+use sinp_core::{Capability, Request, SinpError, SinpResult};
 use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
 
 #[tokio::main]
 async fn main() -> SinpResult<()> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
+    // `import-openapi <spec> [base-url]` prints a capability manifest
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("import-openapi") {
+        let spec = args.get(2).ok_or_else(|| {
+            SinpError::Validation("usage: sinp-server import-openapi <spec> [base-url]".to_string())
+        })?;
+        let manifest = openapi::import_file(spec, args.get(3).map(String::as_str))?;
+        println!("{}", serde_json::to_string_pretty(&manifest)?);
+        return Ok(());
+    }
+
     // Parse command line args
     let bind_addr: SocketAddr = std::env::args()
         .nth(1)
@@ -26,8 +63,15 @@ async fn main() -> SinpResult<()> {
         .expect("Invalid bind address");
 
     // Create config with lower thresholds for testing
-    let config = ServerConfig::with_addr(bind_addr)
-        .with_thresholds(sinp_core::Thresholds::new(0.20, 0.10, 0.10));
+    let mut config = ServerConfig::with_addr(bind_addr)
+        .with_thresholds(sinp_core::Thresholds::new(0.20, 0.10, 0.10))
+        .with_approval(ApprovalConfig::default());
+    if let Ok(path) = std::env::var("SINP_JOBS") {
+        config = config.with_job_store(path);
+    }
+    if let Ok(path) = std::env::var("SINP_SCHEDULES") {
+        config = config.with_schedule_store(path);
+    }
 
     // Create capability registry with example capabilities
     let mut registry = CapabilityRegistry::new();
@@ -41,12 +85,7 @@ async fn main() -> SinpResult<()> {
             privacy_level: "public".to_string(),
             cost_units: 0.1,
         },
-        |req: &Request| {
-            Ok(serde_json::json!({
-                "echo": req.intent,
-                "timestamp": chrono::Utc::now().to_rfc3339()
-            }))
-        },
+        echo,
         0.95,
     );
 
@@ -68,10 +107,99 @@ async fn main() -> SinpResult<()> {
         0.99,
     );
 
+    // Third-party capabilities compiled to WebAssembly
+    if let Ok(dir) = std::env::var("SINP_PLUGINS") {
+        for plugin in PluginHost::new().load_dir(dir)? {
+            plugin.register(&mut registry)?;
+        }
+    }
+
+    // Operations of a REST service described by an OpenAPI document
+    if let Ok(spec) = std::env::var("SINP_OPENAPI") {
+        let base_url = std::env::var("SINP_OPENAPI_BASE_URL").ok();
+        let manifest = openapi::import_file(spec, base_url.as_deref())?;
+        manifest::register(&mut registry, manifest)?;
+    }
+
+    // Capabilities declared in manifests, reloaded when they change
+    let loader = match std::env::var("SINP_MANIFESTS") {
+        Ok(path) => {
+            let loader = ManifestLoader::new(path, registry).with_handler("echo", echo);
+            registry = loader.load()?;
+            Some(loader)
+        }
+        Err(_) => None,
+    };
+
     tracing::info!("Starting SINP server on {}", bind_addr);
     tracing::info!("Registered capabilities: {:?}", registry.capability_ids());
 
+    // Credential-based authentication, if configured
+    let mut authenticators = Authenticators::new();
+    if let Ok(path) = std::env::var("SINP_API_KEYS") {
+        authenticators = authenticators.with_api_key(ApiKeyAuthenticator::from_file(path)?);
+    }
+    if let Ok(secret) = std::env::var("SINP_TOKEN_SECRET") {
+        authenticators = authenticators.with_token(TokenAuthenticator::new(secret));
+    }
+
     // Create and run server
-    let server = Server::new(config, registry)?;
+    let server = Server::new(config, registry)?.with_authenticators(authenticators);
+    spawn_operator_console(server.approvals());
+    if let Some(loader) = loader {
+        loader.watch(server.registry(), Duration::from_secs(2));
+    }
     server.run().await
 }
+
+/// Echo the request intent back.
+fn echo(req: &Request) -> SinpResult<serde_json::Value> {
+    Ok(serde_json::json!({
+        "echo": req.intent,
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    }))
+}
+
+/// Read operator commands from stdin: `list`, `approve <id>`, `deny <id> [reason]`.
+fn spawn_operator_console(approvals: ApprovalQueue) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let mut parts = line.split_whitespace();
+            let command = parts.next().unwrap_or_default();
+            let id = parts.next().and_then(|id| id.parse().ok());
+            let result = match (command, id) {
+                ("list", _) => {
+                    for pending in approvals.pending() {
+                        println!(
+                            "{} {} {} \"{}\" (cost {})",
+                            pending.id,
+                            pending.sender_id,
+                            pending.capability_id,
+                            pending.intent,
+                            pending.estimated_cost
+                        );
+                    }
+                    Ok(())
+                }
+                ("approve", Some(id)) => approvals.approve(id),
+                ("deny", Some(id)) => {
+                    let reason = parts.collect::<Vec<_>>().join(" ");
+                    if reason.is_empty() {
+                        approvals.deny(id, "denied by operator")
+                    } else {
+                        approvals.deny(id, reason)
+                    }
+                }
+                ("", _) => Ok(()),
+                _ => {
+                    println!("usage: list | approve <id> | deny <id> [reason]");
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,543 @@
+//! Capability manifests loaded from disk.
+//!
+//! A manifest is a JSON document listing capabilities. Each entry names a
+//! handler registered with the [`ManifestLoader`], or configures an
+//! external executable or HTTP backend; descriptions, inputs, reliability
+//! and thresholds can be edited and reloaded without a restart. A directory
+//! is loaded as all of its `*.json` files, in name order.
+//!
+//! A reload either applies every manifest or none of them: all validation
+//! errors are reported together and the live registry is left untouched.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use sinp_core::{Capability, Request, SinpError, SinpResult, Thresholds};
+
+use crate::capability::{CapabilityHandler, CapabilityRegistry, SharedRegistry};
+use crate::http_proxy::{self, HttpProxyConfig};
+use crate::subprocess::{self, SubprocessConfig};
+
+/// Privacy levels a manifest may declare.
+pub const PRIVACY_LEVELS: &[&str] = &["public", "private", "pii_sensitive"];
+
+/// Contents of one manifest file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub capabilities: Vec<ManifestEntry>,
+}
+
+/// Capability declared in a manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    pub id: String,
+    pub description: String,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Inputs the capability cannot run without.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required_inputs: Vec<String>,
+    #[serde(default = "default_privacy_level")]
+    pub privacy_level: String,
+    #[serde(default)]
+    pub cost_units: f64,
+    pub reliability: f64,
+    /// Overrides the thresholds derived from privacy level and cost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<Thresholds>,
+    pub handler: HandlerSpec,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_senders: Option<Vec<String>>,
+    #[serde(default = "default_stable")]
+    pub stable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<ManifestDeprecation>,
+}
+
+/// Handler of a manifest capability.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HandlerSpec {
+    /// Name of a handler registered with the loader.
+    Named(String),
+    /// External executable, as `{"subprocess": {...}}`.
+    Subprocess { subprocess: SubprocessConfig },
+    /// HTTP backend, as `{"http": {...}}`.
+    Http { http: HttpProxyConfig },
+}
+
+/// Deprecation declared in a manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestDeprecation {
+    pub sunset: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replacement: Option<String>,
+}
+
+fn default_privacy_level() -> String {
+    "public".to_string()
+}
+
+fn default_stable() -> bool {
+    true
+}
+
+impl ManifestEntry {
+    /// Problems with this entry, if any.
+    fn validate(&self, handlers: &HashMap<String, CapabilityHandler>) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.id.trim().is_empty() {
+            errors.push("id is empty".to_string());
+        }
+        match self.handler {
+            HandlerSpec::Named(ref name) => {
+                if !handlers.contains_key(name) {
+                    errors.push(format!("unknown handler {:?}", name));
+                }
+            }
+            HandlerSpec::Subprocess { ref subprocess } => errors.extend(subprocess.validate()),
+            HandlerSpec::Http { ref http } => errors.extend(http.validate()),
+        }
+        for input in &self.required_inputs {
+            if !self.inputs.contains(input) {
+                errors.push(format!("required input {:?} is not an input", input));
+            }
+        }
+        if !PRIVACY_LEVELS.contains(&self.privacy_level.as_str()) {
+            errors.push(format!("unknown privacy level {:?}", self.privacy_level));
+        }
+        if !(self.cost_units >= 0.0 && self.cost_units.is_finite()) {
+            errors.push(format!(
+                "cost_units must be non-negative, got {}",
+                self.cost_units
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.reliability) {
+            errors.push(format!(
+                "reliability must be in [0, 1], got {}",
+                self.reliability
+            ));
+        }
+        if let Some(t) = self.thresholds {
+            for (name, value) in [
+                ("tau_exec", t.tau_exec),
+                ("tau_clarify", t.tau_clarify),
+                ("tau_accept", t.tau_accept),
+            ] {
+                if !(0.0..=1.0).contains(&value) {
+                    errors.push(format!("{} must be in [0, 1], got {}", name, value));
+                }
+            }
+            if t.tau_clarify > t.tau_exec {
+                errors.push("tau_clarify must not exceed tau_exec".to_string());
+            }
+        }
+        errors
+    }
+
+    fn capability(&self) -> Capability {
+        Capability {
+            id: self.id.clone(),
+            description: self.description.clone(),
+            inputs: self.inputs.clone(),
+            privacy_level: self.privacy_level.clone(),
+            cost_units: self.cost_units,
+        }
+    }
+}
+
+/// Builds registries from manifests on disk.
+///
+/// Manifest capabilities are added on top of a base registry of
+/// capabilities registered in code.
+#[derive(Clone)]
+pub struct ManifestLoader {
+    path: PathBuf,
+    base: CapabilityRegistry,
+    handlers: HashMap<String, CapabilityHandler>,
+}
+
+impl ManifestLoader {
+    /// Load manifests from a file or directory on top of `base`.
+    pub fn new(path: impl Into<PathBuf>, base: CapabilityRegistry) -> Self {
+        Self {
+            path: path.into(),
+            base,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Make a handler available to manifests under `name`.
+    pub fn with_handler<F>(self, name: &str, handler: F) -> Self
+    where
+        F: Fn(&Request) -> SinpResult<serde_json::Value> + Send + Sync + 'static,
+    {
+        self.with_shared_handler(name, Arc::new(handler))
+    }
+
+    /// Make a shared handler available to manifests under `name`.
+    pub fn with_shared_handler(mut self, name: &str, handler: CapabilityHandler) -> Self {
+        self.handlers.insert(name.to_string(), handler);
+        self
+    }
+
+    /// Build a registry from the base and the manifests.
+    ///
+    /// Fails with every validation error found if any manifest is invalid.
+    pub fn load(&self) -> SinpResult<CapabilityRegistry> {
+        let mut errors = Vec::new();
+        let mut entries = Vec::new();
+        for file in self.files()? {
+            match read_manifest(&file) {
+                Ok(manifest) => entries.extend(
+                    manifest
+                        .capabilities
+                        .into_iter()
+                        .map(|e| (file.display().to_string(), e)),
+                ),
+                Err(e) => errors.push(format!("{}: {}", file.display(), e)),
+            }
+        }
+
+        let mut registry = self.base.clone();
+        apply(&mut registry, entries, &self.handlers, errors)?;
+        Ok(registry)
+    }
+
+    /// Load the manifests and swap them into the live registry.
+    ///
+    /// On failure the live registry is unchanged.
+    pub fn reload(&self, shared: &SharedRegistry) -> SinpResult<()> {
+        let registry = self.load()?;
+        tracing::info!("Loaded capabilities: {:?}", registry.capability_ids());
+        shared.replace(registry);
+        Ok(())
+    }
+
+    /// Poll the manifests and reload the live registry when they change.
+    ///
+    /// Rejected reloads are logged and the previous registry stays live.
+    pub fn watch(self, shared: SharedRegistry, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let mut last = self.fingerprint();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let current = self.fingerprint();
+                if current == last {
+                    continue;
+                }
+                last = current;
+                if let Err(e) = self.reload(&shared) {
+                    tracing::warn!("Manifest reload rejected: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Manifest files, in name order.
+    fn files(&self) -> SinpResult<Vec<PathBuf>> {
+        if !self.path.is_dir() {
+            return Ok(vec![self.path.clone()]);
+        }
+        let entries = std::fs::read_dir(&self.path).map_err(|e| {
+            SinpError::Validation(format!("Failed to read {}: {}", self.path.display(), e))
+        })?;
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Modification time and size of every manifest file.
+    fn fingerprint(&self) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+        self.files()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|file| {
+                let meta = std::fs::metadata(&file).ok();
+                let modified = meta.as_ref().and_then(|m| m.modified().ok());
+                let len = meta.map(|m| m.len()).unwrap_or_default();
+                (file, modified, len)
+            })
+            .collect()
+    }
+}
+
+/// Add the capabilities of a manifest to a registry.
+///
+/// Only subprocess and HTTP handlers are available; nothing is registered
+/// unless the whole manifest is valid.
+pub fn register(registry: &mut CapabilityRegistry, manifest: Manifest) -> SinpResult<()> {
+    let entries = manifest
+        .capabilities
+        .into_iter()
+        .map(|e| ("manifest".to_string(), e))
+        .collect();
+    let mut updated = registry.clone();
+    apply(&mut updated, entries, &HashMap::new(), Vec::new())?;
+    *registry = updated;
+    Ok(())
+}
+
+/// Validate manifest entries, then register them all.
+///
+/// `errors` are problems already found while reading the manifests; each
+/// entry is paired with the manifest it came from.
+fn apply(
+    registry: &mut CapabilityRegistry,
+    entries: Vec<(String, ManifestEntry)>,
+    handlers: &HashMap<String, CapabilityHandler>,
+    mut errors: Vec<String>,
+) -> SinpResult<()> {
+    let mut seen: HashSet<String> = registry.capability_ids().into_iter().collect();
+    for (source, entry) in &entries {
+        let mut problems = entry.validate(handlers);
+        if !seen.insert(entry.id.clone()) {
+            problems.push("duplicate capability id".to_string());
+        }
+        errors.extend(
+            problems
+                .into_iter()
+                .map(|p| format!("{}: {}: {}", source, entry.id, p)),
+        );
+    }
+    if !errors.is_empty() {
+        return Err(SinpError::Validation(format!(
+            "Invalid capability manifest: {}",
+            errors.join("; ")
+        )));
+    }
+
+    for (_, entry) in entries {
+        let id = entry.id.clone();
+        let capability = entry.capability();
+        let handler = match entry.handler {
+            HandlerSpec::Named(ref name) => Arc::clone(&handlers[name]),
+            HandlerSpec::Subprocess { subprocess } => subprocess::handler(&capability, subprocess),
+            HandlerSpec::Http { http } => http_proxy::handler(&capability, http),
+        };
+        registry.register_handler(capability, handler, entry.reliability);
+        if let Some(thresholds) = entry.thresholds {
+            registry.set_thresholds(&id, thresholds)?;
+        }
+        if let Some(senders) = entry.allowed_senders {
+            registry.restrict(&id, senders)?;
+        }
+        registry.require_inputs(&id, entry.required_inputs)?;
+        registry.set_stable(&id, entry.stable)?;
+        if let Some(deprecation) = entry.deprecation {
+            registry.deprecate(&id, deprecation.sunset, deprecation.replacement.as_deref())?;
+        }
+    }
+    Ok(())
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Sender};
+
+    /// Scratch directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl std::ops::Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_dir() -> TempDir {
+        let dir = std::env::temp_dir().join(format!("sinp-manifests-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn base() -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "help:v1".to_string(),
+                description: "Get help".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |_req: &Request| Ok(serde_json::json!({})),
+            0.99,
+        );
+        registry
+    }
+
+    fn loader(path: &Path) -> ManifestLoader {
+        ManifestLoader::new(path, base()).with_handler("echo", |req: &Request| {
+            Ok(serde_json::json!({ "echo": req.intent }))
+        })
+    }
+
+    const ECHO: &str = r#"{"capabilities": [{
+        "id": "echo:v1",
+        "description": "Echo back a message",
+        "inputs": ["message"],
+        "required_inputs": ["message"],
+        "reliability": 0.9,
+        "thresholds": {"tau_exec": 0.7, "tau_clarify": 0.4, "tau_accept": 0.5},
+        "handler": "echo",
+        "allowed_senders": ["client_1"]
+    }]}"#;
+
+    #[test]
+    fn load_manifest() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("echo.json"), ECHO).unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a manifest").unwrap();
+
+        let registry = loader(&dir).load().unwrap();
+        let mut ids = registry.capability_ids();
+        ids.sort();
+        assert_eq!(ids, vec!["echo:v1", "help:v1"]);
+        assert_eq!(registry.get_reliability("echo:v1"), 0.9);
+        assert_eq!(registry.required_inputs("echo:v1"), ["message"]);
+        assert_eq!(
+            registry.thresholds_for("echo:v1", &Thresholds::default()),
+            Thresholds::new(0.7, 0.4, 0.5)
+        );
+        let other = Sender {
+            id: "client_2".to_string(),
+            auth_method: AuthMethod::None,
+        };
+        assert!(!registry.is_permitted("echo:v1", &other));
+    }
+
+    #[test]
+    fn subprocess_handler() {
+        let dir = temp_dir();
+        std::fs::write(
+            dir.join("tool.json"),
+            r#"{"capabilities": [{
+                "id": "tool:v1", "description": "Run a tool", "inputs": ["name"],
+                "reliability": 0.9,
+                "handler": {"subprocess": {"program": "/bin/sh", "args": ["-c", "cat"],
+                            "timeout_ms": 5000}}
+            }]}"#,
+        )
+        .unwrap();
+
+        let registry = loader(&dir).load().unwrap();
+        let request = Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            "run tool name=Ada",
+            0.9,
+            sinp_core::Context {
+                context_type: sinp_core::ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        let result = registry.execute("tool:v1", &request).unwrap();
+        assert_eq!(result["parameters"]["name"], "Ada");
+    }
+
+    #[test]
+    fn validation_errors_are_reported_together() {
+        let dir = temp_dir();
+        std::fs::write(
+            dir.join("bad.json"),
+            r#"{"capabilities": [
+                {"id": "a:v1", "description": "", "reliability": 1.5, "handler": "echo"},
+                {"id": "b:v1", "description": "", "reliability": 0.5, "handler": "missing",
+                 "privacy_level": "secret"},
+                {"id": "help:v1", "description": "", "reliability": 0.5, "handler": "echo"}
+            ]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+
+        let Err(err) = loader(&dir).load() else {
+            panic!("expected validation errors");
+        };
+        let err = err.to_string();
+        for expected in [
+            "reliability must be in [0, 1]",
+            "unknown handler \"missing\"",
+            "unknown privacy level \"secret\"",
+            "help:v1: duplicate capability id",
+            "broken.json",
+        ] {
+            assert!(err.contains(expected), "{} missing from {}", expected, err);
+        }
+    }
+
+    #[test]
+    fn reload_is_atomic() {
+        let dir = temp_dir();
+        let file = dir.join("echo.json");
+        std::fs::write(&file, ECHO).unwrap();
+        let loader = loader(&file);
+        let shared = SharedRegistry::new(loader.load().unwrap());
+        let snapshot = shared.current();
+
+        // An invalid manifest leaves the live registry alone
+        std::fs::write(&file, ECHO.replace("0.9", "9")).unwrap();
+        assert!(loader.reload(&shared).is_err());
+        assert_eq!(shared.current().get_reliability("echo:v1"), 0.9);
+
+        std::fs::write(&file, ECHO.replace("0.9", "0.8")).unwrap();
+        loader.reload(&shared).unwrap();
+        assert_eq!(shared.current().get_reliability("echo:v1"), 0.8);
+        // Requests already in flight keep the registry they started with
+        assert_eq!(snapshot.get_reliability("echo:v1"), 0.9);
+    }
+
+    #[tokio::test]
+    async fn watch_applies_changes() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("echo.json"), ECHO).unwrap();
+        let loader = loader(&dir);
+        let shared = SharedRegistry::new(loader.load().unwrap());
+        let watcher = loader.watch(shared.clone(), Duration::from_millis(10));
+
+        std::fs::write(dir.join("echo.json"), ECHO.replace("echo:v1", "echo:v2")).unwrap();
+        let reloaded = async {
+            while !shared
+                .current()
+                .capability_ids()
+                .contains(&"echo:v2".to_string())
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), reloaded)
+            .await
+            .unwrap();
+        assert!(!shared
+            .current()
+            .capability_ids()
+            .contains(&"echo:v1".to_string()));
+        watcher.abort();
+    }
+}
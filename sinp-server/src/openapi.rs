@@ -0,0 +1,380 @@
+//! Import capabilities from OpenAPI 3 documents.
+//!
+//! Every operation becomes a manifest entry with an HTTP-proxy handler: the
+//! operationId names the capability, the summary describes it, and path,
+//! query and header parameters and JSON request body properties become its
+//! inputs. The capability version is the major version of the API.
+//!
+//! Operations may set `x-sinp-privacy-level`, `x-sinp-cost-units` and
+//! `x-sinp-reliability`; otherwise capabilities are public, free and have
+//! [`DEFAULT_RELIABILITY`].
+
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use sinp_core::{SinpError, SinpResult};
+
+use crate::http_proxy::{HttpProxyConfig, ParameterLocation, ParameterMapping, ParameterType};
+use crate::manifest::{HandlerSpec, Manifest, ManifestEntry};
+
+/// Reliability of imported capabilities that do not declare one.
+pub const DEFAULT_RELIABILITY: f64 = 0.9;
+
+/// HTTP methods imported as capabilities.
+const METHODS: &[&str] = &["get", "put", "post", "delete", "patch", "head"];
+
+/// Import an OpenAPI 3 document in YAML or JSON.
+///
+/// `base_url` overrides the document's first server URL.
+pub fn import_file(path: impl AsRef<Path>, base_url: Option<&str>) -> SinpResult<Manifest> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| SinpError::Validation(format!("Failed to read {}: {}", path.display(), e)))?;
+    // YAML is a superset of JSON, so one parser reads both
+    let document: Value = serde_yaml::from_str(&contents)
+        .map_err(|e| SinpError::Validation(format!("Failed to parse {}: {}", path.display(), e)))?;
+    import(&document, base_url)
+}
+
+/// Import the operations of an OpenAPI 3 document.
+///
+/// `base_url` overrides the document's first server URL.
+pub fn import(document: &Value, base_url: Option<&str>) -> SinpResult<Manifest> {
+    let openapi = document["openapi"].as_str().unwrap_or_default();
+    if !openapi.starts_with("3.") {
+        return Err(SinpError::Validation(
+            "Not an OpenAPI 3 document".to_string(),
+        ));
+    }
+
+    let base_url = base_url
+        .or_else(|| document["servers"][0]["url"].as_str())
+        .ok_or_else(|| SinpError::Validation("No server URL in document".to_string()))?
+        .trim_end_matches('/');
+    if !(base_url.starts_with("http://") || base_url.starts_with("https://")) {
+        return Err(SinpError::Validation(format!(
+            "Server URL {} is not absolute; pass a base URL",
+            base_url
+        )));
+    }
+
+    let major = document["info"]["version"]
+        .as_str()
+        .and_then(|v| {
+            v.trim_start_matches('v')
+                .split('.')
+                .next()?
+                .parse::<u32>()
+                .ok()
+        })
+        .unwrap_or(1);
+
+    let mut capabilities = Vec::new();
+    let paths = document["paths"].as_object().cloned().unwrap_or_default();
+    for (path, item) in &paths {
+        let item = resolve(document, item)?;
+        for method in METHODS {
+            let Some(operation) = item.get(*method) else {
+                continue;
+            };
+            capabilities.push(import_operation(
+                document, base_url, major, path, method, item, operation,
+            )?);
+        }
+    }
+
+    Ok(Manifest { capabilities })
+}
+
+fn import_operation(
+    document: &Value,
+    base_url: &str,
+    major: u32,
+    path: &str,
+    method: &str,
+    item: &Value,
+    operation: &Value,
+) -> SinpResult<ManifestEntry> {
+    let name = match operation["operationId"].as_str() {
+        Some(id) => id.to_string(),
+        None => derived_name(method, path),
+    };
+
+    // Operation parameters override path-level ones with the same name and location
+    let mut parameters: BTreeMap<(String, String), &Value> = BTreeMap::new();
+    for list in [&item["parameters"], &operation["parameters"]] {
+        for parameter in list.as_array().into_iter().flatten() {
+            let parameter = resolve(document, parameter)?;
+            let key = (
+                parameter["name"].as_str().unwrap_or_default().to_string(),
+                parameter["in"].as_str().unwrap_or_default().to_string(),
+            );
+            parameters.insert(key, parameter);
+        }
+    }
+
+    let mut inputs = Vec::new();
+    let mut required_inputs = Vec::new();
+    let mut mappings = BTreeMap::new();
+    for ((name, location), parameter) in parameters {
+        let location = match location.as_str() {
+            "path" => ParameterLocation::Path,
+            "query" => ParameterLocation::Query,
+            "header" => ParameterLocation::Header,
+            _ => continue,
+        };
+        let kind = schema_type(resolve(document, &parameter["schema"])?);
+        if parameter["required"].as_bool() == Some(true) {
+            required_inputs.push(name.clone());
+        }
+        inputs.push(name.clone());
+        mappings.insert(
+            name,
+            ParameterMapping {
+                location,
+                name: None,
+                kind,
+            },
+        );
+    }
+
+    let body = resolve(document, &operation["requestBody"])?;
+    let schema = resolve(document, &body["content"]["application/json"]["schema"])?;
+    let required = schema["required"].as_array().cloned().unwrap_or_default();
+    for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+        let kind = schema_type(resolve(document, property)?);
+        if !inputs.contains(name) {
+            inputs.push(name.clone());
+        }
+        if required.iter().any(|r| r == name) && !required_inputs.contains(name) {
+            required_inputs.push(name.clone());
+        }
+        mappings.insert(
+            name.clone(),
+            ParameterMapping {
+                location: ParameterLocation::Body,
+                name: None,
+                kind,
+            },
+        );
+    }
+
+    let description = operation["summary"]
+        .as_str()
+        .or_else(|| operation["description"].as_str())
+        .unwrap_or(&name)
+        .to_string();
+
+    let mut http = HttpProxyConfig::new(method, format!("{}{}", base_url, path));
+    http.parameters = mappings;
+
+    Ok(ManifestEntry {
+        id: format!("{}:v{}", name, major),
+        description,
+        inputs,
+        required_inputs,
+        privacy_level: operation["x-sinp-privacy-level"]
+            .as_str()
+            .unwrap_or("public")
+            .to_string(),
+        cost_units: operation["x-sinp-cost-units"].as_f64().unwrap_or(0.0),
+        reliability: operation["x-sinp-reliability"]
+            .as_f64()
+            .unwrap_or(DEFAULT_RELIABILITY),
+        thresholds: None,
+        handler: HandlerSpec::Http { http },
+        allowed_senders: None,
+        stable: true,
+        deprecation: None,
+    })
+}
+
+/// Follow local `$ref`s such as `#/components/schemas/Booking`.
+fn resolve<'a>(document: &'a Value, mut value: &'a Value) -> SinpResult<&'a Value> {
+    for _ in 0..16 {
+        let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+            return Ok(value);
+        };
+        value = reference
+            .strip_prefix('#')
+            .and_then(|pointer| document.pointer(pointer))
+            .ok_or_else(|| SinpError::Validation(format!("Unresolvable $ref {}", reference)))?;
+    }
+    Err(SinpError::Validation("$ref chain too deep".to_string()))
+}
+
+fn schema_type(schema: &Value) -> Option<ParameterType> {
+    match schema["type"].as_str()? {
+        "string" => Some(ParameterType::String),
+        "integer" => Some(ParameterType::Integer),
+        "number" => Some(ParameterType::Number),
+        "boolean" => Some(ParameterType::Boolean),
+        _ => None,
+    }
+}
+
+/// Capability name for an operation without an operationId, e.g.
+/// `get_flights_origin` for `GET /flights/{origin}`.
+fn derived_name(method: &str, path: &str) -> String {
+    let path: Vec<String> = path
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect();
+    format!("{}_{}", method, path.join("_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capability::CapabilityRegistry;
+    use crate::http_proxy::tests::stub_backend;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+    use sinp_core::Request;
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+info:
+  title: Flights
+  version: 2.1.0
+servers:
+  - url: https://flights.internal/api/
+paths:
+  /flights/{origin}:
+    parameters:
+      - $ref: "#/components/parameters/Origin"
+    get:
+      operationId: search_flights
+      summary: Search flights from an airport
+      parameters:
+        - name: limit
+          in: query
+          schema: {type: integer}
+        - name: session
+          in: cookie
+  /bookings:
+    post:
+      operationId: book_flight
+      description: Book a flight
+      x-sinp-privacy-level: pii_sensitive
+      x-sinp-cost-units: 5
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Booking"
+    delete: {}
+components:
+  parameters:
+    Origin:
+      name: origin
+      in: path
+      required: true
+      schema: {type: string}
+  schemas:
+    Booking:
+      type: object
+      required: [flight]
+      properties:
+        flight: {type: string}
+        seats: {type: integer}
+"##;
+
+    fn document() -> Value {
+        serde_yaml::from_str(SPEC).unwrap()
+    }
+
+    fn http(entry: &ManifestEntry) -> &HttpProxyConfig {
+        match entry.handler {
+            HandlerSpec::Http { ref http } => http,
+            _ => panic!("expected an HTTP handler"),
+        }
+    }
+
+    #[test]
+    fn import_operations() {
+        let manifest = import(&document(), None).unwrap();
+        let ids: Vec<&str> = manifest
+            .capabilities
+            .iter()
+            .map(|c| c.id.as_str())
+            .collect();
+        assert_eq!(
+            ids,
+            vec!["book_flight:v2", "delete_bookings:v2", "search_flights:v2"]
+        );
+
+        let book = &manifest.capabilities[0];
+        assert_eq!(book.description, "Book a flight");
+        assert_eq!(book.inputs, vec!["flight", "seats"]);
+        assert_eq!(book.required_inputs, vec!["flight"]);
+        assert_eq!(book.privacy_level, "pii_sensitive");
+        assert_eq!(book.cost_units, 5.0);
+        assert_eq!(http(book).method, "POST");
+        assert_eq!(http(book).url, "https://flights.internal/api/bookings");
+        assert_eq!(
+            http(book).parameters["seats"].kind,
+            Some(ParameterType::Integer)
+        );
+
+        let search = &manifest.capabilities[2];
+        assert_eq!(search.description, "Search flights from an airport");
+        assert_eq!(search.inputs, vec!["limit", "origin"]);
+        assert_eq!(search.required_inputs, vec!["origin"]);
+        assert_eq!(search.reliability, DEFAULT_RELIABILITY);
+        assert_eq!(
+            http(search).parameters["origin"].location,
+            ParameterLocation::Path
+        );
+        assert_eq!(
+            http(search).parameters["limit"].location,
+            ParameterLocation::Query
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        let swagger = serde_json::json!({"swagger": "2.0", "paths": {}});
+        assert!(import(&swagger, None).is_err());
+
+        let mut relative = document();
+        relative["servers"][0]["url"] = "/api".into();
+        let err = import(&relative, None).unwrap_err().to_string();
+        assert!(err.contains("not absolute"), "{}", err);
+        assert!(import(&relative, Some("http://localhost:8080/api")).is_ok());
+
+        let mut dangling = document();
+        dangling["components"]["schemas"] = serde_json::json!({});
+        let err = import(&dangling, None).unwrap_err().to_string();
+        assert!(err.contains("#/components/schemas/Booking"), "{}", err);
+    }
+
+    #[test]
+    fn imported_capabilities_forward_requests() {
+        let (base, received) = stub_backend(200, r#"{"results": []}"#);
+        let manifest = import(&document(), Some(&base)).unwrap();
+        let mut registry = CapabilityRegistry::new();
+        crate::manifest::register(&mut registry, manifest).unwrap();
+
+        let request = Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            "search flights origin=LHR limit=5",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        let result = registry.execute("search_flights:v2", &request).unwrap();
+        assert_eq!(result["results"], serde_json::json!([]));
+        assert_eq!(
+            received.recv().unwrap().request_line,
+            "GET /flights/LHR?limit=5 HTTP/1.1"
+        );
+    }
+}
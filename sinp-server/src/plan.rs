@@ -0,0 +1,467 @@
+//! Composition of capabilities for compound intents.
+//!
+//! An intent such as "check the weather in Paris and email it to Bob" is
+//! split into clauses that are interpreted one by one. If every clause
+//! matches a capability the intent becomes a [`Plan`] whose steps run in
+//! order. A clause referring back to an earlier one ("it", "that", "the
+//! result") receives the previous step's result as its first unfilled input.
+//!
+//! If a step fails, the completed steps before it are compensated in reverse
+//! order by the compensating handlers of their capabilities, if any.
+
+use serde_json::{Map, Value};
+
+use sinp_core::interpreter::{extract_parameters, split_compound};
+use sinp_core::{
+    compute_server_confidence, Plan, PlanStep, Request, StepResult, StepStatus, Thresholds,
+};
+
+use crate::capability::CapabilityRegistry;
+use crate::progress::ProgressSink;
+
+/// Words by which a clause refers to the previous step's result.
+const BACK_REFERENCES: &[&str] = &["it", "that", "this", "them", "result"];
+
+/// Plan for a compound intent.
+///
+/// Returns `None` unless the intent has at least two clauses and each one
+/// matches a permitted capability with Φ_s ≥ τ_clarify. Each step is held to
+/// its own capability's thresholds, derived from `base`.
+pub fn compose(
+    registry: &CapabilityRegistry,
+    request: &Request,
+    base: &Thresholds,
+) -> Option<Plan> {
+    let clauses = split_compound(&request.intent);
+    if clauses.len() < 2 {
+        return None;
+    }
+
+    let mut steps: Vec<PlanStep> = Vec::new();
+    let mut estimated_cost = 0.0;
+    for clause in clauses {
+        let mut sub = request.clone();
+        sub.intent = clause;
+        let interpretation = registry.interpret(&sub);
+        let capability = interpretation.capability?;
+        let policy = registry.check_policy(&capability.id, &sub);
+        let confidence = compute_server_confidence(
+            interpretation.raw_confidence,
+            registry.get_reliability(&capability.id),
+            1.0,
+            policy,
+        );
+        if !policy || confidence < registry.thresholds_for(&capability.id, base).tau_clarify {
+            return None;
+        }
+
+        let mut parameters = interpretation.parameters;
+        for (name, value) in &request.parameters {
+            if capability.inputs.contains(name) && !parameters.contains_key(name) {
+                parameters.insert(name.clone(), value.clone());
+            }
+        }
+
+        let required = registry.required_inputs(&capability.id);
+        let mut step = PlanStep {
+            capability_id: capability.id.clone(),
+            intent: sub.intent,
+            confidence,
+            parameters,
+            bindings: Default::default(),
+            missing: Vec::new(),
+        };
+
+        if !steps.is_empty() && refers_back(&step.intent) {
+            let target = required
+                .iter()
+                .chain(&capability.inputs)
+                .find(|input| !step.parameters.contains_key(*input));
+            if let Some(target) = target {
+                step.bindings.insert(target.clone(), steps.len() - 1);
+            }
+        }
+        step.missing = required
+            .iter()
+            .filter(|input| {
+                !step.parameters.contains_key(*input) && !step.bindings.contains_key(*input)
+            })
+            .cloned()
+            .collect();
+
+        estimated_cost += capability.cost_units;
+        steps.push(step);
+    }
+
+    Some(Plan {
+        confidence: steps.iter().map(|s| s.confidence).fold(1.0, f64::min),
+        estimated_cost,
+        steps,
+    })
+}
+
+/// Fill missing inputs from a client's answer.
+///
+/// Returns whether the answer provided any of them.
+pub fn answer(plan: &mut Plan, reply: &Request) -> bool {
+    let mut answered = false;
+    for step in &mut plan.steps {
+        let mut given = extract_parameters(&reply.intent, &step.missing);
+        for input in &step.missing {
+            if let Some(value) = reply.parameters.get(input) {
+                given.insert(input.clone(), value.clone());
+            }
+        }
+        step.missing.retain(|input| !given.contains_key(input));
+        answered |= !given.is_empty();
+        step.parameters.extend(given);
+    }
+    answered
+}
+
+/// Questions asking for the inputs a plan is missing.
+pub fn questions(plan: &Plan) -> Vec<String> {
+    plan.steps
+        .iter()
+        .enumerate()
+        .flat_map(|(index, step)| {
+            step.missing.iter().map(move |input| {
+                format!(
+                    "Step {} ({}): what should {} be?",
+                    index + 1,
+                    step.capability_id,
+                    input
+                )
+            })
+        })
+        .collect()
+}
+
+/// Run the steps of a plan in order.
+///
+/// Stops at the first failing step; the steps after it are skipped and the
+/// ones before it compensated, last first. Steps report progress to
+/// `progress`; once it is cancelled, the next step fails without running.
+pub fn execute(
+    registry: &CapabilityRegistry,
+    plan: &Plan,
+    request: &Request,
+    progress: &ProgressSink,
+) -> Vec<StepResult> {
+    let mut results: Vec<StepResult> = Vec::new();
+    let mut requests = Vec::new();
+    let mut failed = false;
+
+    for (index, step) in plan.steps.iter().enumerate() {
+        let mut result = StepResult {
+            step: index,
+            capability_id: step.capability_id.clone(),
+            status: StepStatus::Skipped,
+            result: None,
+            error: None,
+            compensation: None,
+        };
+        if !failed {
+            let step_request = step_request(request, step, &results);
+            let outcome = progress.check_cancelled().and_then(|()| {
+                registry.execute_with_progress(&step.capability_id, &step_request, progress)
+            });
+            match outcome {
+                Ok(value) => {
+                    result.status = StepStatus::Completed;
+                    result.result = Some(value);
+                }
+                Err(e) => {
+                    result.status = StepStatus::Failed;
+                    result.error = Some(e.to_string());
+                    failed = true;
+                }
+            }
+            requests.push(step_request);
+        }
+        results.push(result);
+    }
+
+    if failed {
+        compensate(registry, &mut results, &requests);
+    }
+    results
+}
+
+/// Undo the completed steps, last first.
+///
+/// A step whose capability has no compensating handler stays completed.
+fn compensate(registry: &CapabilityRegistry, results: &mut [StepResult], requests: &[Request]) {
+    for (result, request) in results.iter_mut().zip(requests).rev() {
+        let Some(ref value) = result.result else {
+            continue;
+        };
+        match registry.compensate(&result.capability_id, request, value) {
+            Ok(Some(compensation)) => {
+                result.status = StepStatus::Compensated;
+                result.compensation = Some(compensation);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                result.status = StepStatus::CompensationFailed;
+                result.error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// Request for one step: the step's clause, with its parameters and the
+/// results of earlier steps it is bound to.
+fn step_request(request: &Request, step: &PlanStep, results: &[StepResult]) -> Request {
+    let mut parameters: Map<String, Value> = step.parameters.clone();
+    for (input, &source) in &step.bindings {
+        let Some(value) = results.get(source).and_then(|r| r.result.as_ref()) else {
+            continue;
+        };
+        // An object result carrying the input itself binds just that field
+        let value = value.get(input).unwrap_or(value);
+        parameters.insert(input.clone(), value.clone());
+    }
+
+    let mut sub = request.clone();
+    sub.intent = step.intent.clone();
+    sub.parameters = parameters;
+    sub
+}
+
+fn refers_back(clause: &str) -> bool {
+    clause
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| BACK_REFERENCES.contains(&word))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::progress::CancellationToken;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+    use sinp_core::{Capability, SinpError};
+    use std::sync::{Arc, Mutex};
+
+    pub(crate) fn compose_registry() -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "weather:v1".to_string(),
+                description: "Get weather forecast".to_string(),
+                inputs: vec!["city".to_string()],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |req| {
+                let city = req.parameters.get("city").cloned().unwrap_or_default();
+                Ok(serde_json::json!({ "city": city, "forecast": "sunny" }))
+            },
+            0.95,
+        );
+        registry.register(
+            Capability {
+                id: "email:v1".to_string(),
+                description: "Email message".to_string(),
+                inputs: vec!["body".to_string(), "to".to_string()],
+                privacy_level: "public".to_string(),
+                cost_units: 0.2,
+            },
+            |req| match req.parameters.get("to").and_then(Value::as_str) {
+                Some("nobody") => Err(SinpError::Execution("No such mailbox".to_string())),
+                _ => Ok(serde_json::json!({ "sent": req.parameters })),
+            },
+            0.95,
+        );
+        registry.require_inputs("email:v1", ["body", "to"]).unwrap();
+        registry
+    }
+
+    pub(crate) fn compose_request(intent: &str) -> Request {
+        Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    fn thresholds() -> Thresholds {
+        Thresholds::new(0.6, 0.3, 0.5)
+    }
+
+    #[test]
+    fn compose_binds_previous_result() {
+        let registry = compose_registry();
+        let request = compose_request("get weather city=Paris and email it to=bob");
+        let plan = compose(&registry, &request, &thresholds()).unwrap();
+
+        let ids: Vec<&str> = plan
+            .steps
+            .iter()
+            .map(|s| s.capability_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["weather:v1", "email:v1"]);
+        assert_eq!(plan.steps[0].parameters["city"], "Paris");
+        assert_eq!(plan.steps[1].bindings["body"], 0);
+        assert!(plan.steps[1].missing.is_empty());
+        assert!((plan.estimated_cost - 0.3).abs() < 1e-9);
+
+        let results = execute(&registry, &plan, &request, &ProgressSink::disabled());
+        assert_eq!(results[1].status, StepStatus::Completed);
+        assert_eq!(
+            results[1].result.as_ref().unwrap()["sent"]["body"]["forecast"],
+            "sunny"
+        );
+    }
+
+    #[test]
+    fn compose_needs_every_clause_to_match() {
+        let registry = compose_registry();
+        let simple = compose_request("get weather city=Paris");
+        assert!(compose(&registry, &simple, &thresholds()).is_none());
+        let unmatched = compose_request("get weather and juggle");
+        assert!(compose(&registry, &unmatched, &thresholds()).is_none());
+    }
+
+    #[test]
+    fn steps_use_their_capability_thresholds() {
+        let mut registry = compose_registry();
+        let request = compose_request("get weather city=Paris and email it to=bob");
+        assert!(compose(&registry, &request, &thresholds()).is_some());
+
+        // Stricter than any match, for the second step only
+        registry
+            .set_thresholds("email:v1", Thresholds::new(0.99, 0.99, 0.99))
+            .unwrap();
+        assert!(compose(&registry, &request, &thresholds()).is_none());
+        let weather = compose_request("get weather city=Paris and get weather city=Rome");
+        assert!(compose(&registry, &weather, &thresholds()).is_some());
+    }
+
+    #[test]
+    fn missing_inputs_are_answered() {
+        let registry = compose_registry();
+        let request = compose_request("get weather city=Paris and email message");
+        let mut plan = compose(&registry, &request, &thresholds()).unwrap();
+        assert_eq!(plan.steps[1].missing, vec!["body", "to"]);
+        assert_eq!(
+            questions(&plan),
+            vec![
+                "Step 2 (email:v1): what should body be?",
+                "Step 2 (email:v1): what should to be?"
+            ]
+        );
+
+        assert!(!answer(&mut plan, &compose_request("never mind")));
+        let mut reply = compose_request("to=bob");
+        reply.parameters.insert("body".to_string(), "hi".into());
+        assert!(answer(&mut plan, &reply));
+        assert!(plan.steps[1].missing.is_empty());
+        assert_eq!(plan.steps[1].parameters["body"], "hi");
+    }
+
+    #[test]
+    fn failure_skips_remaining_steps() {
+        let registry = compose_registry();
+        let request = compose_request("email message to=nobody body=x then get weather city=Rome");
+        let plan = compose(&registry, &request, &thresholds()).unwrap();
+        let results = execute(&registry, &plan, &request, &ProgressSink::disabled());
+        assert_eq!(results[0].status, StepStatus::Failed);
+        assert!(results[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("No such mailbox"));
+        assert_eq!(results[1].status, StepStatus::Skipped);
+        assert!(results[1].result.is_none());
+    }
+
+    #[test]
+    fn failure_compensates_in_reverse() {
+        let mut registry = compose_registry();
+        let undone = Arc::new(Mutex::new(Vec::new()));
+        for id in ["weather:v1", "email:v1"] {
+            let undone = undone.clone();
+            registry
+                .register_compensation(id, move |req, result| {
+                    undone.lock().unwrap().push(req.intent.clone());
+                    match result["sent"]["to"].as_str() {
+                        Some("alice") => Err(SinpError::Execution("Already read".to_string())),
+                        _ => Ok(serde_json::json!({ "undone": req.intent })),
+                    }
+                })
+                .unwrap();
+        }
+
+        let request = compose_request(
+            "get weather city=Rome; email it to=alice; email it to=bob; email it to=nobody",
+        );
+        let plan = compose(&registry, &request, &thresholds()).unwrap();
+        let results = execute(&registry, &plan, &request, &ProgressSink::disabled());
+
+        assert_eq!(
+            *undone.lock().unwrap(),
+            vec![
+                "email it to=bob",
+                "email it to=alice",
+                "get weather city=Rome"
+            ]
+        );
+        let statuses: Vec<StepStatus> = results.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                StepStatus::Compensated,
+                StepStatus::CompensationFailed,
+                StepStatus::Compensated,
+                StepStatus::Failed
+            ]
+        );
+        assert_eq!(
+            results[0].compensation.as_ref().unwrap()["undone"],
+            "get weather city=Rome"
+        );
+        assert!(results[1]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("Already read"));
+        // The original result stays in the trail
+        assert_eq!(results[2].result.as_ref().unwrap()["sent"]["to"], "bob");
+    }
+
+    #[test]
+    fn cancellation_stops_between_steps() {
+        let mut registry = compose_registry();
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let weather = registry.capability("weather:v1").unwrap().clone();
+        registry.register(
+            weather,
+            move |_req| {
+                // The client cancels while the first step runs
+                cancel.cancel();
+                Ok(serde_json::json!({ "city": "Rome", "forecast": "sunny" }))
+            },
+            0.9,
+        );
+
+        let request = compose_request("get weather city=Rome and email it to=bob");
+        let plan = compose(&registry, &request, &thresholds()).unwrap();
+        let progress = ProgressSink::disabled().with_cancellation(token);
+        let results = execute(&registry, &plan, &request, &progress);
+
+        assert_eq!(results[0].status, StepStatus::Completed);
+        assert_eq!(results[1].status, StepStatus::Failed);
+        assert!(results[1].error.as_deref().unwrap().contains("cancelled"));
+    }
+}
@@ -0,0 +1,447 @@
+//! Capabilities provided by WebAssembly plugins.
+//!
+//! A plugin is a WASM module with no imports that exports:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`: space for the host to write the input into
+//! - `describe() -> i64`: location of its JSON capability description
+//! - `execute(ptr: i32, len: i32) -> i64`: run on the JSON request at
+//!   `ptr`, returning the location of a JSON outcome
+//!
+//! Locations are packed as `ptr << 32 | len`. The description is a
+//! [`Capability`] with an optional `reliability`; the request is the same
+//! JSON subprocess handlers get on stdin, and the outcome is
+//! `{"result": ...}` or `{"error": "..."}`.
+//!
+//! Every call runs in a fresh instance with bounded memory and fuel, so a
+//! plugin can neither keep state between requests nor run forever.
+
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+
+use sinp_core::{Capability, Request, SinpError, SinpResult};
+use wasmi::core::TrapCode;
+use wasmi::{Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::capability::{handler_input, CapabilityHandler, CapabilityRegistry};
+
+/// Reliability of plugins that do not declare one.
+pub const DEFAULT_RELIABILITY: f64 = 0.9;
+
+/// Resources a plugin call may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginLimits {
+    /// Largest linear memory, in bytes.
+    pub max_memory: usize,
+    /// Fuel per call; roughly one unit per instruction.
+    pub fuel: u64,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            max_memory: 16 * 1024 * 1024,
+            fuel: 10_000_000,
+        }
+    }
+}
+
+/// Loads plugins into a shared WASM engine.
+#[derive(Clone)]
+pub struct PluginHost {
+    engine: Engine,
+    limits: PluginLimits,
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PluginHost {
+    /// Create a host with default limits.
+    pub fn new() -> Self {
+        Self::with_limits(PluginLimits::default())
+    }
+
+    /// Create a host with custom limits.
+    pub fn with_limits(limits: PluginLimits) -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Self {
+            engine: Engine::new(&config),
+            limits,
+        }
+    }
+
+    /// Load a plugin from WASM bytes and read its description.
+    pub fn load(&self, wasm: &[u8]) -> SinpResult<Plugin> {
+        let module = Module::new(&self.engine, wasm)
+            .map_err(|e| SinpError::Validation(format!("Invalid plugin module: {}", e)))?;
+        if let Some(import) = module.imports().next() {
+            return Err(SinpError::Validation(format!(
+                "Plugins may not import host functions: {}::{}",
+                import.module(),
+                import.name()
+            )));
+        }
+
+        let mut sandbox = Sandbox::new(&self.engine, &module, self.limits)?;
+        let location = sandbox
+            .call::<(), i64>("describe", ())
+            .map_err(|e| SinpError::Validation(format!("Plugin describe failed: {}", e)))?;
+        let description: Description = serde_json::from_slice(&sandbox.read(location)?)
+            .map_err(|e| SinpError::Validation(format!("Invalid plugin description: {}", e)))?;
+
+        Ok(Plugin {
+            engine: self.engine.clone(),
+            module: Arc::new(module),
+            limits: self.limits,
+            capability: description.capability,
+            reliability: description.reliability.clamp(0.0, 1.0),
+        })
+    }
+
+    /// Load a plugin from a `.wasm` file.
+    pub fn load_file(&self, path: impl AsRef<Path>) -> SinpResult<Plugin> {
+        let path = path.as_ref();
+        let wasm = std::fs::read(path).map_err(|e| {
+            SinpError::Validation(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        self.load(&wasm)
+            .map_err(|e| SinpError::Validation(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Load every `.wasm` file in a directory, in name order.
+    pub fn load_dir(&self, dir: impl AsRef<Path>) -> SinpResult<Vec<Plugin>> {
+        let dir = dir.as_ref();
+        let entries = std::fs::read_dir(dir).map_err(|e| {
+            SinpError::Validation(format!("Failed to read {}: {}", dir.display(), e))
+        })?;
+        let mut files: Vec<_> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "wasm"))
+            .collect();
+        files.sort();
+        files.iter().map(|file| self.load_file(file)).collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct Description {
+    #[serde(flatten)]
+    capability: Capability,
+    #[serde(default = "default_reliability")]
+    reliability: f64,
+}
+
+fn default_reliability() -> f64 {
+    DEFAULT_RELIABILITY
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Result(serde_json::Value),
+    Error(String),
+}
+
+/// Loaded plugin.
+#[derive(Clone)]
+pub struct Plugin {
+    engine: Engine,
+    module: Arc<Module>,
+    limits: PluginLimits,
+    capability: Capability,
+    reliability: f64,
+}
+
+impl std::fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugin")
+            .field("capability", &self.capability)
+            .field("reliability", &self.reliability)
+            .field("limits", &self.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Plugin {
+    /// Capability the plugin provides.
+    pub fn capability(&self) -> &Capability {
+        &self.capability
+    }
+
+    /// Reliability the plugin declares.
+    pub fn reliability(&self) -> f64 {
+        self.reliability
+    }
+
+    /// Run the plugin on a handler input.
+    pub fn execute(&self, input: &serde_json::Value) -> SinpResult<serde_json::Value> {
+        let id = &self.capability.id;
+        let failed = |e: String| SinpError::Execution(format!("Plugin {} {}", id, e));
+
+        let input = serde_json::to_vec(input)?;
+        let len = i32::try_from(input.len()).map_err(|_| failed("input too large".into()))?;
+        let mut sandbox = Sandbox::new(&self.engine, &self.module, self.limits)?;
+        let ptr = sandbox.call::<i32, i32>("alloc", len).map_err(failed)?;
+        sandbox
+            .write(ptr, &input)
+            .map_err(|e| failed(e.to_string()))?;
+        let location = sandbox
+            .call::<(i32, i32), i64>("execute", (ptr, len))
+            .map_err(failed)?;
+
+        let outcome: Outcome = serde_json::from_slice(&sandbox.read(location)?)
+            .map_err(|e| failed(format!("returned an invalid outcome: {}", e)))?;
+        match outcome {
+            Outcome::Result(result) => Ok(result),
+            Outcome::Error(reason) => Err(failed(format!("failed: {}", reason))),
+        }
+    }
+
+    /// Handler running the plugin.
+    pub fn handler(&self) -> CapabilityHandler {
+        let plugin = self.clone();
+        Arc::new(move |request: &Request| {
+            plugin.execute(&handler_input(&plugin.capability, request))
+        })
+    }
+
+    /// Register the plugin like a native capability.
+    ///
+    /// A plugin may not replace a capability that is already registered.
+    pub fn register(&self, registry: &mut CapabilityRegistry) -> SinpResult<()> {
+        if registry.capability(&self.capability.id).is_some() {
+            return Err(SinpError::Validation(format!(
+                "Plugin capability {} is already registered",
+                self.capability.id
+            )));
+        }
+        registry.register_handler(self.capability.clone(), self.handler(), self.reliability);
+        Ok(())
+    }
+}
+
+/// Fresh, limited instance of a plugin module.
+struct Sandbox {
+    store: Store<StoreLimits>,
+    instance: wasmi::Instance,
+    memory: Memory,
+}
+
+impl Sandbox {
+    fn new(engine: &Engine, module: &Module, limits: PluginLimits) -> SinpResult<Self> {
+        let failed =
+            |e: wasmi::Error| SinpError::Execution(format!("Failed to instantiate plugin: {}", e));
+        let mut store = Store::new(
+            engine,
+            StoreLimitsBuilder::new()
+                .memory_size(limits.max_memory)
+                .instances(1)
+                .build(),
+        );
+        store.limiter(|limits| limits);
+        store
+            .set_fuel(limits.fuel)
+            .map_err(|e| SinpError::Execution(format!("Failed to set plugin fuel: {}", e)))?;
+
+        let instance = Linker::new(engine)
+            .instantiate(&mut store, module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(failed)?;
+        let memory = instance.get_memory(&store, "memory").ok_or_else(|| {
+            SinpError::Validation("Plugin does not export its memory".to_string())
+        })?;
+        Ok(Self {
+            store,
+            instance,
+            memory,
+        })
+    }
+
+    /// Call an export, describing traps in plain words.
+    fn call<P, R>(&mut self, name: &str, params: P) -> Result<R, String>
+    where
+        P: wasmi::WasmParams,
+        R: wasmi::WasmResults,
+    {
+        let func = self
+            .instance
+            .get_typed_func::<P, R>(&self.store, name)
+            .map_err(|e| format!("has no valid {} export: {}", name, e))?;
+        func.call(&mut self.store, params)
+            .map_err(|e| match e.as_trap_code() {
+                Some(TrapCode::OutOfFuel) => "exceeded its fuel limit".to_string(),
+                _ => format!("trapped in {}: {}", name, e),
+            })
+    }
+
+    fn write(&mut self, ptr: i32, bytes: &[u8]) -> Result<(), wasmi::errors::MemoryError> {
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, bytes)
+    }
+
+    /// Bytes at a packed `ptr << 32 | len` location.
+    fn read(&self, location: i64) -> SinpResult<Vec<u8>> {
+        let location = location as u64;
+        let ptr = (location >> 32) as usize;
+        let len = (location & 0xffff_ffff) as usize;
+        self.memory
+            .data(&self.store)
+            .get(ptr..ptr.saturating_add(len))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| {
+                SinpError::Execution(format!("Plugin output {}+{} is out of bounds", ptr, len))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+
+    const DESCRIPTION: &str = r#"{"id":"shout:v1","description":"Shout a message back","inputs":["message"],"privacy_level":"public","cost_units":0.1,"reliability":0.8}"#;
+
+    /// Plugin wrapping its input as `{"result": <input>}`, with `execute`
+    /// replaced by `body` if given.
+    fn plugin_wat(body: Option<&str>, memory_pages: u32) -> Vec<u8> {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let execute = body.map(String::from).unwrap_or_else(|| {
+            "(local $out i32)
+             (local.set $out (global.get $heap))
+             (memory.copy (local.get $out) (i32.const 512) (i32.const 10))
+             (memory.copy (i32.add (local.get $out) (i32.const 10)) (local.get $ptr) (local.get $len))
+             (i32.store8 (i32.add (local.get $out) (i32.add (local.get $len) (i32.const 10))) (i32.const 125))
+             (i64.or
+               (i64.shl (i64.extend_i32_u (local.get $out)) (i64.const 32))
+               (i64.extend_i32_u (i32.add (local.get $len) (i32.const 11))))"
+                .to_string()
+        });
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") {pages})
+                (global $heap (mut i32) (i32.const 4096))
+                (data (i32.const 0) "{description}")
+                (data (i32.const 512) "{{\"result\":")
+                (data (i32.const 768) "{{\"error\":\"no such flight\"}}")
+                (func (export "alloc") (param $len i32) (result i32)
+                  (local $ptr i32)
+                  (local.set $ptr (global.get $heap))
+                  (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+                  (local.get $ptr))
+                (func (export "describe") (result i64)
+                  (i64.const {description_len}))
+                (func (export "execute") (param $ptr i32) (param $len i32) (result i64)
+                  {execute}))"#,
+            pages = memory_pages,
+            description = escape(DESCRIPTION),
+            description_len = DESCRIPTION.len(),
+            execute = execute,
+        );
+        wat::parse_str(wat).unwrap()
+    }
+
+    fn request(intent: &str) -> Request {
+        Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn register_and_execute() {
+        let plugin = PluginHost::new().load(&plugin_wat(None, 1)).unwrap();
+        assert_eq!(plugin.capability().id, "shout:v1");
+        assert_eq!(plugin.capability().inputs, vec!["message"]);
+        assert_eq!(plugin.reliability(), 0.8);
+
+        let mut registry = CapabilityRegistry::new();
+        plugin.register(&mut registry).unwrap();
+        assert_eq!(registry.get_reliability("shout:v1"), 0.8);
+
+        let result = registry
+            .execute("shout:v1", &request("shout message=hello"))
+            .unwrap();
+        assert_eq!(result["capability_id"], "shout:v1");
+        assert_eq!(result["parameters"]["message"], "hello");
+        assert_eq!(result["sender"], "client_1");
+    }
+
+    #[test]
+    fn shadowing_plugin_is_refused() {
+        let plugin = PluginHost::new().load(&plugin_wat(None, 1)).unwrap();
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            plugin.capability().clone(),
+            |_| Ok(serde_json::json!({ "native": true })),
+            0.95,
+        );
+        registry.restrict("shout:v1", ["admin"]).unwrap();
+
+        let err = plugin.register(&mut registry).unwrap_err();
+        assert!(matches!(err, SinpError::Validation(_)), "{}", err);
+        // The native capability and its policy are untouched
+        assert_eq!(registry.get_reliability("shout:v1"), 0.95);
+        assert!(!registry.is_permitted("shout:v1", &request("shout").sender));
+    }
+
+    #[test]
+    fn plugin_errors() {
+        let error = "(i64.const 0x000003000000001a)";
+        let plugin = PluginHost::new().load(&plugin_wat(Some(error), 1)).unwrap();
+        let err = plugin.execute(&serde_json::json!({})).unwrap_err();
+        assert!(matches!(err, SinpError::Execution(_)));
+        assert!(err.to_string().contains("no such flight"), "{}", err);
+
+        let out_of_bounds = "(i64.const 0x0001000000000010)";
+        let plugin = PluginHost::new()
+            .load(&plugin_wat(Some(out_of_bounds), 1))
+            .unwrap();
+        let err = plugin.execute(&serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("out of bounds"), "{}", err);
+
+        let imports = wat::parse_str(r#"(module (import "env" "clock" (func)))"#).unwrap();
+        let err = PluginHost::new().load(&imports).unwrap_err();
+        assert!(err.to_string().contains("env::clock"), "{}", err);
+    }
+
+    #[test]
+    fn limits_are_enforced() {
+        let spin = "(loop $forever (br $forever)) (unreachable)";
+        let host = PluginHost::with_limits(PluginLimits {
+            fuel: 10_000,
+            ..Default::default()
+        });
+        let plugin = host.load(&plugin_wat(Some(spin), 1)).unwrap();
+        let err = plugin.execute(&serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("fuel"), "{}", err);
+
+        let host = PluginHost::with_limits(PluginLimits {
+            max_memory: 64 * 1024,
+            ..Default::default()
+        });
+        assert!(host.load(&plugin_wat(None, 1)).is_ok());
+        let err = host.load(&plugin_wat(None, 4)).unwrap_err();
+        assert!(err.to_string().contains("instantiate"), "{}", err);
+
+        let grow = "(drop (memory.grow (i32.const 1))) (i64.const 0)";
+        let plugin = host.load(&plugin_wat(Some(grow), 1)).unwrap();
+        // Growing past the limit fails inside the plugin, leaving no output
+        let err = plugin.execute(&serde_json::json!({})).unwrap_err();
+        assert!(err.to_string().contains("invalid outcome"), "{}", err);
+    }
+}
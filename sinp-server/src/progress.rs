@@ -0,0 +1,211 @@
+//! Progress reporting and cancellation of running capabilities.
+//!
+//! Handlers registered with [`CapabilityRegistry::register_streaming`] get a
+//! [`ProgressSink`]. What they report is sent to the client as progress
+//! frames ahead of the final response, if the session agreed on streaming.
+//! Through it they also learn when the client cancels the request.
+//!
+//! [`CapabilityRegistry::register_streaming`]: crate::CapabilityRegistry::register_streaming
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use sinp_core::{Progress, RefusalCode, SinpError, SinpResult};
+use tokio::sync::mpsc;
+
+/// Flag telling a running request to stop.
+///
+/// Cancellation is cooperative: handlers check the flag at convenient
+/// points and return early.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the request to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Whether the request was asked to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+/// Where a running handler reports progress and checks for cancellation.
+///
+/// Reports are dropped when the client did not agree on streaming. The
+/// buffer towards the client is bounded: once it is full, reporting blocks
+/// until the client catches up, so a slow client slows the handler down
+/// instead of updates piling up in memory.
+#[derive(Clone, Default)]
+pub struct ProgressSink {
+    target: Option<Arc<Target>>,
+    cancellation: CancellationToken,
+}
+
+struct Target {
+    in_response_to: uuid::Uuid,
+    sequence: AtomicU64,
+    sender: mpsc::Sender<Progress>,
+}
+
+impl ProgressSink {
+    /// Sink that drops all reports.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Sink for updates on a request, buffering at most `capacity` of them.
+    pub fn channel(
+        in_response_to: uuid::Uuid,
+        capacity: usize,
+    ) -> (Self, mpsc::Receiver<Progress>) {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let target = Target {
+            in_response_to,
+            sequence: AtomicU64::new(0),
+            sender,
+        };
+        (
+            Self {
+                target: Some(Arc::new(target)),
+                cancellation: CancellationToken::new(),
+            },
+            receiver,
+        )
+    }
+
+    /// Let the handler observe `token`.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Whether the client cancelled the request.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Fail with a `cancelled` refusal if the client cancelled the request.
+    ///
+    /// Handlers call this between units of work and return the error.
+    pub fn check_cancelled(&self) -> SinpResult<()> {
+        if self.is_cancelled() {
+            return Err(cancelled());
+        }
+        Ok(())
+    }
+
+    /// Whether reports reach a client.
+    pub fn is_enabled(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// Report the share of the work done, from 0 to 1.
+    pub fn report(&self, fraction: f64, message: impl Into<String>) {
+        self.send(Some(fraction.clamp(0.0, 1.0)), Some(message.into()), None);
+    }
+
+    /// Report part of the result.
+    pub fn partial(&self, value: serde_json::Value) {
+        self.send(None, None, Some(value));
+    }
+
+    /// Must not be called from async code: it blocks while the buffer is full.
+    fn send(
+        &self,
+        fraction: Option<f64>,
+        message: Option<String>,
+        partial: Option<serde_json::Value>,
+    ) {
+        let Some(ref target) = self.target else {
+            return;
+        };
+        let progress = Progress {
+            in_response_to: target.in_response_to,
+            sequence: target.sequence.fetch_add(1, Ordering::Relaxed),
+            fraction,
+            message,
+            partial,
+        };
+        // The client went away; the final response will fail to send too
+        if target.sender.blocking_send(progress).is_err() {
+            tracing::debug!("Dropping progress for {}", target.in_response_to);
+        }
+    }
+}
+
+/// Refusal for a request the client cancelled.
+pub fn cancelled() -> SinpError {
+    SinpError::Refused {
+        code: RefusalCode::Cancelled,
+        reason: "Request cancelled by client".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_are_numbered() {
+        let id = uuid::Uuid::new_v4();
+        let (sink, mut receiver) = ProgressSink::channel(id, 4);
+        sink.report(0.5, "halfway");
+        sink.clone().partial(serde_json::json!({ "rows": 10 }));
+        drop(sink);
+
+        let first = receiver.blocking_recv().unwrap();
+        assert_eq!(first.in_response_to, id);
+        assert_eq!((first.sequence, first.fraction), (0, Some(0.5)));
+        assert_eq!(first.message.as_deref(), Some("halfway"));
+        let second = receiver.blocking_recv().unwrap();
+        assert_eq!(second.sequence, 1);
+        assert_eq!(second.partial, Some(serde_json::json!({ "rows": 10 })));
+        assert!(receiver.blocking_recv().is_none());
+
+        // Disabled sinks drop everything
+        ProgressSink::disabled().report(1.0, "done");
+    }
+
+    #[test]
+    fn full_buffer_blocks_the_handler() {
+        let (sink, mut receiver) = ProgressSink::channel(uuid::Uuid::new_v4(), 1);
+        let handler = std::thread::spawn(move || {
+            for i in 0..3 {
+                sink.report(i as f64 / 3.0, "working");
+            }
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        // Only the buffered update was sent; the handler waits for the client
+        assert!(!handler.is_finished());
+
+        let sequences: Vec<u64> = std::iter::from_fn(|| receiver.blocking_recv())
+            .map(|p| p.sequence)
+            .collect();
+        handler.join().unwrap();
+        assert_eq!(sequences, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn cancellation_is_shared() {
+        let token = CancellationToken::new();
+        let sink = ProgressSink::disabled().with_cancellation(token.clone());
+        assert!(sink.check_cancelled().is_ok());
+
+        token.cancel();
+        assert!(sink.clone().is_cancelled());
+        match sink.check_cancelled() {
+            Err(SinpError::Refused { code, .. }) => assert_eq!(code, RefusalCode::Cancelled),
+            other => panic!("expected a cancellation, got {:?}", other),
+        }
+    }
+}
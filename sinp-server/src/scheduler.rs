@@ -0,0 +1,462 @@
+//! Scheduled executions.
+//!
+//! A request carrying a [`Schedule`] is answered with a
+//! [`ScheduledExecution`] as soon as it is negotiated to EXECUTE. The
+//! [`Scheduler`] keeps it and starts a background job each time it falls
+//! due, until the sender cancels it or a one-off has run. Runs missed while
+//! the server was down happen once when it starts again.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use sinp_core::{Request, Schedule, ScheduleQuery, ScheduledExecution, SinpError, SinpResult};
+
+use crate::capability::SharedRegistry;
+use crate::jobs::{self, DeferredExecution, JobStore};
+
+/// Longest the scheduler sleeps, so a jump in the clock is noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Next time `schedule` runs after `after`.
+pub fn next_run(schedule: &Schedule, after: DateTime<Utc>) -> SinpResult<DateTime<Utc>> {
+    match schedule {
+        Schedule::At { at } if *at > after => Ok(*at),
+        Schedule::At { at } => Err(SinpError::Validation(format!(
+            "Scheduled time {} has passed",
+            at
+        ))),
+        Schedule::Cron { expression } => cron_schedule(expression)?
+            .after(&after)
+            .next()
+            .ok_or_else(|| SinpError::Validation(format!("Schedule '{}' never runs", expression))),
+    }
+}
+
+/// Parse a cron expression, taking five fields to run on the minute.
+fn cron_schedule(expression: &str) -> SinpResult<cron::Schedule> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {}", expression),
+        _ => expression.to_string(),
+    };
+    cron::Schedule::from_str(&expression)
+        .map_err(|e| SinpError::Validation(format!("Invalid schedule '{}': {}", expression, e)))
+}
+
+/// Scheduled execution with the request it runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    scheduled: ScheduledExecution,
+    request: Request,
+}
+
+/// Shared store of scheduled executions.
+///
+/// Cloning yields another handle to the same store. Executions run once
+/// [`Scheduler::run`] is driving it; with a backing file they also survive
+/// a restart.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    entries: Mutex<HashMap<Uuid, Entry>>,
+    path: Option<PathBuf>,
+    changed: Notify,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    /// Create an empty in-memory scheduler.
+    pub fn new() -> Self {
+        Self::with_entries(HashMap::new(), None)
+    }
+
+    /// Open a scheduler persisted to `path`, creating it if it does not exist.
+    pub fn open(path: impl Into<PathBuf>) -> SinpResult<Self> {
+        let path = path.into();
+        let entries = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice::<Vec<Entry>>(&contents).map_err(|e| {
+                SinpError::Validation(format!("Invalid schedule store {}: {}", path.display(), e))
+            })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(SinpError::Execution(format!(
+                    "Failed to read schedule store {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        let entries = entries
+            .into_iter()
+            .map(|entry| (entry.scheduled.id, entry))
+            .collect();
+        Ok(Self::with_entries(entries, Some(path)))
+    }
+
+    fn with_entries(entries: HashMap<Uuid, Entry>, path: Option<PathBuf>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                entries: Mutex::new(entries),
+                path,
+                changed: Notify::new(),
+            }),
+        }
+    }
+
+    /// Store a deferred execution to run on its request's schedule.
+    ///
+    /// The request is stored without its credential and signature; later
+    /// runs only re-check the sender's permission.
+    pub fn schedule(&self, deferred: DeferredExecution) -> SinpResult<ScheduledExecution> {
+        let DeferredExecution {
+            mut request,
+            capability,
+        } = deferred;
+        request.credential = None;
+        request.signature = None;
+        let schedule = request
+            .schedule
+            .clone()
+            .ok_or_else(|| SinpError::Validation("Request carries no schedule".to_string()))?;
+        let now = Utc::now();
+        let scheduled = ScheduledExecution {
+            id: Uuid::new_v4(),
+            conversation_id: request.conversation_id,
+            sender_id: request.sender.id.clone(),
+            capability_id: capability.id,
+            intent: request.intent.clone(),
+            next_run: next_run(&schedule, now)?,
+            schedule,
+            created_at: now,
+            last_run: None,
+            last_job: None,
+            runs: 0,
+        };
+        tracing::info!(
+            "Scheduled {}: {} for {} at {}",
+            scheduled.id,
+            scheduled.capability_id,
+            scheduled.sender_id,
+            scheduled.next_run
+        );
+        {
+            let mut entries = self.lock();
+            let entry = Entry {
+                scheduled: scheduled.clone(),
+                request,
+            };
+            entries.insert(scheduled.id, entry);
+            self.save(&entries);
+        }
+        self.inner.changed.notify_one();
+        Ok(scheduled)
+    }
+
+    /// Answer a query for the sender's scheduled executions.
+    ///
+    /// Executions of other senders are reported as missing.
+    pub fn query(&self, query: &ScheduleQuery) -> SinpResult<Vec<ScheduledExecution>> {
+        let sender_id = query.sender.id.as_str();
+        match query.cancel {
+            Some(id) => Ok(vec![self.cancel(sender_id, id)?]),
+            None => Ok(self.list(sender_id)),
+        }
+    }
+
+    /// List the sender's scheduled executions, soonest first.
+    pub fn list(&self, sender_id: &str) -> Vec<ScheduledExecution> {
+        let mut scheduled: Vec<_> = self
+            .lock()
+            .values()
+            .filter(|entry| entry.scheduled.sender_id == sender_id)
+            .map(|entry| entry.scheduled.clone())
+            .collect();
+        scheduled.sort_by_key(|scheduled| scheduled.next_run);
+        scheduled
+    }
+
+    /// Cancel one of the sender's scheduled executions and return it.
+    pub fn cancel(&self, sender_id: &str, id: Uuid) -> SinpResult<ScheduledExecution> {
+        let mut entries = self.lock();
+        if entries
+            .get(&id)
+            .is_none_or(|entry| entry.scheduled.sender_id != sender_id)
+        {
+            return Err(SinpError::Validation(format!(
+                "No scheduled execution {}",
+                id
+            )));
+        }
+        let entry = entries.remove(&id).expect("checked above");
+        self.save(&entries);
+        tracing::info!("Cancelled scheduled execution {}", id);
+        Ok(entry.scheduled)
+    }
+
+    /// Start executions as they fall due, as background jobs in `jobs`.
+    ///
+    /// Runs until the task is dropped.
+    pub async fn run(self, registry: SharedRegistry, jobs: JobStore) {
+        loop {
+            let wait = match self.fire_due(Utc::now(), &registry, &jobs) {
+                Some(next) => (next - Utc::now())
+                    .to_std()
+                    .unwrap_or_default()
+                    .min(MAX_SLEEP),
+                None => MAX_SLEEP,
+            };
+            tokio::select! {
+                () = tokio::time::sleep(wait) => {}
+                () = self.inner.changed.notified() => {}
+            }
+        }
+    }
+
+    /// Start the executions due at `now` and return when the next one is.
+    fn fire_due(
+        &self,
+        now: DateTime<Utc>,
+        registry: &SharedRegistry,
+        jobs: &JobStore,
+    ) -> Option<DateTime<Utc>> {
+        let registry = registry.current();
+        let mut entries = self.lock();
+        let due: Vec<Uuid> = entries
+            .values()
+            .filter(|entry| entry.scheduled.next_run <= now)
+            .map(|entry| entry.scheduled.id)
+            .collect();
+
+        for id in &due {
+            let Some(entry) = entries.get_mut(id) else {
+                continue;
+            };
+            let capability = registry
+                .capability(&entry.scheduled.capability_id)
+                .filter(|_| {
+                    registry.is_permitted(&entry.scheduled.capability_id, &entry.request.sender)
+                })
+                .cloned();
+            match capability {
+                Some(capability) => {
+                    let mut request = entry.request.clone();
+                    request.timestamp = now;
+                    let deferred = DeferredExecution {
+                        request,
+                        capability,
+                    };
+                    let job = jobs.spawn(deferred, Arc::clone(&registry));
+                    entry.scheduled.last_job = Some(job.id);
+                    entry.scheduled.runs += 1;
+                }
+                None => tracing::warn!(
+                    "Skipped scheduled execution {}: {} is no longer available to {}",
+                    id,
+                    entry.scheduled.capability_id,
+                    entry.scheduled.sender_id
+                ),
+            }
+            entry.scheduled.last_run = Some(now);
+
+            // One-off executions, and schedules that ran out, are done
+            match next_run(&entry.scheduled.schedule, now) {
+                Ok(next) if matches!(entry.scheduled.schedule, Schedule::Cron { .. }) => {
+                    entry.scheduled.next_run = next;
+                }
+                _ => {
+                    entries.remove(id);
+                }
+            }
+        }
+        if !due.is_empty() {
+            self.save(&entries);
+        }
+        entries.values().map(|entry| entry.scheduled.next_run).min()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Entry>> {
+        self.inner.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write the entries to the backing file, if any.
+    ///
+    /// Failures are logged: the entries are still served from memory.
+    fn save(&self, entries: &HashMap<Uuid, Entry>) {
+        let Some(ref path) = self.inner.path else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.values().collect();
+        entries.sort_by_key(|entry| entry.scheduled.created_at);
+        if let Err(e) = jobs::write_atomically(path, &entries) {
+            tracing::warn!("Failed to save schedule store {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capability::CapabilityRegistry;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+    use sinp_core::{Capability, JobQuery, JobStatus};
+
+    fn capability() -> Capability {
+        Capability {
+            id: "echo:v1".to_string(),
+            description: "Echo message".to_string(),
+            inputs: vec![],
+            privacy_level: "public".to_string(),
+            cost_units: 0.1,
+        }
+    }
+
+    fn registry() -> SharedRegistry {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            capability(),
+            |req| Ok(serde_json::json!({ "echo": req.intent })),
+            0.95,
+        );
+        SharedRegistry::new(registry)
+    }
+
+    fn sender(id: &str) -> Sender {
+        Sender {
+            id: id.to_string(),
+            auth_method: AuthMethod::None,
+        }
+    }
+
+    fn deferred(sender_id: &str, schedule: Schedule) -> DeferredExecution {
+        let mut request = Request::new(
+            sender(sender_id),
+            "echo report",
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        );
+        request.schedule = Some(schedule);
+        DeferredExecution {
+            request,
+            capability: capability(),
+        }
+    }
+
+    #[test]
+    fn schedules_are_validated() {
+        let now = Utc::now();
+        let hourly = Schedule::Cron {
+            expression: "0 * * * *".to_string(),
+        };
+        let next = next_run(&hourly, now).unwrap();
+        assert!(next > now && next - now <= chrono::Duration::hours(1));
+        assert_eq!(next.timestamp() % 3600, 0);
+
+        let past = Schedule::At {
+            at: now - chrono::Duration::minutes(1),
+        };
+        assert!(next_run(&past, now).is_err());
+        let invalid = Schedule::Cron {
+            expression: "every hour".to_string(),
+        };
+        assert!(next_run(&invalid, now).is_err());
+    }
+
+    #[tokio::test]
+    async fn due_executions_run_as_jobs() {
+        let (registry, jobs) = (registry(), JobStore::new(Duration::from_secs(60)));
+        let scheduler = Scheduler::new();
+        let at = Utc::now() + chrono::Duration::minutes(5);
+        let once = scheduler
+            .schedule(deferred("client_1", Schedule::At { at }))
+            .unwrap();
+        let hourly = Schedule::Cron {
+            expression: "0 * * * *".to_string(),
+        };
+        let repeated = scheduler
+            .schedule(deferred("client_1", hourly.clone()))
+            .unwrap();
+        assert_eq!(once.next_run, at);
+
+        // Nothing is due yet
+        let next = scheduler.fire_due(Utc::now(), &registry, &jobs);
+        assert_eq!(next, Some(repeated.next_run.min(at)));
+        assert!(jobs.list("client_1").is_empty());
+
+        // The one-off runs once; the recurring one moves on
+        let later = at.max(repeated.next_run);
+        scheduler.fire_due(later, &registry, &jobs);
+        let remaining = scheduler.list("client_1");
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, repeated.id);
+        assert_eq!(remaining[0].runs, 1);
+        assert_eq!(remaining[0].next_run, next_run(&hourly, later).unwrap());
+
+        let mut query = JobQuery::job(sender("client_1"), remaining[0].last_job.unwrap());
+        query.wait = true;
+        let job = jobs.query(&query).await.unwrap().remove(0);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(jobs.list("client_1").len(), 2);
+    }
+
+    #[test]
+    fn cancelled_per_sender() {
+        let scheduler = Scheduler::new();
+        let at = Utc::now() + chrono::Duration::hours(1);
+        let scheduled = scheduler
+            .schedule(deferred("client_1", Schedule::At { at }))
+            .unwrap();
+
+        // Other senders neither see nor cancel it
+        assert!(scheduler.list("client_2").is_empty());
+        assert!(scheduler.cancel("client_2", scheduled.id).is_err());
+
+        let query = ScheduleQuery::cancel(sender("client_1"), scheduled.id);
+        assert_eq!(scheduler.query(&query).unwrap(), vec![scheduled.clone()]);
+        assert!(scheduler.list("client_1").is_empty());
+        assert!(scheduler.cancel("client_1", scheduled.id).is_err());
+    }
+
+    #[test]
+    fn schedules_survive_restart() {
+        let dir = std::env::temp_dir().join(format!("sinp-schedules-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("schedules.json");
+
+        let scheduler = Scheduler::open(&path).unwrap();
+        let hourly = Schedule::Cron {
+            expression: "0 * * * *".to_string(),
+        };
+        let mut authenticated = deferred("client_1", hourly);
+        authenticated.request.credential = Some("secret-token".to_string());
+        authenticated.request.signature = Some("secret-signature".to_string());
+        let scheduled = scheduler.schedule(authenticated).unwrap();
+
+        // Credentials are never persisted
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains("secret-token"));
+        assert!(!stored.contains("secret-signature"));
+
+        let reopened = Scheduler::open(&path).unwrap();
+        assert_eq!(reopened.list("client_1"), vec![scheduled]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
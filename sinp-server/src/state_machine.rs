@@ -1,13 +1,18 @@
 //! Server state machine implementation.
 
 use sinp_core::{
-    check_replay, compute_server_confidence, decide_action,
-    Action, ActionMetadata, Interpretation, RefusalCode, Request, Responder, Response,
-    ServerEvent, ServerState, SinpError, SinpResult,
+    check_replay, compute_server_confidence, decision_rule,
+    Action, ActionMetadata, Capability, Confirmation, DecisionRule, Explanation, Interpretation,
+    Plan, Preview, RefusalCode, Request, Responder, Response, Sender, ServerEvent, ServerState,
+    SinpError, SinpResult, StepResult, StepStatus,
 };
 
+use crate::approval::{ApprovalDecision, PendingApproval};
 use crate::config::ServerConfig;
 use crate::capability::CapabilityRegistry;
+use crate::jobs::DeferredExecution;
+use crate::plan;
+use crate::progress::ProgressSink;
 
 /// Server state machine managing a single conversation.
 pub struct ServerStateMachine {
@@ -15,6 +20,41 @@ pub struct ServerStateMachine {
     config: ServerConfig,
     conversation_id: Option<uuid::Uuid>,
     last_message_id: Option<uuid::Uuid>,
+    pending_confirmation: Option<PendingConfirmation>,
+    pending_plan: Option<PendingPlan>,
+    awaiting_approval: Option<ParkedExecution>,
+    deferred: Option<DeferredExecution>,
+    progress: ProgressSink,
+}
+
+/// Interpretation awaiting a yes/no answer from the client.
+struct PendingConfirmation {
+    /// Response that asked for confirmation.
+    message_id: uuid::Uuid,
+    /// Request the interpretation was made for.
+    request: Request,
+    capability: Capability,
+    interpretation: String,
+    confidence: f64,
+    /// Explanation of the decision to ask, if explanations are enabled.
+    explanation: Option<Explanation>,
+}
+
+/// Plan for a compound intent under negotiation.
+struct PendingPlan {
+    /// Response that proposed the plan or asked for its missing inputs.
+    message_id: uuid::Uuid,
+    /// Request the plan was composed for.
+    request: Request,
+    plan: Plan,
+}
+
+/// Execution parked until an operator approves it.
+struct ParkedExecution {
+    request: Request,
+    capability: Capability,
+    /// Plan to run instead of the single capability.
+    plan: Option<Plan>,
 }
 
 impl ServerStateMachine {
@@ -25,6 +65,11 @@ impl ServerStateMachine {
             config,
             conversation_id: None,
             last_message_id: None,
+            pending_confirmation: None,
+            pending_plan: None,
+            awaiting_approval: None,
+            deferred: None,
+            progress: ProgressSink::disabled(),
         }
     }
 
@@ -33,12 +78,22 @@ impl ServerStateMachine {
         self.state
     }
 
+    /// Set where executed capabilities report progress.
+    pub fn set_progress(&mut self, progress: ProgressSink) {
+        self.progress = progress;
+    }
+
     /// Process an incoming request.
     pub fn process_request(
         &mut self,
         request: &Request,
         registry: &CapabilityRegistry,
     ) -> SinpResult<Response> {
+        // Transition: Negotiating -> Received
+        if self.state == ServerState::Negotiating {
+            self.transition(ServerEvent::ClientResponded)?;
+        }
+
         // Transition: Received -> Validating
         self.transition(ServerEvent::RequestReceived)?;
 
@@ -69,8 +124,34 @@ impl ServerStateMachine {
         // Transition: Validating -> Interpreting
         self.transition(ServerEvent::ValidationPassed)?;
 
+        // A yes/no answer to a confirmation skips interpretation
+        if let Some(pending) = self.pending_confirmation.take() {
+            if request.in_response_to == Some(pending.message_id) {
+                if let Some(confirmed) = parse_confirmation(&request.intent) {
+                    return self.answer_confirmation(request, pending, confirmed, registry);
+                }
+            }
+        }
+
+        // Answers about a plan under negotiation apply to that plan
+        if let Some(pending) = self.pending_plan.take() {
+            if request.in_response_to == Some(pending.message_id) {
+                if let Some(response) = self.answer_plan(request, pending, registry)? {
+                    return Ok(response);
+                }
+            }
+        }
+
+        // A compound intent is negotiated as a whole
+        if let Some(plan) = plan::compose(registry, request, &self.config.thresholds) {
+            if request.background || request.schedule.is_some() {
+                return self.refuse_deferred_plan(request, plan, registry);
+            }
+            return self.negotiate_plan(request, request.clone(), plan, registry);
+        }
+
         // Interpret the request
-        let interpretation_result = registry.interpret(&request.intent, &request.context);
+        let interpretation_result = registry.interpret(request);
 
         // Transition: Interpreting -> Deciding
         self.transition(ServerEvent::InterpretationComplete {
@@ -78,37 +159,42 @@ impl ServerStateMachine {
         })?;
 
         // Compute server confidence
-        let (phi_s, policy_passed) = if let Some(ref cap) = interpretation_result.capability {
-            let reliability = registry.get_reliability(&cap.id);
-            let availability = 1.0; // TODO: Resource availability check
-            let policy = registry.check_policy(&request);
-            let conf = compute_server_confidence(
-                interpretation_result.raw_confidence,
-                reliability,
-                availability,
-                policy,
-            );
-            (conf, policy)
-        } else {
-            (0.0, true)
+        let availability = 1.0; // TODO: Resource availability check
+        let (phi_s, reliability, policy_passed) =
+            if let Some(ref cap) = interpretation_result.capability {
+                let reliability = registry.get_reliability(&cap.id);
+                let policy = registry.check_policy(&cap.id, request);
+                let conf = compute_server_confidence(
+                    interpretation_result.raw_confidence,
+                    reliability,
+                    availability,
+                    policy,
+                );
+                (conf, reliability, policy)
+            } else {
+                (0.0, 0.0, true)
+            };
+
+        // Thresholds of the top match take precedence over the global ones
+        let thresholds = match interpretation_result.capability {
+            Some(ref cap) => registry.thresholds_for(&cap.id, &self.config.thresholds),
+            None => self.config.thresholds,
         };
 
         // Decide action
         let has_alternatives = !interpretation_result.alternatives.is_empty();
-        let action = decide_action(
+        let rule = decision_rule(
             phi_s,
             request.confidence,
-            &self.config.thresholds,
-            has_alternatives && phi_s < self.config.thresholds.tau_exec,
+            &thresholds,
+            has_alternatives && phi_s < thresholds.tau_exec,
             !policy_passed,
             false,
         );
+        let action = rule.action();
 
         // Build response
-        let responder = Responder {
-            id: "sinp-server".to_string(),
-            capabilities: registry.capability_ids(),
-        };
+        let responder = Self::responder(registry, &request.sender);
 
         let interpretation = Interpretation {
             text: interpretation_result.interpretation.clone(),
@@ -117,30 +203,96 @@ impl ServerStateMachine {
 
         let mut response = Response::to_request(request, responder, interpretation, action, phi_s);
 
+        if self.config.explain_decisions {
+            response.explanation = Some(Explanation {
+                capability_id: interpretation_result.capability.as_ref().map(|c| c.id.clone()),
+                rho: interpretation_result.raw_confidence,
+                reliability,
+                availability,
+                policy_passed,
+                client_confidence: request.confidence,
+                thresholds,
+                rule,
+            });
+        }
+
+        // Add alternatives for PROPOSE
+        if action == Action::Propose {
+            response.alternatives = Some(
+                interpretation_result
+                    .alternatives
+                    .into_iter()
+                    .map(|alt| sinp_core::Alternative {
+                        interpretation: alt.interpretation,
+                        confidence: alt.confidence,
+                        estimated_cost: Some(alt.capability.cost_units),
+                        capability_id: alt.capability.id,
+                    })
+                    .collect(),
+            );
+        }
+
+        // Dry run: report the decision without side effects
+        if request.dry_run {
+            self.transition(ServerEvent::DryRunCompleted)?;
+            let capability = interpretation_result.capability.as_ref();
+            let effect = match capability {
+                Some(cap) if action == Action::Execute => registry.preview(&cap.id, request)?,
+                _ => None,
+            };
+            response.action_metadata = Some(ActionMetadata {
+                preview: Some(Preview {
+                    action,
+                    capability_id: capability.map(|c| c.id.clone()),
+                    parameters: interpretation_result.parameters,
+                    estimated_cost: capability.map(|c| c.cost_units),
+                    requires_approval: capability.is_some_and(|c| self.requires_approval(c)),
+                    effect,
+                }),
+                deprecation: capability.and_then(|c| registry.deprecation(&c.id)),
+                ..Default::default()
+            });
+            return Ok(response);
+        }
+
         // Add action metadata
         response.action_metadata = Some(match action {
             Action::Execute => {
-                // Execute the capability
-                self.transition(ServerEvent::DecisionExecute)?;
-                let result = if let Some(ref cap) = interpretation_result.capability {
-                    registry.execute(&cap.id, request)?
-                } else {
-                    serde_json::Value::Null
-                };
-                // State is already Done after DecisionExecute
-                ActionMetadata {
-                    result: Some(result),
-                    ..Default::default()
-                }
+                self.execute(interpretation_result.capability.as_ref(), request, registry)?
             }
             Action::Clarify => {
                 self.transition(ServerEvent::DecisionClarify)?;
-                ActionMetadata {
-                    questions: Some(vec![
-                        "Could you provide more details?".to_string(),
-                        "What specific action would you like?".to_string(),
-                    ]),
-                    ..Default::default()
+                match (rule, &interpretation_result.capability) {
+                    (DecisionRule::Confirm, Some(cap)) => {
+                        let confirmation = Confirmation {
+                            capability_id: cap.id.clone(),
+                            interpretation: interpretation_result.interpretation.clone(),
+                            estimated_cost: cap.cost_units,
+                        };
+                        self.pending_confirmation = Some(PendingConfirmation {
+                            message_id: response.message_id,
+                            request: request.clone(),
+                            capability: cap.clone(),
+                            interpretation: interpretation_result.interpretation.clone(),
+                            confidence: phi_s,
+                            explanation: response.explanation.clone(),
+                        });
+                        ActionMetadata {
+                            questions: Some(vec![format!(
+                                "{} (estimated cost: {} units). Proceed? (yes/no)",
+                                confirmation.interpretation, confirmation.estimated_cost
+                            )]),
+                            confirmation: Some(confirmation),
+                            ..Default::default()
+                        }
+                    }
+                    _ => ActionMetadata {
+                        questions: Some(vec![
+                            "Could you provide more details?".to_string(),
+                            "What specific action would you like?".to_string(),
+                        ]),
+                        ..Default::default()
+                    },
                 }
             }
             Action::Propose => {
@@ -149,41 +301,567 @@ impl ServerStateMachine {
             }
             Action::Refuse => {
                 self.transition(ServerEvent::DecisionRefuse)?;
-                let code = if !policy_passed {
-                    RefusalCode::PolicyViolation
-                } else if interpretation_result.capability.is_none() {
-                    RefusalCode::CapabilityMissing
+                let (code, reason) = if !policy_passed {
+                    (
+                        RefusalCode::PolicyViolation,
+                        "Request refused: policy_violation".to_string(),
+                    )
                 } else {
-                    RefusalCode::MalformedContext
+                    (
+                        RefusalCode::CapabilityMissing,
+                        format!(
+                            "Request refused: capability_missing (confidence {:.2} below {:.2})",
+                            phi_s, thresholds.tau_clarify
+                        ),
+                    )
                 };
                 ActionMetadata {
                     reason_code: Some(code),
-                    reason: Some(format!("Request refused: {}", code)),
+                    reason: Some(reason),
                     ..Default::default()
                 }
             }
         });
 
-        // Add alternatives for PROPOSE
+        // Warn clients still using a deprecated version
+        if let (Some(cap), Some(metadata)) = (
+            interpretation_result.capability.as_ref(),
+            response.action_metadata.as_mut(),
+        ) {
+            metadata.deprecation = registry.deprecation(&cap.id);
+        }
+
+        self.last_message_id = Some(response.message_id);
+        Ok(response)
+    }
+
+    /// Answer to a confirm-style CLARIFY.
+    ///
+    /// "yes" executes the bound capability with the original request, "no"
+    /// asks the client to restate the intent.
+    fn answer_confirmation(
+        &mut self,
+        request: &Request,
+        pending: PendingConfirmation,
+        confirmed: bool,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<Response> {
+        // Transition: Interpreting -> Deciding
+        self.transition(ServerEvent::InterpretationComplete {
+            confidence: pending.confidence,
+        })?;
+
+        let interpretation = Interpretation {
+            text: pending.interpretation,
+            confidence: pending.confidence,
+        };
+        let action = if confirmed {
+            Action::Execute
+        } else {
+            Action::Clarify
+        };
+
+        let mut response = Response::to_request(
+            request,
+            Self::responder(registry, &request.sender),
+            interpretation,
+            action,
+            pending.confidence,
+        );
+        response.explanation = pending.explanation.map(|explanation| Explanation {
+            client_confidence: request.confidence,
+            rule: if confirmed {
+                DecisionRule::Confirmed
+            } else {
+                DecisionRule::Clarify
+            },
+            ..explanation
+        });
+
+        response.action_metadata = Some(if confirmed {
+            self.execute(Some(&pending.capability), &pending.request, registry)?
+        } else {
+            self.transition(ServerEvent::DecisionClarify)?;
+            ActionMetadata {
+                questions: Some(vec!["What would you like to do instead?".to_string()]),
+                ..Default::default()
+            }
+        });
+
+        self.last_message_id = Some(response.message_id);
+        Ok(response)
+    }
+
+    /// Answer to a plan under negotiation.
+    ///
+    /// Fills in missing inputs, or executes or drops a proposed plan on
+    /// yes/no. Returns `None` if the request is about something else.
+    fn answer_plan(
+        &mut self,
+        request: &Request,
+        mut pending: PendingPlan,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<Option<Response>> {
+        if pending.plan.steps.iter().any(|s| !s.missing.is_empty()) {
+            if !plan::answer(&mut pending.plan, request) {
+                return Ok(None);
+            }
+            return self
+                .negotiate_plan(request, pending.request, pending.plan, registry)
+                .map(Some);
+        }
+
+        let Some(confirmed) = parse_confirmation(&request.intent) else {
+            return Ok(None);
+        };
+
+        // Transition: Interpreting -> Deciding
+        self.transition(ServerEvent::InterpretationComplete {
+            confidence: pending.plan.confidence,
+        })?;
+
+        let action = if confirmed {
+            Action::Execute
+        } else {
+            Action::Clarify
+        };
+        let mut response = Response::to_request(
+            request,
+            Self::responder(registry, &request.sender),
+            Self::plan_interpretation(&pending.plan),
+            action,
+            pending.plan.confidence,
+        );
+        let rule = if confirmed {
+            DecisionRule::Confirmed
+        } else {
+            DecisionRule::Clarify
+        };
+        response.explanation =
+            self.plan_explanation(request, &pending.request, &pending.plan, registry, rule);
+
+        response.action_metadata = Some(if confirmed {
+            self.execute_plan(&mut response, pending.plan, &pending.request, registry)?
+        } else {
+            self.transition(ServerEvent::DecisionClarify)?;
+            ActionMetadata {
+                questions: Some(vec!["What would you like to do instead?".to_string()]),
+                ..Default::default()
+            }
+        });
+
+        self.last_message_id = Some(response.message_id);
+        Ok(Some(response))
+    }
+
+    /// PROPOSE a plan, or CLARIFY the inputs it is missing.
+    ///
+    /// `original` is the request the plan was composed for; its steps run
+    /// with it once the client accepts.
+    fn negotiate_plan(
+        &mut self,
+        request: &Request,
+        original: Request,
+        plan: Plan,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<Response> {
+        // Transition: Interpreting -> Deciding
+        self.transition(ServerEvent::InterpretationComplete {
+            confidence: plan.confidence,
+        })?;
+
+        let questions = plan::questions(&plan);
+        let rule = if questions.is_empty() {
+            DecisionRule::Plan
+        } else {
+            DecisionRule::Clarify
+        };
+        let action = rule.action();
+        let mut response = Response::to_request(
+            request,
+            Self::responder(registry, &request.sender),
+            Self::plan_interpretation(&plan),
+            action,
+            plan.confidence,
+        );
+        response.explanation = self.plan_explanation(request, &original, &plan, registry, rule);
+
+        if request.dry_run {
+            self.transition(ServerEvent::DryRunCompleted)?;
+            response.action_metadata = Some(ActionMetadata {
+                preview: Some(Preview {
+                    action,
+                    capability_id: None,
+                    parameters: Default::default(),
+                    estimated_cost: Some(plan.estimated_cost),
+                    requires_approval: self.plan_requires_approval(&plan, registry).is_some(),
+                    effect: None,
+                }),
+                plan: Some(plan),
+                ..Default::default()
+            });
+            return Ok(response);
+        }
+
         if action == Action::Propose {
-            response.alternatives = Some(
-                interpretation_result
-                    .alternatives
-                    .into_iter()
-                    .map(|alt| sinp_core::Alternative {
-                        interpretation: alt.interpretation,
-                        confidence: alt.confidence,
-                        estimated_cost: Some(alt.capability.cost_units),
-                        capability_id: alt.capability.id,
-                    })
-                    .collect(),
-            );
+            self.transition(ServerEvent::DecisionPropose)?;
+        } else {
+            self.transition(ServerEvent::DecisionClarify)?;
+        }
+        response.action_metadata = Some(ActionMetadata {
+            questions: Some(if questions.is_empty() {
+                vec![format!(
+                    "Run these {} steps (estimated cost: {} units)? (yes/no)",
+                    plan.steps.len(),
+                    plan.estimated_cost
+                )]
+            } else {
+                questions
+            }),
+            plan: Some(plan.clone()),
+            ..Default::default()
+        });
+        self.pending_plan = Some(PendingPlan {
+            message_id: response.message_id,
+            request: original,
+            plan,
+        });
+
+        self.last_message_id = Some(response.message_id);
+        Ok(response)
+    }
+
+    /// REFUSE a compound intent asked to run in the background or on a
+    /// schedule.
+    ///
+    /// Only single capabilities are deferred; plans run on the connection.
+    fn refuse_deferred_plan(
+        &mut self,
+        request: &Request,
+        plan: Plan,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<Response> {
+        // Transition: Interpreting -> Deciding
+        self.transition(ServerEvent::InterpretationComplete {
+            confidence: plan.confidence,
+        })?;
+        if request.dry_run {
+            self.transition(ServerEvent::DryRunCompleted)?;
+        } else {
+            self.transition(ServerEvent::DecisionRefuse)?;
         }
 
+        let mut response = Response::to_request(
+            request,
+            Self::responder(registry, &request.sender),
+            Self::plan_interpretation(&plan),
+            Action::Refuse,
+            plan.confidence,
+        );
+        response.explanation = self.plan_explanation(
+            request,
+            request,
+            &plan,
+            registry,
+            DecisionRule::PolicyViolation,
+        );
+        let deferral = if request.schedule.is_some() {
+            "on a schedule"
+        } else {
+            "in the background"
+        };
+        response.action_metadata = Some(ActionMetadata {
+            reason_code: Some(RefusalCode::PolicyViolation),
+            reason: Some(format!(
+                "Request refused: policy_violation (compound intents cannot run {})",
+                deferral
+            )),
+            plan: Some(plan),
+            ..Default::default()
+        });
         self.last_message_id = Some(response.message_id);
         Ok(response)
     }
 
+    /// Execute an accepted plan, or park it if any step requires operator
+    /// approval.
+    fn execute_plan(
+        &mut self,
+        response: &mut Response,
+        plan: Plan,
+        request: &Request,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<ActionMetadata> {
+        if let Some(capability) = self.plan_requires_approval(&plan, registry) {
+            self.transition(ServerEvent::ApprovalRequested)?;
+            let metadata = ActionMetadata {
+                plan: Some(plan.clone()),
+                ..Default::default()
+            };
+            self.awaiting_approval = Some(ParkedExecution {
+                request: request.clone(),
+                capability,
+                plan: Some(plan),
+            });
+            return Ok(metadata);
+        }
+
+        self.transition(ServerEvent::DecisionExecute)?;
+        let steps = plan::execute(registry, &plan, request, &self.progress);
+        Ok(Self::plan_outcome(response, plan, steps))
+    }
+
+    /// First step capability of a plan that needs operator approval.
+    fn plan_requires_approval(
+        &self,
+        plan: &Plan,
+        registry: &CapabilityRegistry,
+    ) -> Option<Capability> {
+        plan.steps
+            .iter()
+            .filter_map(|step| registry.capability(&step.capability_id))
+            .find(|cap| self.requires_approval(cap))
+            .cloned()
+    }
+
+    /// Metadata for an executed plan: the last step's result and the
+    /// per-step trail. A failed step turns the response into a REFUSE whose
+    /// reason summarizes the compensation.
+    fn plan_outcome(response: &mut Response, plan: Plan, steps: Vec<StepResult>) -> ActionMetadata {
+        let mut metadata = ActionMetadata {
+            plan: Some(plan),
+            ..Default::default()
+        };
+        if let Some(failed) = steps.iter().find(|s| s.status == StepStatus::Failed) {
+            response.action = Action::Refuse;
+            metadata.reason_code = Some(RefusalCode::ExecutionFailed);
+            let mut reason = format!(
+                "Request refused: execution_failed (step {} {}: {})",
+                failed.step + 1,
+                failed.capability_id,
+                failed.error.as_deref().unwrap_or_default()
+            );
+            let compensated = steps
+                .iter()
+                .filter(|s| s.status == StepStatus::Compensated)
+                .count();
+            if compensated > 0 {
+                reason.push_str(&format!("; compensated {} earlier step(s)", compensated));
+            }
+            let unrecovered: Vec<String> = steps
+                .iter()
+                .filter(|s| s.status == StepStatus::CompensationFailed)
+                .map(|s| (s.step + 1).to_string())
+                .collect();
+            if !unrecovered.is_empty() {
+                reason.push_str(&format!(
+                    "; compensation failed for step(s) {}",
+                    unrecovered.join(", ")
+                ));
+            }
+            metadata.reason = Some(reason);
+        } else {
+            metadata.result = steps.last().and_then(|s| s.result.clone());
+        }
+        metadata.steps = Some(steps);
+        metadata
+    }
+
+    /// Explanation of a decision about a plan, if explanations are enabled.
+    ///
+    /// A plan is only as confident as its weakest step, so that step's
+    /// factors and thresholds are reported. `original` is the request the
+    /// plan was composed for.
+    fn plan_explanation(
+        &self,
+        request: &Request,
+        original: &Request,
+        plan: &Plan,
+        registry: &CapabilityRegistry,
+        rule: DecisionRule,
+    ) -> Option<Explanation> {
+        if !self.config.explain_decisions {
+            return None;
+        }
+        let step = plan
+            .steps
+            .iter()
+            .min_by(|a, b| a.confidence.total_cmp(&b.confidence))?;
+        let mut clause = original.clone();
+        clause.intent = step.intent.clone();
+        Some(Explanation {
+            capability_id: Some(step.capability_id.clone()),
+            rho: registry.interpret(&clause).raw_confidence,
+            reliability: registry.get_reliability(&step.capability_id),
+            availability: 1.0,
+            // Only permitted capabilities become steps
+            policy_passed: rule != DecisionRule::PolicyViolation,
+            client_confidence: request.confidence,
+            thresholds: registry.thresholds_for(&step.capability_id, &self.config.thresholds),
+            rule,
+        })
+    }
+
+    /// Interpretation of a compound intent as its plan.
+    fn plan_interpretation(plan: &Plan) -> Interpretation {
+        let ids: Vec<&str> = plan.steps.iter().map(|s| s.capability_id.as_str()).collect();
+        Interpretation {
+            text: format!("Plan: {}", ids.join(", then ")),
+            confidence: plan.confidence,
+        }
+    }
+
+    /// Execute a capability, or park it if it requires operator approval.
+    fn execute(
+        &mut self,
+        capability: Option<&Capability>,
+        request: &Request,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<ActionMetadata> {
+        if let Some(cap) = capability {
+            if self.requires_approval(cap) {
+                self.transition(ServerEvent::ApprovalRequested)?;
+                self.awaiting_approval = Some(ParkedExecution {
+                    request: request.clone(),
+                    capability: cap.clone(),
+                    plan: None,
+                });
+                return Ok(ActionMetadata::default());
+            }
+        }
+
+        self.transition(ServerEvent::DecisionExecute)?;
+        match capability {
+            Some(cap) => self.run_or_defer(cap, request, registry),
+            None => Ok(ActionMetadata {
+                result: Some(serde_json::Value::Null),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Run a capability, or leave it to a background job or the scheduler
+    /// if the request asked for one.
+    fn run_or_defer(
+        &mut self,
+        capability: &Capability,
+        request: &Request,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<ActionMetadata> {
+        if let Some(ref schedule) = request.schedule {
+            crate::scheduler::next_run(schedule, chrono::Utc::now())?;
+        }
+        if request.background || request.schedule.is_some() {
+            self.deferred = Some(DeferredExecution {
+                request: request.clone(),
+                capability: capability.clone(),
+            });
+            return Ok(ActionMetadata::default());
+        }
+        Ok(ActionMetadata {
+            result: Some(registry.execute_with_progress(&capability.id, request, &self.progress)?),
+            ..Default::default()
+        })
+    }
+
+    /// Execution the last response promised to run as a background job,
+    /// or to schedule if its request carries a schedule.
+    ///
+    /// The caller starts the job or schedules it, and fills it into the
+    /// response.
+    pub fn take_deferred(&mut self) -> Option<DeferredExecution> {
+        self.deferred.take()
+    }
+
+    /// Check whether executing a capability needs operator approval.
+    fn requires_approval(&self, capability: &Capability) -> bool {
+        self.config
+            .approval
+            .as_ref()
+            .is_some_and(|a| a.requires_approval(capability))
+    }
+
+    /// Execution awaiting operator approval, if the last request parked one.
+    pub fn pending_approval(&self) -> Option<PendingApproval> {
+        self.awaiting_approval.as_ref().map(|parked| {
+            // A plan is approved as a whole
+            let (capability_id, estimated_cost) = match parked.plan {
+                Some(ref plan) => (
+                    plan.steps
+                        .iter()
+                        .map(|s| s.capability_id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    plan.estimated_cost,
+                ),
+                None => (parked.capability.id.clone(), parked.capability.cost_units),
+            };
+            PendingApproval {
+                id: parked.request.message_id,
+                conversation_id: parked.request.conversation_id,
+                sender_id: parked.request.sender.id.clone(),
+                capability_id,
+                intent: parked.request.intent.clone(),
+                estimated_cost,
+                requested_at: chrono::Utc::now(),
+            }
+        })
+    }
+
+    /// Complete a parked execution with the operator's decision.
+    ///
+    /// Fills in the result of the provisional EXECUTE `response`, or turns it
+    /// into a REFUSE if approval was denied.
+    pub fn resolve_approval(
+        &mut self,
+        mut response: Response,
+        decision: ApprovalDecision,
+        registry: &CapabilityRegistry,
+    ) -> SinpResult<Response> {
+        let parked = self
+            .awaiting_approval
+            .take()
+            .ok_or_else(|| SinpError::Protocol("No execution awaiting approval".to_string()))?;
+
+        response.timestamp = chrono::Utc::now();
+        response.action_metadata = Some(match decision {
+            ApprovalDecision::Approved => {
+                self.transition(ServerEvent::ApprovalGranted)?;
+                match parked.plan {
+                    Some(plan) => {
+                        let steps = plan::execute(registry, &plan, &parked.request, &self.progress);
+                        Self::plan_outcome(&mut response, plan, steps)
+                    }
+                    None => self.run_or_defer(&parked.capability, &parked.request, registry)?,
+                }
+            }
+            ApprovalDecision::Denied(reason) => {
+                self.transition(ServerEvent::ApprovalDenied(reason.clone()))?;
+                response.action = Action::Refuse;
+                ActionMetadata {
+                    reason_code: Some(RefusalCode::ApprovalDenied),
+                    reason: Some(format!("Request refused: approval_denied ({})", reason)),
+                    ..Default::default()
+                }
+            }
+        });
+
+        Ok(response)
+    }
+
+    /// Responder identity advertised in responses.
+    fn responder(registry: &CapabilityRegistry, sender: &Sender) -> Responder {
+        Responder {
+            id: "sinp-server".to_string(),
+            capabilities: registry
+                .capabilities_for(sender)
+                .into_iter()
+                .map(|c| c.id.clone())
+                .collect(),
+            catalog_version: Some(registry.catalog_version(sender)),
+        }
+    }
+
     /// Transition to a new state based on event.
     fn transition(&mut self, event: ServerEvent) -> SinpResult<()> {
         let new_state = match (&self.state, &event) {
@@ -197,8 +875,15 @@ impl ServerStateMachine {
             (ServerState::Deciding, ServerEvent::DecisionClarify) => ServerState::Negotiating,
             (ServerState::Deciding, ServerEvent::DecisionPropose) => ServerState::Negotiating,
             (ServerState::Deciding, ServerEvent::DecisionRefuse) => ServerState::Done,
+            (ServerState::Deciding, ServerEvent::DryRunCompleted) => ServerState::Done,
+            (ServerState::Deciding, ServerEvent::ApprovalRequested) => {
+                ServerState::AwaitingApproval
+            }
+            (ServerState::AwaitingApproval, ServerEvent::ApprovalGranted) => ServerState::Done,
+            (ServerState::AwaitingApproval, ServerEvent::ApprovalDenied(_)) => ServerState::Done,
             (ServerState::Done, ServerEvent::ActionCompleted) => ServerState::Done,
             (ServerState::Negotiating, ServerEvent::ClientResponded) => ServerState::Received,
+            (state, ServerEvent::Cancelled) if !state.is_terminal() => ServerState::Cancelled,
             (_, ServerEvent::Error(msg)) => {
                 tracing::error!("State machine error: {}", msg);
                 ServerState::Failed
@@ -223,10 +908,509 @@ impl ServerStateMachine {
         }
     }
 
+    /// End a conversation at the client's request.
+    ///
+    /// Drops whatever awaits an answer from the client or a decision from
+    /// an operator. Fails if the conversation is not in progress.
+    pub fn cancel(&mut self, conversation_id: uuid::Uuid) -> SinpResult<()> {
+        if self.conversation_id != Some(conversation_id) || self.state.is_terminal() {
+            return Err(SinpError::Validation(format!(
+                "No conversation {} in progress",
+                conversation_id
+            )));
+        }
+        self.transition(ServerEvent::Cancelled)?;
+        self.pending_confirmation = None;
+        self.pending_plan = None;
+        self.awaiting_approval = None;
+        self.deferred = None;
+        Ok(())
+    }
+
     /// Reset state machine for new conversation.
     pub fn reset(&mut self) {
         self.state = ServerState::Received;
         self.conversation_id = None;
         self.last_message_id = None;
+        self.pending_confirmation = None;
+        self.pending_plan = None;
+        self.awaiting_approval = None;
+        self.deferred = None;
+    }
+}
+
+/// Parse a yes/no answer to a confirmation.
+fn parse_confirmation(intent: &str) -> Option<bool> {
+    let answer = intent
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_lowercase();
+    match answer.as_str() {
+        "yes" | "y" | "confirm" | "ok" | "proceed" => Some(true),
+        "no" | "n" | "cancel" | "deny" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, ContextType, Sender};
+    use sinp_core::{Capability, Context, DecisionRule, Thresholds};
+
+    fn sample_config() -> ServerConfig {
+        ServerConfig::default().with_thresholds(Thresholds::new(0.6, 0.3, 0.5))
+    }
+
+    fn sample_registry() -> CapabilityRegistry {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec![],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |req| Ok(serde_json::json!({ "echo": req.intent })),
+            0.95,
+        );
+        registry
+    }
+
+    fn sample_request(intent: &str, confidence: f64) -> Request {
+        Request::new(
+            Sender {
+                id: "test".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            confidence,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn explanation_only_when_enabled() {
+        let registry = sample_registry();
+        let request = sample_request("echo message", 0.9);
+
+        let mut sm = ServerStateMachine::new(sample_config());
+        let response = sm.process_request(&request, &registry).unwrap();
+        assert!(response.explanation.is_none());
+
+        let mut sm = ServerStateMachine::new(sample_config().with_explanations(true));
+        let response = sm.process_request(&request, &registry).unwrap();
+        let explanation = response.explanation.unwrap();
+        assert_eq!(explanation.capability_id.as_deref(), Some("echo:v1"));
+        assert_eq!(explanation.reliability, 0.95);
+        assert_eq!(explanation.client_confidence, 0.9);
+        assert_eq!(explanation.rule, DecisionRule::Execute);
+        assert_eq!(response.action, Action::Execute);
+
+        // Answers to a confirmation are explained too
+        let mut sm = ServerStateMachine::new(sample_config().with_explanations(true));
+        let confirm = confirm_request(&registry, &mut sm);
+        assert_eq!(confirm.explanation.as_ref().unwrap().rule, DecisionRule::Confirm);
+        let done = sm.process_request(&reply(&confirm, "yes"), &registry).unwrap();
+        assert_eq!(done.action, Action::Execute);
+        let explanation = done.explanation.unwrap();
+        assert_eq!(explanation.rule, DecisionRule::Confirmed);
+        assert_eq!(explanation.capability_id.as_deref(), Some("echo:v1"));
+        assert_eq!(
+            explanation.thresholds,
+            confirm.explanation.unwrap().thresholds
+        );
+
+        // And decisions about plans
+        let registry = crate::plan::tests::compose_registry();
+        let mut sm = ServerStateMachine::new(sample_config().with_explanations(true));
+        let request = sample_request("get weather city=Paris and email it to=bob", 0.9);
+        let propose = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(propose.action, Action::Propose);
+        assert_eq!(propose.explanation.as_ref().unwrap().rule, DecisionRule::Plan);
+        let done = sm.process_request(&reply(&propose, "yes"), &registry).unwrap();
+        assert_eq!(done.action, Action::Execute);
+        assert_eq!(done.explanation.unwrap().rule, DecisionRule::Confirmed);
+    }
+
+    #[test]
+    fn explanation_below_clarify() {
+        let registry = sample_registry();
+        let request = sample_request("play some music", 0.9);
+
+        let mut sm = ServerStateMachine::new(sample_config().with_explanations(true));
+        let response = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(response.action, Action::Refuse);
+        assert_eq!(
+            response.action_metadata.unwrap().reason_code,
+            Some(RefusalCode::CapabilityMissing)
+        );
+
+        let explanation = response.explanation.unwrap();
+        assert!(explanation.capability_id.is_none());
+        assert_eq!(explanation.rule, DecisionRule::BelowClarify);
+    }
+
+    fn confirm_request(registry: &CapabilityRegistry, sm: &mut ServerStateMachine) -> Response {
+        let request = sample_request("echo message", 0.2);
+        let response = sm.process_request(&request, registry).unwrap();
+        assert_eq!(response.action, Action::Clarify);
+        assert_eq!(sm.state(), ServerState::Negotiating);
+
+        let confirmation = response
+            .action_metadata
+            .as_ref()
+            .and_then(|m| m.confirmation.clone())
+            .unwrap();
+        assert_eq!(confirmation.capability_id, "echo:v1");
+        assert_eq!(confirmation.estimated_cost, 0.1);
+        response
+    }
+
+    fn reply(previous: &Response, intent: &str) -> Request {
+        Request::reply(
+            previous,
+            Sender {
+                id: "test".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            1.0,
+            Context {
+                context_type: ContextType::Transcript,
+                content: String::new(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn confirmation_yes_executes_bound_capability() {
+        let registry = sample_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+        let clarify = confirm_request(&registry, &mut sm);
+
+        let response = sm.process_request(&reply(&clarify, "Yes"), &registry).unwrap();
+        assert_eq!(response.action, Action::Execute);
+        assert_eq!(sm.state(), ServerState::Done);
+
+        // Executed with the original request, not the "yes" answer
+        let result = response.action_metadata.unwrap().result.unwrap();
+        assert_eq!(result["echo"], "echo message");
+    }
+
+    #[test]
+    fn confirmation_no_asks_again() {
+        let registry = sample_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+        let clarify = confirm_request(&registry, &mut sm);
+
+        let response = sm.process_request(&reply(&clarify, "no"), &registry).unwrap();
+        assert_eq!(response.action, Action::Clarify);
+        assert!(response.action_metadata.unwrap().confirmation.is_none());
+        assert_eq!(sm.state(), ServerState::Negotiating);
+    }
+
+    fn approval_config() -> ServerConfig {
+        sample_config().with_approval(crate::approval::ApprovalConfig {
+            capabilities: vec!["echo:v1".to_string()],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn approval_granted_executes() {
+        let registry = sample_registry();
+        let mut sm = ServerStateMachine::new(approval_config());
+
+        let request = sample_request("echo message", 0.9);
+        let response = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(sm.state(), ServerState::AwaitingApproval);
+        assert_eq!(response.action, Action::Execute);
+
+        let pending = sm.pending_approval().unwrap();
+        assert_eq!(pending.id, request.message_id);
+        assert_eq!(pending.capability_id, "echo:v1");
+
+        let response = sm
+            .resolve_approval(response, ApprovalDecision::Approved, &registry)
+            .unwrap();
+        assert_eq!(sm.state(), ServerState::Done);
+        assert!(sm.pending_approval().is_none());
+        let result = response.action_metadata.unwrap().result.unwrap();
+        assert_eq!(result["echo"], "echo message");
+    }
+
+    #[test]
+    fn approval_denied_refuses() {
+        let registry = sample_registry();
+        let mut sm = ServerStateMachine::new(approval_config());
+
+        let response = sm
+            .process_request(&sample_request("echo message", 0.9), &registry)
+            .unwrap();
+        let response = sm
+            .resolve_approval(
+                response,
+                ApprovalDecision::Denied("not today".to_string()),
+                &registry,
+            )
+            .unwrap();
+        assert_eq!(sm.state(), ServerState::Done);
+        assert_eq!(response.action, Action::Refuse);
+        let metadata = response.action_metadata.unwrap();
+        assert_eq!(metadata.reason_code, Some(RefusalCode::ApprovalDenied));
+        assert!(metadata.reason.unwrap().contains("not today"));
+    }
+
+    #[test]
+    fn dry_run_previews_without_executing() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec!["message".to_string()],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |_req| panic!("dry run must not execute"),
+            0.95,
+        );
+        registry
+            .register_preview("echo:v1", |req| Ok(serde_json::json!({ "would_echo": req.intent })))
+            .unwrap();
+
+        let mut request = sample_request("echo message=hi", 0.9);
+        request.dry_run = true;
+
+        let mut sm = ServerStateMachine::new(approval_config());
+        let response = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(sm.state(), ServerState::Done);
+        assert!(sm.pending_approval().is_none());
+        assert_eq!(response.action, Action::Execute);
+
+        let metadata = response.action_metadata.unwrap();
+        assert!(metadata.result.is_none());
+        let preview = metadata.preview.unwrap();
+        assert_eq!(preview.action, Action::Execute);
+        assert_eq!(preview.capability_id.as_deref(), Some("echo:v1"));
+        assert_eq!(preview.parameters["message"], "hi");
+        assert_eq!(preview.estimated_cost, Some(0.1));
+        assert!(preview.requires_approval);
+        assert_eq!(preview.effect.unwrap()["would_echo"], "echo message=hi");
+    }
+
+    #[test]
+    fn background_execution_is_deferred() {
+        let mut registry = CapabilityRegistry::new();
+        registry.register(
+            Capability {
+                id: "echo:v1".to_string(),
+                description: "Echo message".to_string(),
+                inputs: vec!["message".to_string()],
+                privacy_level: "public".to_string(),
+                cost_units: 0.1,
+            },
+            |_req| panic!("background executions run as jobs"),
+            0.95,
+        );
+
+        let mut request = sample_request("echo message=hi", 0.9);
+        request.background = true;
+        let mut sm = ServerStateMachine::new(sample_config());
+        let response = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(response.action, Action::Execute);
+        assert_eq!(sm.state(), ServerState::Done);
+        assert!(response.action_metadata.unwrap().result.is_none());
+
+        let deferred = sm.take_deferred().unwrap();
+        assert_eq!(deferred.capability.id, "echo:v1");
+        assert_eq!(deferred.request.message_id, request.message_id);
+        assert!(sm.take_deferred().is_none());
+    }
+
+    #[test]
+    fn confirmation_other_answer_reinterprets() {
+        let registry = sample_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+        let clarify = confirm_request(&registry, &mut sm);
+
+        let response = sm
+            .process_request(&reply(&clarify, "play some music"), &registry)
+            .unwrap();
+        assert_eq!(response.action, Action::Refuse);
+    }
+
+    #[test]
+    fn deprecation_in_response() {
+        let mut registry = sample_registry();
+        let sunset = chrono::Utc::now() + chrono::Duration::days(30);
+        registry.deprecate("echo:v1", sunset, Some("echo:v2")).unwrap();
+
+        let mut sm = ServerStateMachine::new(sample_config());
+        let response = sm
+            .process_request(&sample_request("echo message", 0.9), &registry)
+            .unwrap();
+        assert_eq!(response.action, Action::Execute);
+        let deprecation = response.action_metadata.unwrap().deprecation.unwrap();
+        assert_eq!(deprecation.capability_id, "echo:v1");
+        assert_eq!(deprecation.sunset, sunset);
+        assert_eq!(deprecation.replacement.as_deref(), Some("echo:v2"));
+    }
+
+    #[test]
+    fn plan_negotiated_and_executed() {
+        let registry = crate::plan::tests::compose_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+
+        let request = sample_request("get weather city=Paris and email it", 0.9);
+        let clarify = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(clarify.action, Action::Clarify);
+        let metadata = clarify.action_metadata.clone().unwrap();
+        assert_eq!(
+            metadata.questions.unwrap(),
+            vec!["Step 2 (email:v1): what should to be?"]
+        );
+        assert_eq!(metadata.plan.unwrap().steps.len(), 2);
+
+        let propose = sm
+            .process_request(&reply(&clarify, "to=bob"), &registry)
+            .unwrap();
+        assert_eq!(propose.action, Action::Propose);
+        assert_eq!(propose.interpretation.text, "Plan: weather:v1, then email:v1");
+
+        let done = sm.process_request(&reply(&propose, "yes"), &registry).unwrap();
+        assert_eq!(done.action, Action::Execute);
+        assert_eq!(sm.state(), ServerState::Done);
+        let metadata = done.action_metadata.unwrap();
+        let steps = metadata.steps.unwrap();
+        assert!(steps.iter().all(|s| s.status == StepStatus::Completed));
+        assert_eq!(steps[0].result.as_ref().unwrap()["city"], "Paris");
+        let result = metadata.result.unwrap();
+        assert_eq!(result["sent"]["to"], "bob");
+        assert_eq!(result["sent"]["body"]["forecast"], "sunny");
+    }
+
+    #[test]
+    fn deferred_plan_is_refused() {
+        let registry = crate::plan::tests::compose_registry();
+        let mut background = sample_request("get weather city=Paris and email it to=bob", 0.9);
+        background.background = true;
+        let mut scheduled = background.clone();
+        scheduled.background = false;
+        scheduled.schedule = Some(sinp_core::Schedule::At {
+            at: chrono::Utc::now() + chrono::Duration::hours(1),
+        });
+
+        for (request, deferral) in [(background, "background"), (scheduled, "schedule")] {
+            let mut sm = ServerStateMachine::new(sample_config());
+            let response = sm.process_request(&request, &registry).unwrap();
+            assert_eq!(response.action, Action::Refuse);
+            assert_eq!(sm.state(), ServerState::Done);
+            assert!(sm.take_deferred().is_none());
+            let metadata = response.action_metadata.unwrap();
+            assert_eq!(metadata.reason_code, Some(RefusalCode::PolicyViolation));
+            assert!(metadata.reason.unwrap().contains(deferral));
+            assert!(metadata.steps.is_none());
+        }
+    }
+
+    #[test]
+    fn plan_failure_refuses_with_trail() {
+        let registry = crate::plan::tests::compose_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+
+        let request = sample_request("get weather city=Oslo then email it to=nobody", 0.9);
+        let propose = sm.process_request(&request, &registry).unwrap();
+        assert_eq!(propose.action, Action::Propose);
+
+        let response = sm.process_request(&reply(&propose, "yes"), &registry).unwrap();
+        assert_eq!(response.action, Action::Refuse);
+        let metadata = response.action_metadata.unwrap();
+        assert_eq!(metadata.reason_code, Some(RefusalCode::ExecutionFailed));
+        assert!(metadata.reason.unwrap().contains("step 2 email:v1"));
+        let steps = metadata.steps.unwrap();
+        assert_eq!(steps[0].status, StepStatus::Completed);
+        assert_eq!(steps[1].status, StepStatus::Failed);
+    }
+
+    #[test]
+    fn plan_failure_reports_compensation() {
+        let mut registry = crate::plan::tests::compose_registry();
+        registry
+            .register_compensation("weather:v1", |_req, _result| {
+                Ok(serde_json::json!({ "forgotten": true }))
+            })
+            .unwrap();
+        let mut sm = ServerStateMachine::new(sample_config());
+
+        let request = sample_request("get weather city=Oslo then email it to=nobody", 0.9);
+        let propose = sm.process_request(&request, &registry).unwrap();
+        let response = sm.process_request(&reply(&propose, "yes"), &registry).unwrap();
+        let metadata = response.action_metadata.unwrap();
+        let reason = metadata.reason.unwrap();
+        assert!(reason.ends_with("; compensated 1 earlier step(s)"), "{}", reason);
+        let steps = metadata.steps.unwrap();
+        assert_eq!(steps[0].status, StepStatus::Compensated);
+        assert_eq!(steps[0].compensation.as_ref().unwrap()["forgotten"], true);
+    }
+
+    #[test]
+    fn plan_declined_asks_again() {
+        let registry = crate::plan::tests::compose_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+
+        let request = sample_request("get weather city=Oslo then email it to=bob", 0.9);
+        let propose = sm.process_request(&request, &registry).unwrap();
+        let response = sm.process_request(&reply(&propose, "no"), &registry).unwrap();
+        assert_eq!(response.action, Action::Clarify);
+        assert!(response.action_metadata.unwrap().steps.is_none());
+    }
+
+    #[test]
+    fn plan_approved_as_a_whole() {
+        let registry = crate::plan::tests::compose_registry();
+        let config = sample_config().with_approval(crate::approval::ApprovalConfig {
+            capabilities: vec!["email:v1".to_string()],
+            ..Default::default()
+        });
+        let mut sm = ServerStateMachine::new(config);
+
+        let request = sample_request("get weather city=Oslo then email it to=bob", 0.9);
+        let propose = sm.process_request(&request, &registry).unwrap();
+        let response = sm.process_request(&reply(&propose, "yes"), &registry).unwrap();
+        assert_eq!(sm.state(), ServerState::AwaitingApproval);
+        let pending = sm.pending_approval().unwrap();
+        assert_eq!(pending.capability_id, "weather:v1, email:v1");
+
+        let response = sm
+            .resolve_approval(response, ApprovalDecision::Approved, &registry)
+            .unwrap();
+        assert_eq!(response.action, Action::Execute);
+        assert_eq!(response.action_metadata.unwrap().steps.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cancel_negotiation() {
+        let registry = crate::plan::tests::compose_registry();
+        let mut sm = ServerStateMachine::new(sample_config());
+
+        let request = sample_request("get weather city=Oslo then email it to=bob", 0.9);
+        assert!(sm.cancel(request.conversation_id).is_err());
+        sm.process_request(&request, &registry).unwrap();
+        assert_eq!(sm.state(), ServerState::Negotiating);
+
+        assert!(sm.cancel(uuid::Uuid::new_v4()).is_err());
+        sm.cancel(request.conversation_id).unwrap();
+        assert_eq!(sm.state(), ServerState::Cancelled);
+        assert!(sm.pending_plan.is_none());
+        // Only once
+        assert!(sm.cancel(request.conversation_id).is_err());
     }
 }
@@ -0,0 +1,359 @@
+//! Capabilities backed by external executables.
+//!
+//! The executable receives the request as JSON on stdin (capability ID,
+//! intent, extracted parameters, context, sender and conversation) and
+//! writes its JSON result to stdout. A non-zero exit status fails the
+//! execution with the process's stderr as the reason.
+//!
+//! The process runs with a cleared environment, plus only the variables the
+//! configuration sets or explicitly inherits, and is killed at the timeout.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sinp_core::{Capability, Request, SinpError, SinpResult};
+
+use crate::capability::{handler_input, CapabilityHandler};
+
+/// How to run a subprocess-backed capability.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubprocessConfig {
+    /// Executable to run.
+    pub program: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Kill the process after this many milliseconds.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Variables set in the process environment.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Variables passed through from the server environment.
+    #[serde(default)]
+    pub inherit_env: Vec<String>,
+    /// Working directory; the server's if unset.
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    /// Largest accepted stdout, in bytes.
+    #[serde(default = "default_max_output")]
+    pub max_output: usize,
+}
+
+fn default_timeout_ms() -> u64 {
+    10_000
+}
+
+fn default_max_output() -> usize {
+    1024 * 1024
+}
+
+impl SubprocessConfig {
+    /// Run `program` with defaults: 10 s timeout, empty environment.
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            timeout_ms: default_timeout_ms(),
+            env: BTreeMap::new(),
+            inherit_env: Vec::new(),
+            working_dir: None,
+            max_output: default_max_output(),
+        }
+    }
+
+    /// Set command-line arguments.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    /// Set an environment variable.
+    pub fn with_env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(name.into(), value.into());
+        self
+    }
+
+    /// Pass a variable through from the server environment.
+    pub fn with_inherited_env(mut self, name: impl Into<String>) -> Self {
+        self.inherit_env.push(name.into());
+        self
+    }
+
+    /// Set the working directory.
+    pub fn with_working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the largest accepted stdout, in bytes.
+    pub fn with_max_output(mut self, bytes: usize) -> Self {
+        self.max_output = bytes;
+        self
+    }
+
+    /// Problems with this configuration, if any.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.program.as_os_str().is_empty() {
+            errors.push("program is empty".to_string());
+        }
+        if self.timeout_ms == 0 {
+            errors.push("timeout_ms must be positive".to_string());
+        }
+        if let Some(ref dir) = self.working_dir {
+            if !dir.is_dir() {
+                errors.push(format!("working_dir {} is not a directory", dir.display()));
+            }
+        }
+        errors
+    }
+
+    /// Run the process with `input` on stdin and parse its stdout.
+    pub fn run(&self, input: &serde_json::Value) -> SinpResult<serde_json::Value> {
+        let program = self.program.display();
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .env_clear()
+            .envs(
+                self.inherit_env
+                    .iter()
+                    .filter_map(|name| std::env::var_os(name).map(|value| (name, value))),
+            )
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(ref dir) = self.working_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| SinpError::Execution(format!("Failed to start {}: {}", program, e)))?;
+
+        // Feed stdin and drain the pipes on their own threads so a chatty
+        // process cannot block on a full pipe
+        let payload = serde_json::to_vec(input)?;
+        let mut stdin = child.stdin.take();
+        let writer = thread::spawn(move || {
+            if let Some(ref mut stdin) = stdin {
+                // The process may exit without reading its input
+                let _ = stdin.write_all(&payload);
+            }
+        });
+        let stdout = drain(child.stdout.take(), self.max_output);
+        let stderr = drain(child.stderr.take(), self.max_output);
+
+        let deadline = Instant::now() + Duration::from_millis(self.timeout_ms);
+        let status = loop {
+            let status = child.try_wait().map_err(|e| {
+                SinpError::Execution(format!("Failed to wait for {}: {}", program, e))
+            })?;
+            if let Some(status) = status {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(SinpError::Execution(format!(
+                    "{} timed out after {} ms",
+                    program, self.timeout_ms
+                )));
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+
+        let _ = writer.join();
+        let (stdout, truncated) = stdout.join().unwrap_or_default();
+        let (stderr, _) = stderr.join().unwrap_or_default();
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(SinpError::Execution(format!(
+                "{} {}: {}",
+                program,
+                describe(status),
+                stderr.trim()
+            )));
+        }
+        if truncated {
+            return Err(SinpError::Execution(format!(
+                "{} output exceeds {} bytes",
+                program, self.max_output
+            )));
+        }
+        serde_json::from_slice(&stdout)
+            .map_err(|e| SinpError::Execution(format!("{} returned invalid JSON: {}", program, e)))
+    }
+}
+
+/// Read a pipe to the end, keeping at most `limit` bytes.
+///
+/// Returns the bytes kept and whether any were dropped.
+fn drain<R: Read + Send + 'static>(
+    pipe: Option<R>,
+    limit: usize,
+) -> thread::JoinHandle<(Vec<u8>, bool)> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        let mut truncated = false;
+        let Some(mut pipe) = pipe else {
+            return (kept, truncated);
+        };
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = pipe.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let room = limit.saturating_sub(kept.len());
+            kept.extend_from_slice(&buf[..n.min(room)]);
+            truncated |= n > room;
+        }
+        (kept, truncated)
+    })
+}
+
+fn describe(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => format!("exited with status {}", code),
+        None => "was killed by a signal".to_string(),
+    }
+}
+
+/// Handler running a capability as a subprocess.
+pub fn handler(capability: &Capability, config: SubprocessConfig) -> CapabilityHandler {
+    let capability = capability.clone();
+    Arc::new(move |request: &Request| config.run(&handler_input(&capability, request)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sinp_core::message::{AuthMethod, Context, ContextType, Sender};
+
+    fn sh(script: &str) -> SubprocessConfig {
+        SubprocessConfig::new("/bin/sh").with_args(["-c", script])
+    }
+
+    fn request(intent: &str) -> Request {
+        Request::new(
+            Sender {
+                id: "client_1".to_string(),
+                auth_method: AuthMethod::None,
+            },
+            intent,
+            0.9,
+            Context {
+                context_type: ContextType::Transcript,
+                content: "earlier turns".to_string(),
+                semantic_hash: String::new(),
+            },
+        )
+    }
+
+    fn capability() -> Capability {
+        Capability {
+            id: "tool:v1".to_string(),
+            description: "Run a tool".to_string(),
+            inputs: vec!["name".to_string()],
+            privacy_level: "public".to_string(),
+            cost_units: 0.1,
+        }
+    }
+
+    #[test]
+    fn request_on_stdin() {
+        let handler = handler(&capability(), sh("cat"));
+        let result = handler(&request("greet name=Ada")).unwrap();
+        assert_eq!(result["capability_id"], "tool:v1");
+        assert_eq!(result["intent"], "greet name=Ada");
+        assert_eq!(result["parameters"]["name"], "Ada");
+        assert_eq!(result["context"]["content"], "earlier turns");
+        assert_eq!(result["sender"], "client_1");
+    }
+
+    #[test]
+    fn failures_are_errors() {
+        let input = serde_json::json!({});
+        let err = sh("echo boom >&2; exit 3").run(&input).unwrap_err();
+        assert!(matches!(err, SinpError::Execution(_)));
+        let err = err.to_string();
+        assert!(err.contains("status 3") && err.contains("boom"), "{}", err);
+
+        let err = sh("echo not json").run(&input).unwrap_err().to_string();
+        assert!(err.contains("invalid JSON"), "{}", err);
+
+        let err = sh("echo '\"0123456789\"'")
+            .with_max_output(4)
+            .run(&input)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("exceeds 4 bytes"), "{}", err);
+
+        let err = SubprocessConfig::new("/nonexistent/tool")
+            .run(&input)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Failed to start"), "{}", err);
+    }
+
+    #[test]
+    fn timeout_kills_process() {
+        let started = Instant::now();
+        let err = sh("sleep 5")
+            .with_timeout(Duration::from_millis(100))
+            .run(&serde_json::json!({}))
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+
+    #[test]
+    fn environment_and_working_dir() {
+        std::env::set_var("SINP_SUBPROCESS_TEST", "inherited");
+        let dir = std::env::temp_dir();
+        let config = sh(r#"printf '{"home":"%s","set":"%s","inherited":"%s","dir":"%s"}' "$HOME" "$SET" "$SINP_SUBPROCESS_TEST" "$(pwd)""#)
+            .with_env("SET", "value")
+            .with_working_dir(&dir);
+
+        // Nothing leaks from the server environment unless inherited
+        let result = config.run(&serde_json::json!({})).unwrap();
+        assert_eq!(result["home"], "");
+        assert_eq!(result["set"], "value");
+        assert_eq!(result["inherited"], "");
+        assert_eq!(
+            PathBuf::from(result["dir"].as_str().unwrap())
+                .canonicalize()
+                .unwrap(),
+            dir.canonicalize().unwrap()
+        );
+
+        let config = config.with_inherited_env("SINP_SUBPROCESS_TEST");
+        let result = config.run(&serde_json::json!({})).unwrap();
+        assert_eq!(result["inherited"], "inherited");
+
+        let errors = sh("true")
+            .with_timeout(Duration::ZERO)
+            .with_working_dir("/nonexistent")
+            .validate();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+    }
+}